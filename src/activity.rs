@@ -0,0 +1,38 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// When each watch path received its last file system event, by path.
+static EVENTS: Mutex<BTreeMap<PathBuf, SystemTime>> = Mutex::new(BTreeMap::new());
+/// When each bucket applied its last action, by bucket name.
+static ACTIONS: Mutex<BTreeMap<String, SystemTime>> = Mutex::new(BTreeMap::new());
+
+/// Remember that `watch_path` just received an event.
+pub fn record_event(watch_path: &Path) {
+    EVENTS
+        .lock()
+        .expect("activity lock poisoned")
+        .insert(watch_path.to_path_buf(), SystemTime::now());
+}
+
+/// Remember that `bucket` just applied its action.
+pub fn record_action(bucket: &str) {
+    ACTIONS
+        .lock()
+        .expect("activity lock poisoned")
+        .insert(bucket.to_string(), SystemTime::now());
+}
+
+/// When each watch path which received any event since janitors was started received its last
+/// one.
+pub fn last_events() -> BTreeMap<PathBuf, SystemTime> {
+    EVENTS.lock().expect("activity lock poisoned").clone()
+}
+
+/// When each bucket which applied any action since janitors was started applied its last one.
+pub fn last_actions() -> BTreeMap<String, SystemTime> {
+    ACTIONS.lock().expect("activity lock poisoned").clone()
+}
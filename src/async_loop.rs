@@ -0,0 +1,221 @@
+//! An asynchronous event loop, for running janitors inside of a tokio application.
+//!
+//! The loop behaves like the one of the `janitors` binary: events are debounced, deferred paths
+//! are picked up once they are due, batches are flushed and lost events make the watch path be
+//! rescanned. Missing watch paths are watched once they exist, and the statistics, rescans and
+//! maintenance runs happen in their intervals. Actions touch the file system synchronously, so
+//! they run on tokio's blocking threads, which keeps them from blocking the tasks of the
+//! application.
+//!
+//! Unlike the binary, the loop doesn't reload the configuration, keep paths in the
+//! `pending_file`, serve metrics or the event socket, or warn about idle watch paths.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use notify::Event;
+use resolve_path::PathResolveExt;
+use tokio::{sync::mpsc, task::JoinSet};
+
+use crate::{
+    batch,
+    config::{Config, FileWatcher},
+    debounce::{self, Debouncer},
+    errors::JResult,
+    schedule, stats, throttle,
+    watch_path::WatchPath,
+};
+
+/// Sends the events of a watcher along with its index.
+type EventSender = mpsc::UnboundedSender<(usize, notify::Result<Event>)>;
+
+/// Watch the enabled watch paths of `config` and put their new paths into buckets, forever.
+pub async fn run(config: Config) -> JResult {
+    run_until(config, std::future::pending()).await
+}
+
+/// Watch the enabled watch paths of `config` like `run`, until `shutdown` completes.
+///
+/// Actions which are running when it completes are waited for, and paths which are waiting in
+/// batches or for a burst are handled before returning.
+pub async fn run_until(config: Config, shutdown: impl Future<Output = ()>) -> JResult {
+    let config = Arc::new(config);
+    let (tx, mut rx) = mpsc::unbounded_channel::<(usize, notify::Result<Event>)>();
+    // The watchers stop sending events once they are dropped, and `None` while their watch path
+    // doesn't exist.
+    let mut watchers: Vec<(WatchPath, Option<FileWatcher>)> = Vec::new();
+    for (idx, watch_path) in config.enabled_watch_paths().enumerate() {
+        let path = watch_path.path.resolve();
+        if !path.exists() && config.create_watch_dirs {
+            log::info!("creating watch path '{}'", watch_path.path.display());
+            tokio::fs::create_dir_all(&path).await?;
+        } else if !path.exists() {
+            log::warn!(
+                "watch path '{}' does not exist, it's watched once it exists",
+                watch_path.path.display()
+            );
+            watchers.push((watch_path.clone(), None));
+            continue;
+        }
+        let watcher = watch(idx, watch_path, &tx)?;
+        watchers.push((watch_path.clone(), Some(watcher)));
+    }
+    log::info!("File watchers have been setup.");
+    throttle::set_queueing(true);
+
+    let mut tasks = JoinSet::new();
+    let sweep = Arc::clone(&config);
+    tasks.spawn_blocking(move || sweep.sweep_on_start(false));
+
+    let mut debouncer = Debouncer::new(config.debounce_window());
+    let mut last_summary = Instant::now();
+    let mut last_rescan = Instant::now();
+    let mut last_maintenance = Instant::now();
+    let mut last_reconnect = Instant::now();
+    tokio::pin!(shutdown);
+    loop {
+        let timeout = debouncer
+            .next_deadline()
+            .map_or(Duration::from_secs(1), |d| d.min(Duration::from_secs(1)));
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = tokio::time::sleep(timeout) => {}
+            received = rx.recv() => {
+                let Some(first) = received else {
+                    break;
+                };
+                // Take every event which is ready as well, so that the paths of a burst are
+                // handled together.
+                let mut burst = vec![first];
+                while let Ok(received) = rx.try_recv() {
+                    burst.push(received);
+                }
+                for received in burst {
+                    match received {
+                        (_, Err(e)) => log::error!("Notify event error: {e}"),
+                        (idx, Ok(ev)) if ev.need_rescan() => {
+                            let watch_path = watchers[idx].0.clone();
+                            log::warn!(
+                                "Events of '{}' were lost, rescanning it.",
+                                watch_path.path.display()
+                            );
+                            let config = Arc::clone(&config);
+                            tasks.spawn_blocking(move || config.scan(&watch_path));
+                        }
+                        (idx, Ok(ev)) => watchers[idx].0.handle_event(ev, &mut debouncer, &config),
+                    }
+                }
+            }
+        }
+
+        if let Some(interval) = config.stats_interval()
+            && last_summary.elapsed() >= interval
+        {
+            stats::log_summary(&config.stats());
+            last_summary = Instant::now();
+        }
+        if let Some(interval) = config.rescan_interval()
+            && last_rescan.elapsed() >= interval
+        {
+            log::debug!("Rescanning all watch paths.");
+            let rescan = Arc::clone(&config);
+            tasks.spawn_blocking(move || {
+                for watch_path in rescan.enabled_watch_paths() {
+                    if let Err(e) = rescan.scan(watch_path) {
+                        log::error!("rescanning '{}': {e}", watch_path.path.display());
+                    }
+                }
+                Ok(())
+            });
+            last_rescan = Instant::now();
+        }
+        if let Some(interval) = config.maintain_interval()
+            && last_maintenance.elapsed() >= interval
+        {
+            let maintain = Arc::clone(&config);
+            tasks.spawn_blocking(move || maintain.maintain().map(|_| ()));
+            last_maintenance = Instant::now();
+        }
+        if let Some(interval) = config.reconnect_interval()
+            && last_reconnect.elapsed() >= interval
+        {
+            for (idx, (watch_path, watcher)) in watchers.iter_mut().enumerate() {
+                let exists = watch_path.path.resolve().exists();
+                if watcher.is_some() && !exists {
+                    log::warn!(
+                        "watch path '{}' disappeared, it's watched again once it exists",
+                        watch_path.path.display()
+                    );
+                    *watcher = None;
+                } else if watcher.is_none() && exists {
+                    match watch(idx, watch_path, &tx) {
+                        Ok(reconnected) => {
+                            log::info!("watching '{}' again", watch_path.path.display());
+                            *watcher = Some(reconnected);
+                            // Paths which were created while nothing was watching are handled
+                            // right away.
+                            let (config, watch_path) = (Arc::clone(&config), watch_path.clone());
+                            tasks.spawn_blocking(move || config.scan(&watch_path));
+                        }
+                        Err(e) => {
+                            log::debug!("watching '{}' again: {e}", watch_path.path.display())
+                        }
+                    }
+                }
+            }
+            last_reconnect = Instant::now();
+        }
+
+        let ready = debouncer.ready().into_iter().chain(schedule::due());
+        for (watch_path, paths, is_file) in debounce::group(ready) {
+            let config = Arc::clone(&config);
+            tasks.spawn_blocking(move || watch_path.handle_paths(paths, is_file, &config));
+        }
+        if !batch::batched().is_empty() {
+            let flush = Arc::clone(&config);
+            tasks.spawn_blocking(move || flush.flush_batches(false));
+        }
+        while let Some(finished) = tasks.try_join_next() {
+            log_failure(finished);
+        }
+    }
+
+    log::info!("Shutting down.");
+    while let Some(finished) = tasks.join_next().await {
+        log_failure(finished);
+    }
+    let flush = Arc::clone(&config);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = flush.flush_bursts() {
+            log::error!("flushing bursts: {e}");
+        }
+        flush.flush_batches(true)
+    })
+    .await
+    .expect("flushing batches panicked")
+}
+
+/// Watch the path of `watch_path`, sending its events along with `idx` to `tx`.
+fn watch(idx: usize, watch_path: &WatchPath, tx: &EventSender) -> JResult<FileWatcher> {
+    let tx = tx.clone();
+    let mut watcher = FileWatcher::new(
+        move |res| {
+            let _ = tx.send((idx, res));
+        },
+        watch_path.poll_interval_ms,
+    )?;
+    watcher.watch(&watch_path.path.resolve(), watch_path.recursive_mode.into())?;
+    Ok(watcher)
+}
+
+/// Log the error of a finished task, if it failed.
+fn log_failure(finished: Result<JResult, tokio::task::JoinError>) {
+    match finished {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::error!("Error occured when handling event: {e}"),
+        Err(e) => log::error!("handling paths panicked: {e}"),
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::watch_path::WatchPath;
+
+/// A waiting path, with the watch path it was found in and whether it's a file.
+pub type Entry = (WatchPath, PathBuf, bool);
+
+/// Paths which wait for more paths to fit into the same bucket.
+struct Batch {
+    paths: Vec<Entry>,
+    started: Instant,
+}
+
+/// The batches of buckets with a `min_batch`, by bucket name.
+static BATCHES: Mutex<BTreeMap<String, Batch>> = Mutex::new(BTreeMap::new());
+
+/// Add `path` to the batch of `bucket`, and remove and return the whole batch once it holds at
+/// least `min` paths.
+pub fn add(
+    bucket: &str,
+    min: usize,
+    watch_path: &WatchPath,
+    path: PathBuf,
+    is_file: bool,
+) -> Option<Vec<Entry>> {
+    let mut batches = BATCHES.lock().expect("batch lock poisoned");
+    let batch = batches.entry(bucket.to_string()).or_insert_with(|| Batch {
+        paths: Vec::new(),
+        started: Instant::now(),
+    });
+    if !batch.paths.iter().any(|(_, p, _)| *p == path) {
+        batch.paths.push((watch_path.clone(), path, is_file));
+    }
+    if batch.paths.len() < min {
+        log::info!(
+            "bin '{bucket}' has {} of {min} paths, waiting for more",
+            batch.paths.len()
+        );
+        return None;
+    }
+    batches.remove(bucket).map(|batch| batch.paths)
+}
+
+/// Remove and return the batches, by bucket name, which were started at least as long ago as
+/// `flush_after` returns for their bucket. Batches of buckets for which it returns `None` stay.
+pub fn overdue(flush_after: impl Fn(&str) -> Option<Duration>) -> Vec<(String, Vec<Entry>)> {
+    let mut batches = BATCHES.lock().expect("batch lock poisoned");
+    let overdue: Vec<String> = batches
+        .iter()
+        .filter(|(bucket, batch)| flush_after(bucket).is_some_and(|d| batch.started.elapsed() >= d))
+        .map(|(bucket, _)| bucket.clone())
+        .collect();
+
+    overdue
+        .into_iter()
+        .filter_map(|bucket| {
+            let batch = batches.remove(&bucket)?;
+            Some((bucket, batch.paths))
+        })
+        .collect()
+}
+
+/// All paths which are waiting in a batch.
+pub fn batched() -> Vec<Entry> {
+    BATCHES
+        .lock()
+        .expect("batch lock poisoned")
+        .values()
+        .flat_map(|batch| batch.paths.iter().cloned())
+        .collect()
+}
@@ -1,18 +1,73 @@
 use std::{
-    fs::{remove_dir_all, remove_file},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    ffi::{CString, OsStr, OsString},
+    fs::{
+        File, FileTimes, create_dir_all, hard_link, metadata, read_link, remove_dir,
+        remove_dir_all, remove_file, rename, set_permissions, symlink_metadata,
+    },
+    io::{ErrorKind, Write},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
     path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use anyhow::bail;
+use anyhow::{Context, bail};
+use chrono::NaiveTime;
 use fs_extra::{
-    dir::{copy as copy_dir, move_dir},
-    file::{copy, move_file},
+    dir::{copy as copy_dir, copy_with_progress as dir_copy_with_progress},
+    file::{copy, copy_with_progress as file_copy_with_progress},
 };
-use regex::Regex;
+use regex::{Captures, Regex, RegexBuilder};
 use resolve_path::PathResolveExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::errors::{JError, JResult};
+use crate::{
+    activity,
+    config::default_true,
+    errors::{JError, JResult},
+    extract,
+    feed::{self, Outcome},
+    journal, manifest, notification, preview,
+    schedule::ActiveHours,
+    sftp::SftpDestination,
+    stats, throttle, units,
+};
+
+/// Built-in groups of extensions, which extension filters can name instead of listing every
+/// extension of the group.
+pub const EXTENSION_GROUPS: [(&str, &[&str]); 5] = [
+    (
+        "image",
+        &[
+            "jpg", "jpeg", "png", "gif", "webp", "heic", "heif", "bmp", "tiff", "svg", "avif",
+        ],
+    ),
+    (
+        "video",
+        &["mp4", "mkv", "webm", "mov", "avi", "m4v", "wmv", "flv"],
+    ),
+    (
+        "audio",
+        &["mp3", "flac", "ogg", "opus", "wav", "m4a", "aac", "wma"],
+    ),
+    (
+        "archive",
+        &["zip", "tar", "gz", "bz2", "xz", "zst", "7z", "rar", "tgz"],
+    ),
+    (
+        "document",
+        &[
+            "pdf", "doc", "docx", "odt", "xls", "xlsx", "ods", "ppt", "pptx", "odp", "rtf", "txt",
+            "md", "epub",
+        ],
+    ),
+];
 
 /// A `Bucket` is a destination for files from watched paths.
 ///
@@ -24,30 +79,353 @@ use crate::errors::{JError, JResult};
 /// The `extension_filters` checks only the final extension, so for example file
 /// `archive.tar.gz` would not be recognized by name filter `"tar"`, because only the final
 /// extension is checked.
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize)]
+// The derived implementation is wrapped by the one below, which resolves the actions.
+#[serde(remote = "Self")]
 pub struct Bucket {
     /// Unique identifier for the bucket.
+    ///
+    /// Only inline buckets of a watch path may leave it out.
+    #[serde(default)]
     pub name: String,
+    /// Whether paths are put into the bucket. Disabled buckets are skipped when looking for the
+    /// bucket a path fits into.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
     /// Where should the files belonging to this bucket be stored.
+    ///
+    /// Besides local paths, `sftp://[user@]host[:port]/path` URLs are supported, in which case
+    /// files are uploaded to the remote host. Buckets which only delete or rename paths don't
+    /// need one.
+    ///
+    /// Local destinations may contain the placeholders `{ext}`, `{stem}` and `{date:FORMAT}`,
+    /// which are filled in for every path, see `fill_placeholders`.
+    #[serde(default)]
     pub destination: PathBuf,
     /// File extensions of files which should belong to this bucket.
+    ///
+    /// Names of `EXTENSION_GROUPS`, like `image`, stand for all extensions of the group, and `*`
+    /// matches any extension.
+    #[serde(default)]
     pub extension_filters: Vec<String>,
+    /// Regular expressions which the whole final extension of files which belong to this bucket
+    /// matches, like `mp[0-9]` for `mp3` and `mp4`. They count as extension filters.
+    #[serde(default)]
+    pub extension_regex: Vec<String>,
     /// Move the file into the bucket if the its name matches at least one of the filters.
     ///
     /// The filters use regular expressions.
+    #[serde(default)]
     pub name_filters: Vec<String>,
-    /// If multiple buckets can move a file, pick the one with the highest priority.
-    pub priority: u32,
-    /// What action should be performed on the file.
+    /// MIME types like `application/pdf`, or whole categories like `image/*`, of the contents of
+    /// files which belong to this bucket. The type is told from the first bytes of a file, so
+    /// its extension doesn't matter. Directories never match.
+    #[serde(default)]
+    pub mime_filters: Vec<String>,
+    /// Whether a path has to match any kind of filter or all of them.
+    #[serde(default)]
+    pub match_mode: MatchMode,
+    /// A shell command which decides whether a file fits, after the other filters matched. The
+    /// file fits if the command exits with `0`. `{path}` is replaced with the path of the file,
+    /// which is appended to the command if it doesn't contain `{path}`.
+    ///
+    /// A process is started for every file the command is asked about, so this is slow.
+    #[serde(default)]
+    pub content_predicate: Option<String>,
+    /// How many seconds the `content_predicate` may run before it's killed, which fails the
+    /// path.
+    #[serde(default = "default_predicate_timeout_secs")]
+    pub content_predicate_timeout_secs: u64,
+    /// Names of filter sets of the configuration whose filters are added to the filters of the
+    /// bucket.
+    #[serde(default)]
+    pub use_filter_sets: Vec<String>,
+    /// Whether the name filters ignore the case of file names.
+    #[serde(default)]
+    pub case_insensitive_names: bool,
+    /// Whether the name filters have to match the whole file name, or the whole path with
+    /// `match_full_path`, instead of any part of it.
+    #[serde(default)]
+    pub anchor_names: bool,
+    /// Whether the name filters are matched against the whole path instead of just the file
+    /// name. The same compiled filters are used either way.
+    #[serde(default)]
+    pub match_full_path: bool,
+    /// Only paths owned by this user ID fit into the bucket. Only supported on Unix.
+    #[serde(default)]
+    pub owner_uid: Option<u32>,
+    /// Only paths owned by this group ID fit into the bucket. Only supported on Unix.
+    #[serde(default)]
+    pub owner_gid: Option<u32>,
+    /// Only paths which were not modified for at least this many days fit into the bucket.
+    ///
+    /// New paths usually don't fit into such a bucket, so these buckets are mostly applied by
+    /// maintenance runs, which look at every path inside of the watch paths.
+    #[serde(default)]
+    pub min_age_days: Option<u64>,
+    /// If multiple buckets can move a file, pick the one with the highest priority. Priorities
+    /// may be negative, to rank a bucket below the ones with the default of `0`.
+    pub priority: i32,
+    /// What action should be performed on the file. Left out, it's `move`, or the
+    /// `default_action` of the configuration once `Config::fill_bucket_defaults` ran.
+    #[serde(skip)]
     pub action: Action,
+    /// Actions which replace `action` for files with one of these final extensions.
+    #[serde(default)]
+    pub extension_actions: BTreeMap<String, Action>,
+    /// The new name of files for the `rename` action.
+    ///
+    /// `{stem}` is replaced with the file name without its extension, `{ext}` with the extension,
+    /// `{date}` with the modification date of the file and `{counter}` with the lowest number,
+    /// starting at 1, for which the new name is not taken yet. Capture groups of the name filter
+    /// which matches the file, like `$1` or `${name}`, are filled in as well.
+    #[serde(default)]
+    pub rename_template: Option<String>,
+    /// Regular expressions and their replacements, which are applied to the names of paths put
+    /// into the destination one after another, before `normalize_name`. Replacements can refer
+    /// to the capture groups of their expression, like `$1`.
+    #[serde(default)]
+    pub filename_replace: Vec<(String, String)>,
+    /// Whether the names of paths are cleaned up when they are put into the destination, so
+    /// that `My File (1).PDF` becomes `my-file-1.pdf`.
+    ///
+    /// Every run of characters which are neither alphanumeric nor `_` is replaced with the
+    /// `normalize_separator`, except for the dots between the parts of the name.
+    #[serde(default)]
+    pub normalize_name: bool,
+    /// Whether normalized names are lowercased.
+    #[serde(default = "default_true")]
+    pub normalize_lowercase: bool,
+    /// What replaces spaces and special characters in normalized names.
+    #[serde(default = "default_separator")]
+    pub normalize_separator: String,
+    /// Whether the bucket takes files, directories or both.
+    #[serde(default)]
+    pub target: Target,
     /// What action should be taken, if a file/directory of the same name exists in the bucket
-    /// already.
+    /// already. Left out, it's `skip`, or the `default_override_action` of the configuration
+    /// once `Config::fill_bucket_defaults` ran.
+    #[serde(skip)]
     pub override_action: OverrideAction,
+    /// What happens to a file which collides with an existing one while merging directories,
+    /// see `OverrideAction::Merge`. Another `merge` is the same as `skip`.
+    #[serde(default)]
+    pub merge_override_action: OverrideAction,
+    /// Whether the destination directory is created when it does not exist yet.
+    #[serde(default = "default_true")]
+    pub create_destination: bool,
+    /// How many bytes have to stay free on the destination's file system after copying a path
+    /// there. Paths which would leave less space free are not copied or moved.
+    ///
+    /// Either a number of bytes or a size like `"10GiB"`.
+    #[serde(default, deserialize_with = "units::deserialize_size")]
+    pub min_free_bytes: u64,
+    /// How many actions the bucket applies per minute at most. Paths which arrive while the limit
+    /// is reached wait until the bucket may apply the next action. Unlimited if not set.
+    #[serde(default)]
+    pub max_actions_per_minute: Option<u32>,
+    /// A shell command which is run before the action. If it fails, the action is not applied.
+    ///
+    /// `{path}` is replaced with the path the action is applied to and `{destination}` with the
+    /// path it's placed at. Both are quoted already, so they must not be put in quotes.
+    #[serde(default)]
+    pub pre_hook: Option<String>,
+    /// A shell command which is run after the action was applied, with the same replacements as
+    /// in `pre_hook`. If it fails, only a warning is logged.
+    #[serde(default)]
+    pub post_hook: Option<String>,
+    /// Private key used to authenticate with `sftp://` destinations. Without it, the SSH agent
+    /// is used.
+    #[serde(default)]
+    pub ssh_key: Option<PathBuf>,
+    /// The daily time window in which the bucket is active, e.g. `"22:00-06:00"`. Outside of it
+    /// the bucket is dormant and not considered for any path.
+    #[serde(default)]
+    pub active_hours: Option<String>,
+    /// Whether a path which fits best into the bucket while it's dormant is handled again once
+    /// the bucket becomes active, instead of picking another bucket.
+    #[serde(default)]
+    pub defer_outside_active_hours: bool,
+    /// How many paths have to fit into the bucket before its action is applied to all of them at
+    /// once. Paths are handled one by one if it's at most 1.
+    #[serde(default)]
+    pub min_batch: usize,
+    /// After how many minutes the paths waiting for a `min_batch` are handled anyway. They wait
+    /// indefinitely if not set.
+    #[serde(default)]
+    pub batch_flush_minutes: Option<u64>,
+    /// How many paths have to fit into the bucket within `burst_window_secs` before its action
+    /// is applied to all of them. Paths which don't become part of such a burst are left where
+    /// they are. There's no such condition if it's at most 1.
+    #[serde(default)]
+    pub burst_threshold: usize,
+    /// How many seconds the paths of a burst may arrive apart from the first to the last.
+    #[serde(default = "default_burst_window_secs")]
+    pub burst_window_secs: u64,
+    /// Whether the files inside of a directory are placed into the bucket one by one, instead of
+    /// the directory as a whole.
+    #[serde(default)]
+    pub flatten: bool,
+    /// Whether directories which are empty after moving their files out of them are removed.
+    #[serde(default)]
+    pub remove_flattened_dirs: bool,
+    /// How many of the directories a path is in, inside of its watch path, are recreated in the
+    /// destination, counted from the innermost one. With `1`, `photos/2024/trip/a.jpg` is put
+    /// into `trip/a.jpg` in the destination. `0` puts every path right into the destination.
+    #[serde(default)]
+    pub keep_components: usize,
+    /// Whether copies keep the modification and access times and the permissions of their
+    /// source, for directories including everything inside of them.
+    ///
+    /// The owner is never preserved and, on other platforms than Unix, the only permission which
+    /// is preserved is whether the path is read-only.
+    #[serde(default)]
+    pub preserve_metadata: bool,
+    /// Whether the action is applied to symbolic links themselves instead of what they point to,
+    /// so deleting a link to a directory only removes the link and moving or copying it places a
+    /// link to the same target in the destination.
+    #[serde(default)]
+    pub treat_symlinks_as_links: bool,
+    /// Permissions which moved and copied paths get, like `"644"`. Strings are read as octal
+    /// numbers, integers are taken as they are, so TOML's `0o644` works as well.
+    ///
+    /// Inside of directories only files get the permissions, because a mode like `644` would make
+    /// directories unusable. Only supported on Unix.
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    pub set_mode: Option<u32>,
+    /// Name or ID of the group which moved and copied paths, and everything inside of them, are
+    /// given. Only supported on Unix.
+    #[serde(default)]
+    pub set_group: Option<String>,
+    /// Whether deletions are confirmed on the terminal when running with `--interactive`.
+    #[serde(default = "default_true")]
+    pub confirm_deletes: bool,
+    /// Whether directories are only deleted if they are empty. Directories which aren't are
+    /// left alone with a warning.
+    #[serde(default)]
+    pub delete_empty_only: bool,
+    /// Whether the `extract` action deletes archives after unpacking them.
+    #[serde(default)]
+    pub delete_extracted: bool,
+    /// Whether the bucket only logs what it would do with paths, without doing it. A running
+    /// preview plans its actions, as for any other bucket.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Whether a desktop notification is shown after a path was put into the bucket. Falls back
+    /// to the global `notify_desktop` setting.
+    #[serde(default)]
+    pub notify_desktop: Option<bool>,
     #[serde(skip)]
     pub _regexes: Vec<Regex>,
+    #[serde(skip)]
+    pub _ext_regexes: Vec<Regex>,
+    /// The compiled expressions of `filename_replace`, with their replacements.
+    #[serde(skip)]
+    pub _filename_replace: Vec<(Regex, String)>,
+    #[serde(skip)]
+    pub _active_hours: Option<ActiveHours>,
+    /// The journal of the configuration, which moves and copies are recorded in.
+    #[serde(skip)]
+    pub _journal: Option<PathBuf>,
+    /// The ID of `set_group`.
+    #[serde(skip)]
+    pub _group: Option<u32>,
+    /// Where the bucket is defined in the merged configuration, starting at `0`.
+    #[serde(skip)]
+    pub _index: usize,
+    /// The watch path whose path the bucket is applied to, which `keep_components` counts the
+    /// directories in. It's set by the watch path.
+    #[serde(skip)]
+    pub _watch_root: Option<PathBuf>,
+    /// The `action` as written in the configuration, `None` if it's left out. Only
+    /// `Config::fill_bucket_defaults` looks at it, everything else uses `action`.
+    #[serde(default, rename = "action")]
+    pub(crate) _action: Option<Action>,
+    /// The `override_action` as written in the configuration, `None` if it's left out, see
+    /// `_action`.
+    #[serde(default, rename = "override_action")]
+    pub(crate) _override_action: Option<OverrideAction>,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+impl Default for Bucket {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            destination: PathBuf::new(),
+            extension_filters: Vec::new(),
+            extension_regex: Vec::new(),
+            mime_filters: Vec::new(),
+            name_filters: Vec::new(),
+            match_mode: MatchMode::default(),
+            content_predicate: None,
+            content_predicate_timeout_secs: default_predicate_timeout_secs(),
+            use_filter_sets: Vec::new(),
+            case_insensitive_names: false,
+            anchor_names: false,
+            match_full_path: false,
+            owner_uid: None,
+            owner_gid: None,
+            min_age_days: None,
+            priority: 0,
+            action: Action::default(),
+            extension_actions: BTreeMap::new(),
+            rename_template: None,
+            filename_replace: Vec::new(),
+            normalize_name: false,
+            normalize_lowercase: true,
+            normalize_separator: default_separator(),
+            target: Target::default(),
+            override_action: OverrideAction::default(),
+            merge_override_action: OverrideAction::default(),
+            create_destination: true,
+            min_free_bytes: 0,
+            max_actions_per_minute: None,
+            pre_hook: None,
+            post_hook: None,
+            ssh_key: None,
+            active_hours: None,
+            defer_outside_active_hours: false,
+            min_batch: 0,
+            batch_flush_minutes: None,
+            burst_threshold: 0,
+            burst_window_secs: default_burst_window_secs(),
+            flatten: false,
+            remove_flattened_dirs: false,
+            keep_components: 0,
+            preserve_metadata: false,
+            treat_symlinks_as_links: false,
+            set_mode: None,
+            set_group: None,
+            confirm_deletes: true,
+            delete_empty_only: false,
+            delete_extracted: false,
+            dry_run: false,
+            notify_desktop: None,
+            _regexes: Vec::new(),
+            _ext_regexes: Vec::new(),
+            _filename_replace: Vec::new(),
+            _active_hours: None,
+            _journal: None,
+            _group: None,
+            _index: 0,
+            _watch_root: None,
+            _action: None,
+            _override_action: None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Bucket {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut bucket = Bucket::deserialize(deserializer)?;
+        bucket.action = bucket._action.clone().unwrap_or_default();
+        bucket.override_action = bucket._override_action.clone().unwrap_or_default();
+        Ok(bucket)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum Action {
     /// Move the file to the bucket destination.
@@ -57,6 +435,99 @@ pub enum Action {
     Delete,
     /// Copy the file into the bucket destination.
     Copy,
+    /// Rename the file in place according to the bucket's `rename_template`.
+    Rename,
+    /// Create a hard link to the file in the bucket destination.
+    Hardlink,
+    /// Create a symbolic link to the file in the bucket destination.
+    Symlink,
+    /// Unpack a zip, tar or gzip archive into a directory named after it in the bucket
+    /// destination.
+    Extract,
+}
+
+impl Action {
+    /// Whether the path is gone from where it was after the action was applied.
+    pub fn consumes(&self) -> bool {
+        !matches!(
+            self,
+            Self::Copy | Self::Hardlink | Self::Symlink | Self::Extract
+        )
+    }
+
+    /// Whether the action creates a link to the path instead of placing its contents.
+    pub fn links(&self) -> bool {
+        matches!(self, Self::Hardlink | Self::Symlink)
+    }
+}
+
+/// Which kind of paths a bucket takes.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// Only files.
+    Files,
+    /// Only directories.
+    Dirs,
+    /// Files and directories.
+    #[default]
+    Both,
+}
+
+impl Target {
+    /// Check whether a file, or a directory if `is_file` is false, is taken.
+    pub fn accepts(&self, is_file: bool) -> bool {
+        match self {
+            Self::Files => is_file,
+            Self::Dirs => !is_file,
+            Self::Both => true,
+        }
+    }
+}
+
+/// Which of the filters of a bucket a path has to match.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// One of the extension filters, one of the name filters or one of the MIME filters.
+    #[default]
+    Any,
+    /// One filter of every kind. If the bucket has no filters of a kind, only the other kinds
+    /// have to match.
+    All,
+}
+
+/// Why a path fits into a bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchReason {
+    /// The final extension of the path is one of the extension filters.
+    Extension(String),
+    /// The name filter, as written in the configuration, matches the name of the path.
+    NameFilter(String),
+    /// Both the extension and the name filter match, which `MatchMode::All` requires.
+    Both(String, String),
+    /// The MIME filter, as written in the configuration, matches the content type of the file,
+    /// which comes second.
+    Mime(String, String),
+    /// The content predicate, which is the only filter of the bucket, succeeds for the file.
+    Predicate(String),
+}
+
+impl std::fmt::Display for MatchReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Extension(extension) => write!(f, "extension filters contain '{extension}'"),
+            Self::NameFilter(filter) => write!(f, "name filter '{filter}' matches"),
+            Self::Both(extension, filter) => write!(
+                f,
+                "extension filters contain '{extension}' and name filter '{filter}' matches"
+            ),
+            Self::Mime(filter, mime) => {
+                write!(f, "mime filter '{filter}' matches content type '{mime}'")
+            }
+            Self::Predicate(command) => write!(f, "content predicate '{command}' succeeds"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -68,16 +539,52 @@ pub enum OverrideAction {
     Rename,
     #[default]
     Skip,
+    /// Overwrite the file/directory only if the new one was modified more recently.
+    #[serde(rename = "keep-newer")]
+    KeepNewer,
+    /// Overwrite the file/directory only if the new one is larger.
+    #[serde(rename = "keep-larger")]
+    KeepLarger,
+    /// Merge the contents of a directory into the existing directory of the same name. Nested
+    /// directories are merged as well and colliding files are handled by the bucket's
+    /// `merge_override_action`.
+    Merge,
+    /// Append a short hash of the contents of the new file to its name, `file.txt` ->
+    /// `file-1a2b3c4d.txt`, and skip it if a file of that name exists already, since it's the
+    /// same file. Directories are renamed like with `rename`.
+    #[serde(rename = "hash-suffix")]
+    HashSuffix,
+}
+
+/// How buckets with the same priority are ordered when a path fits into several of them.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TieBreak {
+    /// Only the priority is compared, ties go to the bucket whose name sorts last.
+    #[default]
+    #[serde(rename = "highest-priority")]
+    HighestPriority,
+    /// Ties go to the bucket which is defined first in the configuration. Buckets of included
+    /// files are defined after the buckets of the including file.
+    #[serde(rename = "first-defined")]
+    FirstDefined,
+    /// Ties go to the bucket whose name sorts first.
+    Name,
+}
+
+/// What a bucket would do with a path, as decided by `Bucket::plan_action`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionPlan {
+    pub source: PathBuf,
+    /// Where the path ends up if nothing is there yet, empty for deletions. A path which is
+    /// there already is handled by the `override_action` when the action is applied.
+    pub destination: PathBuf,
+    pub action: Action,
 }
 
-#[allow(clippy::non_canonical_partial_ord_impl)]
 impl PartialOrd for Bucket {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let ordering = self.priority.cmp(&other.priority);
-        if ordering.is_eq() {
-            return Some(self.name.cmp(&other.name));
-        }
-        Some(ordering)
+        Some(self.cmp(other))
     }
 }
 
@@ -91,46 +598,284 @@ impl PartialEq for Bucket {
 
 impl Eq for Bucket {}
 
+/// Buckets are ordered by their priority, lowest first, and buckets with the same priority by
+/// their name. Since bucket names are unique, this is a total order of the buckets of a
+/// configuration.
 impl Ord for Bucket {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| self.name.cmp(&other.name))
     }
 }
 
 impl Bucket {
+    /// Whether any of the bucket's actions puts paths into its destination.
+    pub fn needs_destination(&self) -> bool {
+        std::iter::once(&self.action)
+            .chain(self.extension_actions.values())
+            .any(|action| !matches!(action, Action::Delete | Action::Rename))
+    }
+
+    /// The extensions of the extension filters, with the names of `EXTENSION_GROUPS` replaced by
+    /// the extensions of the groups.
+    pub fn extensions(&self) -> Vec<&str> {
+        self.extension_filters
+            .iter()
+            .flat_map(|filter| {
+                EXTENSION_GROUPS
+                    .iter()
+                    .find(|(group, _)| group == filter)
+                    .map_or(vec![filter.as_str()], |(_, extensions)| extensions.to_vec())
+            })
+            .collect()
+    }
+
+    /// A filter which the bucket shares with `other`, described like `extension filter 'pdf'`,
+    /// so that some paths fit into both of them. Content predicates may tell the paths apart, so
+    /// buckets with one share nothing.
+    pub fn shared_filter(&self, other: &Bucket) -> Option<String> {
+        let kinds_overlap = [true, false]
+            .iter()
+            .any(|is_file| self.target.accepts(*is_file) && other.target.accepts(*is_file));
+        if !kinds_overlap || self.content_predicate.is_some() || other.content_predicate.is_some() {
+            return None;
+        }
+        let (ours, theirs) = (self.extensions(), other.extensions());
+        let extension = ours.iter().find(|ext| theirs.contains(ext)).or_else(|| {
+            match (ours.contains(&"*"), theirs.contains(&"*")) {
+                (true, _) => theirs.first(),
+                (_, true) => ours.first(),
+                _ => None,
+            }
+        });
+        if let Some(extension) = extension {
+            return Some(format!("extension filter '{extension}'"));
+        }
+        self._regexes
+            .iter()
+            .find(|ours| {
+                other
+                    ._regexes
+                    .iter()
+                    .any(|theirs| theirs.as_str() == ours.as_str())
+            })
+            .map(|regex| format!("name filter '{}'", regex.as_str()))
+    }
+
+    /// Whether a path is gone from where it was after the bucket's action was applied to it.
+    pub fn consumes(&self) -> bool {
+        self.action.consumes() || matches!(self.action, Action::Extract) && self.delete_extracted
+    }
+
+    /// The bucket with its action replaced by the one of `extension_actions` for the extension of
+    /// `path`, if any.
+    pub fn for_path(&self, path: &Path) -> Cow<'_, Bucket> {
+        let action = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.extension_actions.get(extension));
+        match action {
+            Some(action) => Cow::Owned(Bucket {
+                action: action.clone(),
+                extension_actions: BTreeMap::new(),
+                ..self.clone()
+            }),
+            None => Cow::Borrowed(self),
+        }
+    }
+
     /// Given a path, check if the file fits into the bucket.
     pub fn is_fitting(&self, path: &impl AsRef<Path>) -> JResult<bool> {
+        Ok(self.match_reason(path)?.is_some())
+    }
+
+    /// Given a path, find out why the file fits into the bucket, or `None` if it doesn't.
+    pub fn match_reason(&self, path: &impl AsRef<Path>) -> JResult<Option<MatchReason>> {
         let path = path.as_ref();
-        let opt = path.extension();
-        if let Some(raw_ext) = opt {
-            if let Some(extension) = raw_ext.to_str() {
-                if self.extension_filters.contains(&extension.to_string()) {
-                    return Ok(true);
+        if !self.owner_matches(path)? || !self.is_old_enough(path)? {
+            return Ok(None);
+        }
+        let reason = self.filter_reason(path)?;
+        let Some(predicate) = &self.content_predicate else {
+            return Ok(reason);
+        };
+        // Without any other filters, the predicate alone decides.
+        if (reason.is_none() && self.has_filters()) || !path.is_file() {
+            return Ok(None);
+        }
+        if !self.predicate_passes(predicate, path)? {
+            return Ok(None);
+        }
+        Ok(reason.or_else(|| Some(MatchReason::Predicate(predicate.clone()))))
+    }
+
+    /// Why the file fits into the extension, name and MIME filters of the bucket, or `None` if
+    /// it doesn't.
+    pub(crate) fn filter_reason(&self, path: &Path) -> JResult<Option<MatchReason>> {
+        let extension = path
+            .extension()
+            .and_then(|raw_ext| raw_ext.to_str())
+            .filter(|extension| {
+                self.extension_filters
+                    .iter()
+                    .any(|filter| extension_matches(filter, extension))
+                    || self
+                        ._ext_regexes
+                        .iter()
+                        .any(|regex| regex.is_match(extension))
+            });
+        let name_filter = self.filter_subject(path).and_then(|fname| {
+            self.name_filters
+                .iter()
+                .zip(self._regexes.iter())
+                .find(|(_, regex)| regex.is_match(fname))
+                .map(|(filter, _)| filter)
+        });
+
+        // The contents of a file are only looked at if the other filters don't decide already.
+        let reason = match self.match_mode {
+            MatchMode::Any => match (extension, name_filter) {
+                (Some(e), _) => Some(MatchReason::Extension(e.to_string())),
+                (None, Some(f)) => Some(MatchReason::NameFilter(f.clone())),
+                (None, None) => self.mime_reason(path)?,
+            },
+            // Kinds of filters which the bucket doesn't have don't need to match.
+            MatchMode::All => {
+                let reason = match (extension, name_filter) {
+                    (Some(e), Some(f)) => Some(MatchReason::Both(e.to_string(), f.clone())),
+                    (Some(e), None) if self.name_filters.is_empty() => {
+                        Some(MatchReason::Extension(e.to_string()))
+                    }
+                    (None, Some(f)) if !self.has_extension_filters() => {
+                        Some(MatchReason::NameFilter(f.clone()))
+                    }
+                    (None, None)
+                        if !self.has_extension_filters() && self.name_filters.is_empty() =>
+                    {
+                        None
+                    }
+                    _ => return Ok(None),
+                };
+                if self.mime_filters.is_empty() {
+                    reason
+                } else {
+                    // The content type is only reported if nothing else had to match.
+                    self.mime_reason(path)?.map(|mime| reason.unwrap_or(mime))
                 }
             }
-        }
-        // If no extension filters are not found, try name filters.
-        let opt = path.file_name();
-        if let Some(raw_fname) = opt {
-            if let Some(fname) = raw_fname.to_str() {
-                let name_match = self._regexes.iter().any(|filter| filter.is_match(fname));
+        };
+        Ok(reason)
+    }
 
-                return Ok(name_match);
+    /// Run the `content_predicate` for the file `path`, killing it once its timeout has passed.
+    fn predicate_passes(&self, predicate: &str, path: &Path) -> JResult<bool> {
+        let command = if predicate.contains("{path}") {
+            predicate.replace("{path}", &shell_quote(path))
+        } else {
+            format!("{predicate} {}", shell_quote(path))
+        };
+        log::trace!("running content predicate: {command}");
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let timeout = Duration::from_secs(self.content_predicate_timeout_secs);
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status.success());
             }
+            if started.elapsed() >= timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!(JError::PredicateTimedOut(
+                    command,
+                    self.content_predicate_timeout_secs
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// What the name filters are matched against, the name of `path` or, with
+    /// `match_full_path`, all of it.
+    fn filter_subject<'a>(&self, path: &'a Path) -> Option<&'a str> {
+        let subject = if self.match_full_path {
+            Some(path.as_os_str())
+        } else {
+            path.file_name()
+        };
+        subject.and_then(|subject| subject.to_str())
+    }
+
+    /// Whether the bucket has any extension, name or MIME filters.
+    pub fn has_filters(&self) -> bool {
+        self.has_extension_filters()
+            || !self.name_filters.is_empty()
+            || !self.mime_filters.is_empty()
+    }
+
+    /// Whether the bucket has any extension filters, literal or regular expressions.
+    pub fn has_extension_filters(&self) -> bool {
+        !self.extension_filters.is_empty() || !self.extension_regex.is_empty()
+    }
+
+    /// The first MIME filter which matches the content type of the file at `path`, if any.
+    fn mime_reason(&self, path: &Path) -> JResult<Option<MatchReason>> {
+        if self.mime_filters.is_empty() || !path.is_file() {
+            return Ok(None);
+        }
+        let Some(kind) = infer::get_from_path(path)? else {
+            return Ok(None);
+        };
+        let mime = kind.mime_type();
+        Ok(self
+            .mime_filters
+            .iter()
+            .find(|filter| mime_matches(filter, mime))
+            .map(|filter| MatchReason::Mime(filter.clone(), mime.to_string())))
+    }
+
+    /// Check whether `path` is owned by the user and group of the owner filters.
+    #[cfg(unix)]
+    pub fn owner_matches(&self, path: &Path) -> JResult<bool> {
+        if self.owner_uid.is_none() && self.owner_gid.is_none() {
+            return Ok(true);
         }
-        Ok(false)
+        let meta = metadata(path)?;
+        Ok(self.owner_uid.is_none_or(|uid| meta.uid() == uid)
+            && self.owner_gid.is_none_or(|gid| meta.gid() == gid))
+    }
+
+    /// Owner filters are rejected by `init` on other platforms, so every path matches.
+    #[cfg(not(unix))]
+    pub fn owner_matches(&self, _path: &Path) -> JResult<bool> {
+        Ok(true)
+    }
+
+    /// Check whether `path` was last modified at least `min_age_days` days ago.
+    pub fn is_old_enough(&self, path: &Path) -> JResult<bool> {
+        let Some(days) = self.min_age_days else {
+            return Ok(true);
+        };
+        let age = metadata(path)?.modified()?.elapsed().unwrap_or_default();
+        Ok(age >= std::time::Duration::from_secs(days * 24 * 60 * 60))
     }
 
     // Rename path semantically.
-    fn rename_seq(&self, path: &impl AsRef<Path>) -> JResult<PathBuf> {
-        let path = path.as_ref();
-        if !path.exists() {
+    fn rename_seq(&self, path: &Path, taken: impl Fn(&Path) -> bool) -> JResult<PathBuf> {
+        if !taken(path) {
             return Ok(path.to_owned());
         }
         let mut count = 1;
         let mut other_path = path.to_owned();
 
-        while other_path.exists() {
+        while taken(&other_path) {
             if let Some(path_str) = path.to_str() {
                 other_path = PathBuf::from(format!("{}.{}", path_str, count));
                 count += 1;
@@ -147,73 +892,1353 @@ impl Bucket {
         Ok(other_path)
     }
 
-    /// Try to apply the bucket's action on file.
-    ///
-    /// Note: This method does not check if the file fits into the bucket.
-    pub fn apply_action(&self, path: &impl AsRef<Path>, is_file: bool) -> JResult {
+    /// `target` with a short hash of the contents of `path` appended to its stem, like
+    /// `report-1a2b3c4d.pdf`. Directories can't be hashed, they get the next free name of
+    /// `rename_seq` instead.
+    fn hash_suffixed(
+        &self,
+        path: &Path,
+        target: &Path,
+        taken: impl Fn(&Path) -> bool,
+    ) -> JResult<PathBuf> {
+        if symlink_metadata(path)?.is_dir() {
+            return self.rename_seq(target, taken);
+        }
+        hash_suffixed(path, target)
+    }
+
+    /// The capture groups of the first name filter which matches `path`, which the destination
+    /// refers to like `$1` or `${client}`, or `None` if it doesn't refer to any or no name filter
+    /// matches.
+    fn destination_captures<'a>(&self, path: &'a Path) -> Option<Captures<'a>> {
+        self.destination.to_str().filter(|d| d.contains('$'))?;
+        self.name_captures(path)
+    }
+
+    /// What `apply_action` would do with `path`, including any override of the bucket which
+    /// applies to it, without changing anything on disk.
+    pub fn plan_action(&self, path: &impl AsRef<Path>, is_file: bool) -> JResult<ActionPlan> {
         let path = path.as_ref();
-        let mut to_path = self.destination.resolve().join(
-            path.components()
-                .next_back()
-                .expect("unable to get last component of path"),
-        );
+        if let Cow::Owned(bucket) = self.for_path(path) {
+            return bucket.plan_action(&path, is_file);
+        }
+        let is_file = is_file || self.treats_as_link(path);
+        Ok(ActionPlan {
+            source: path.to_owned(),
+            destination: self.destination_for(path, is_file, |p| p != path && p.exists())?,
+            action: self.action.clone(),
+        })
+    }
 
-        if matches!(self.override_action, OverrideAction::Skip)
-            && to_path.exists()
-            && !matches!(self.action, Action::Delete)
+    /// Whether `path` is a symlink which is handled as a link rather than what it points to.
+    fn treats_as_link(&self, path: &Path) -> bool {
+        self.treat_symlinks_as_links && symlink_metadata(path).is_ok_and(|m| m.is_symlink())
+    }
+
+    /// Where `path` is put, where `taken` tells which paths the `rename` action has to avoid.
+    /// Flattened directories are put into the destination itself.
+    fn destination_for(
+        &self,
+        path: &Path,
+        is_file: bool,
+        taken: impl Fn(&Path) -> bool,
+    ) -> JResult<PathBuf> {
+        // Captures are filled in last, so that braces in captured names are never placeholders.
+        let captures = self.destination_captures(path);
+        let filled = self.fill_placeholders(path, captures.is_some())?;
+        let destination = match captures {
+            Some(captures) => {
+                let mut destination = String::new();
+                captures.expand(&filled.to_string_lossy(), &mut destination);
+                PathBuf::from(destination)
+            }
+            None => filled,
+        };
+        let destination = destination.resolve().join(self.kept_components(path));
+        Ok(match self.action {
+            Action::Delete => PathBuf::new(),
+            Action::Rename => self.rename_target(path, taken)?,
+            Action::Move | Action::Copy if self.flatten && !is_file => destination,
+            Action::Extract => {
+                destination.join(self.destination_name(extract::archive_stem(path).as_os_str()))
+            }
+            _ => destination.join(
+                self.destination_name(
+                    path.file_name()
+                        .expect("unable to get last component of path"),
+                ),
+            ),
+        })
+    }
+
+    /// The innermost `keep_components` directories which `path` is in inside of its watch path.
+    fn kept_components(&self, path: &Path) -> PathBuf {
+        let Some(parent) = self
+            ._watch_root
+            .as_ref()
+            .filter(|_| self.keep_components > 0)
+            .and_then(|root| path.parent()?.strip_prefix(root).ok())
+        else {
+            return PathBuf::new();
+        };
+        let components: Vec<_> = parent.components().collect();
+        let skipped = components.len().saturating_sub(self.keep_components);
+        components[skipped..].iter().collect()
+    }
+
+    /// Whether the destination contains any placeholders.
+    pub fn has_placeholders(&self) -> bool {
+        let mut found = false;
+        let _ = replace_placeholders(&self.destination.to_string_lossy(), |_| {
+            found = true;
+            Ok(String::new())
+        });
+        found
+    }
+
+    /// The part of the destination before its first placeholder, which every path the bucket
+    /// puts somewhere ends up in.
+    pub fn destination_root(&self) -> PathBuf {
+        self.destination
+            .components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains('{'))
+            .collect()
+    }
+
+    /// `destination` with its placeholders filled in for `path`: `{ext}` with the extension,
+    /// `{stem}` with the name without the extension, `{date}` with the modification date like
+    /// `2024-05-31`, and `{date:FORMAT}` with the modification time in a `strftime` format like
+    /// `%Y`. Placeholders which are filled in with nothing leave out their directory.
+    ///
+    /// With `escape`, every `$` which is filled in is doubled, so that expanding the capture
+    /// groups afterwards leaves it as it is.
+    fn fill_placeholders(&self, path: &Path, escape: bool) -> JResult<PathBuf> {
+        if !self.has_placeholders() {
+            return Ok(self.destination.clone());
+        }
+        let mut modified = None;
+        let filled = replace_placeholders(&self.destination.to_string_lossy(), |token| {
+            let value: String = match token {
+                "ext" => path
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into(),
+                "stem" => path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into(),
+                _ => {
+                    let format = token.strip_prefix("date:").unwrap_or("%Y-%m-%d");
+                    let modified = match modified {
+                        Some(modified) => modified,
+                        None => *modified.insert(chrono::DateTime::<chrono::Local>::from(
+                            path.metadata()?.modified()?,
+                        )),
+                    };
+                    modified.format(format).to_string()
+                }
+            };
+            Ok(if escape {
+                value.replace('$', "$$")
+            } else {
+                value
+            })
+        })?;
+        // Components are collected again, so that empty ones don't leave `//` behind.
+        Ok(Path::new(&filled).components().collect())
+    }
+
+    /// The capture groups of the first name filter which matches `path`, which is the one that
+    /// `match_reason` reports.
+    fn name_captures<'a>(&self, path: &'a Path) -> Option<Captures<'a>> {
+        let subject = self.filter_subject(path)?;
+        self._regexes.iter().find_map(|r| r.captures(subject))
+    }
+
+    /// Decide where a path is going to be placed and reserve that destination.
+    ///
+    /// Returns `None` if the path should be skipped, because its destination is already taken.
+    fn reserve_destination(&self, plan: &ActionPlan) -> JResult<Option<Reservation>> {
+        let path = plan.source.as_path();
+        let destination = plan.destination.parent().unwrap_or(&plan.destination);
+        if (self.create_destination
+            || self.destination_captures(path).is_some()
+            || self.has_placeholders()
+            || self.keep_components > 0)
+            && !matches!(self.action, Action::Delete | Action::Rename)
+            && !destination.exists()
         {
+            log::info!("creating destination '{}'", destination.display());
+            create_dir_all(destination)?;
+        }
+
+        loop {
+            let mut reserved = RESERVED_PATHS.lock().expect("reserved paths lock poisoned");
+            let taken = |p: &Path| p.exists() || reserved.contains(p);
+
+            if matches!(self.action, Action::Delete) {
+                return Ok(Some(Reservation(None)));
+            }
+            // Other actions may have taken the planned name in the meantime.
+            let to_path = match self.action {
+                Action::Rename => self.rename_target(path, taken)?,
+                _ => plan.destination.clone(),
+            };
+            if to_path == path {
+                log::trace!("'{}' has the right name already", path.display());
+                return Ok(None);
+            }
+            // E.g. a destination with capture groups may lead right back to where the path is.
+            if is_same_place(path, &to_path) {
+                log::info!(
+                    source:% = path.display(),
+                    bucket = self.name.as_str();
+                    "skipping '{}', because bin '{}' would put it where it is already",
+                    path.display(),
+                    self.name,
+                );
+                stats::record_skip();
+                return Ok(None);
+            }
+            if matches!(self.override_action, OverrideAction::Skip) && taken(&to_path) {
+                log::info!(
+                    source:% = path.display(),
+                    destination:% = to_path.display(),
+                    bucket = self.name.as_str();
+                    "skipping '{}' because bin action is 'skip' and '{}' already exists",
+                    path.display(),
+                    to_path.display(),
+                );
+                stats::record_skip();
+                return Ok(None);
+            }
+            if matches!(
+                self.override_action,
+                OverrideAction::Overwrite
+                    | OverrideAction::KeepNewer
+                    | OverrideAction::KeepLarger
+                    | OverrideAction::Merge
+            ) && reserved.contains(&to_path)
+            {
+                // Another action is writing to the same destination, wait for it to finish.
+                drop(reserved);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                continue;
+            }
+            if to_path.exists() && !self.replaces(&self.override_action, path, &to_path)? {
+                stats::record_skip();
+                return Ok(None);
+            }
+
+            let to_path = match self.override_action {
+                OverrideAction::Rename => self.rename_seq(&to_path, taken)?,
+                OverrideAction::HashSuffix if taken(&to_path) => {
+                    let hashed = self.hash_suffixed(path, &to_path, taken)?;
+                    if taken(&hashed) {
+                        log::info!(
+                            source:% = path.display(),
+                            destination:% = hashed.display(),
+                            bucket = self.name.as_str();
+                            "skipping '{}', because '{}' has the same contents",
+                            path.display(),
+                            hashed.display(),
+                        );
+                        stats::record_skip();
+                        return Ok(None);
+                    }
+                    hashed
+                }
+                _ => to_path,
+            };
+            reserved.insert(to_path.clone());
+            return Ok(Some(Reservation(Some(to_path))));
+        }
+    }
+
+    /// The path `path` is renamed to by the `rename` action, in the same directory.
+    fn rename_target(&self, path: &Path, taken: impl Fn(&Path) -> bool) -> JResult<PathBuf> {
+        let Some(template) = &self.rename_template else {
+            bail!(JError::MissingValue("rename_template".into()))
+        };
+        // Captures are filled in first, so a `$` in the file name isn't taken for one.
+        let template = match self.name_captures(path) {
+            Some(captures) if template.contains('$') => {
+                let mut expanded = String::new();
+                captures.expand(template, &mut expanded);
+                Cow::Owned(expanded)
+            }
+            _ => Cow::Borrowed(template),
+        };
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+        let date = chrono::DateTime::<chrono::Local>::from(path.metadata()?.modified()?)
+            .format("%Y-%m-%d")
+            .to_string();
+        let render = |counter: usize| {
+            let name = template
+                .replace("{stem}", &stem)
+                .replace("{ext}", &ext)
+                .replace("{date}", &date)
+                .replace("{counter}", &counter.to_string());
+            // Files without an extension would end up with a trailing dot otherwise.
+            path.with_file_name(self.destination_name(name.trim_end_matches('.').as_ref()))
+        };
+
+        if !template.contains("{counter}") {
+            return Ok(render(0));
+        }
+        let mut counter = 1;
+        while render(counter) != path && taken(&render(counter)) {
+            counter += 1;
+        }
+        Ok(render(counter))
+    }
+
+    /// The name which a path called `name` gets in the destination.
+    fn destination_name(&self, name: &OsStr) -> OsString {
+        if !self.normalize_name && self._filename_replace.is_empty() {
+            return name.to_owned();
+        }
+        let mut name = name.to_string_lossy().into_owned();
+        for (regex, replacement) in self._filename_replace.iter() {
+            name = regex.replace_all(&name, replacement.as_str()).into_owned();
+        }
+        if !self.normalize_name {
+            return name.into();
+        }
+        normalize_name(&name, self.normalize_lowercase, &self.normalize_separator).into()
+    }
+
+    /// Decide whether `path` replaces the `existing` path in the destination.
+    ///
+    /// Only the `KeepNewer` and `KeepLarger` override actions compare the two, the others always
+    /// replace.
+    fn replaces(
+        &self,
+        override_action: &OverrideAction,
+        path: &Path,
+        existing: &Path,
+    ) -> JResult<bool> {
+        let (replace, reason) = match override_action {
+            OverrideAction::KeepNewer => (
+                path.metadata()?.modified()? > existing.metadata()?.modified()?,
+                "newer",
+            ),
+            OverrideAction::KeepLarger => (size_of(path) > size_of(existing), "larger"),
+            _ => return Ok(true),
+        };
+
+        if replace {
+            log::info!(
+                source:% = path.display(),
+                destination:% = existing.display(),
+                bucket = self.name.as_str();
+                "replacing '{}', because '{}' is {reason}",
+                existing.display(),
+                path.display(),
+            );
+        } else {
             log::info!(
-                "skipping '{}' because bin action is 'skip' and '{}' already exists",
+                source:% = path.display(),
+                destination:% = existing.display(),
+                bucket = self.name.as_str();
+                "skipping '{}', because it is not {reason} than '{}'",
                 path.display(),
-                to_path.display(),
+                existing.display(),
             );
+        }
+        Ok(replace)
+    }
+
+    /// Make sure that placing `bytes` bytes from `path` at `to_path` leaves at least
+    /// `min_free_bytes` free.
+    ///
+    /// Moves within a single file system don't need any space, so they are always allowed.
+    fn check_free_space(&self, path: &Path, to_path: &Path, bytes: u64) -> JResult {
+        if self.min_free_bytes == 0 {
             return Ok(());
         }
+        let destination = to_path.parent().unwrap_or(to_path);
+        let same_device = || -> JResult<bool> {
+            Ok(symlink_metadata(path)?.dev() == metadata(destination)?.dev())
+        };
+        match self.action {
+            Action::Delete | Action::Rename | Action::Hardlink | Action::Symlink => return Ok(()),
+            Action::Move if same_device()? => return Ok(()),
+            _ => {}
+        }
 
-        if matches!(self.override_action, OverrideAction::Rename) {
-            to_path = self.rename_seq(&to_path)?;
+        let available = available_space(destination)?;
+        let needed = bytes.saturating_add(self.min_free_bytes);
+        if needed > available {
+            bail!(JError::InsufficientSpace(
+                destination.to_owned(),
+                needed,
+                available
+            ));
+        }
+        Ok(())
+    }
+
+    /// Try to apply the bucket's action on file.
+    ///
+    /// Note: This method does not check if the file fits into the bucket. It's safe to call it
+    /// from multiple threads at once, even for files with the same name.
+    pub fn apply_action(&self, path: &impl AsRef<Path>, is_file: bool) -> JResult {
+        let path = path.as_ref();
+        if let Cow::Owned(bucket) = self.for_path(path) {
+            return bucket.apply_action(&path, is_file);
+        }
+        let plan = self.plan_action(&path, is_file)?;
+        if preview::plan(&self.name, &plan.action, path, &plan.destination) {
+            return Ok(());
+        }
+        if self.dry_run {
+            preview::log_planned(&self.name, &plan.action, path, &plan.destination);
+            return Ok(());
+        }
+        let is_link = self.treats_as_link(path);
+        // A link is handled like a file, whatever it points to.
+        let is_file = is_file || is_link;
+        if self.flatten && !is_file && matches!(self.action, Action::Move | Action::Copy) {
+            return self.apply_flattened(path);
+        }
+        if let Some(max) = self.max_actions_per_minute {
+            throttle::acquire(&self.name, max, std::time::Duration::from_secs(60));
+        }
+        // Waiting for the throttle is not part of the time the action takes.
+        let started = Instant::now();
+        if matches!(self.action, Action::Move | Action::Copy)
+            && let Some(remote) = SftpDestination::parse(&self.destination)
+        {
+            return self.upload(path, &remote, is_file);
+        }
+        let Some(reservation) = self.reserve_destination(&plan)? else {
+            feed::publish(
+                &self.name,
+                &self.action,
+                path.to_owned(),
+                plan.destination,
+                Outcome::Skipped,
+                None,
+            );
+            return Ok(());
+        };
+        let to_path = reservation.path();
+        // The size has to be taken before the action, moved and deleted paths are gone after it.
+        let bytes = size_of(path);
+        self.check_free_space(path, to_path, bytes)?;
+        // Asking comes first, so that nothing of the action happens if the answer is no.
+        if matches!(self.action, Action::Delete)
+            && self.confirm_deletes
+            && INTERACTIVE.load(Ordering::Relaxed)
+            && !confirm_delete(path, is_file)?
+        {
+            log::info!("not deleting '{}'", path.display());
+            stats::record_skip();
+            feed::publish(
+                &self.name,
+                &self.action,
+                path.to_owned(),
+                to_path.to_owned(),
+                Outcome::Skipped,
+                None,
+            );
+            return Ok(());
+        }
+        if let Some(hook) = &self.pre_hook {
+            run_hook(hook, path, to_path)?;
         }
 
+        let merged = matches!(self.override_action, OverrideAction::Merge)
+            && matches!(self.action, Action::Move | Action::Copy | Action::Extract)
+            && !is_link
+            && symlink_metadata(to_path).is_ok();
+        // The override action already decided that an existing path may be replaced. Files
+        // replace files in place, but moving or copying onto a directory would fail or mix the
+        // contents of both, so it's removed first.
+        if matches!(self.action, Action::Move | Action::Copy)
+            && !merged
+            && !is_link
+            && symlink_metadata(to_path).is_ok_and(|meta| !is_file || meta.is_dir())
+        {
+            remove_existing(to_path)?;
+        }
         match self.action {
+            Action::Extract => {
+                // The override action already decided that an existing extraction may be
+                // replaced, `merge` unpacks into it.
+                if !merged {
+                    remove_existing(to_path)?;
+                }
+                extract::extract(path, to_path)?;
+                if self.delete_extracted {
+                    remove_file(path)?;
+                }
+            }
+            _ if merged => self.merge(path, to_path)?,
+            Action::Move | Action::Copy if is_link => {
+                copy_link(path, to_path)?;
+                if matches!(self.action, Action::Move) {
+                    remove_file(path)?;
+                }
+            }
             Action::Delete => {
                 if is_file {
                     remove_file(path)?
+                } else if self.delete_empty_only {
+                    match remove_dir(path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => {
+                            log::warn!(
+                                "not deleting '{}', because it's not empty and bin '{}' only deletes empty directories",
+                                path.display(),
+                                self.name
+                            );
+                            stats::record_skip();
+                            return Ok(());
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
                 } else {
                     remove_dir_all(path)?
                 };
             }
-            Action::Move => {
-                if is_file {
-                    move_file(
-                        path,
-                        to_path,
-                        &fs_extra::file::CopyOptions::new().skip_exist(true),
-                    )?;
-                } else {
-                    move_dir(path, to_path, &fs_extra::dir::CopyOptions::new())?;
-                };
-            }
+            Action::Move => move_path(path, to_path, is_file)?,
+            Action::Rename => rename(path, to_path)?,
             Action::Copy => {
-                if is_file {
-                    copy(path, to_path, &fs_extra::file::CopyOptions::new())?
+                copy_path(path, to_path, is_file)?;
+                if self.preserve_metadata {
+                    copy_metadata(path, to_path)?;
+                }
+            }
+            Action::Hardlink | Action::Symlink => link_path(path, to_path, &self.action, is_file)?,
+        };
+
+        // Moving a merged directory back would take the contents it was merged with along.
+        if let Some(journal) = &self._journal
+            && !matches!(self.action, Action::Delete)
+            && !merged
+            && let Err(e) = journal::record(journal, &self.name, &self.action, path, to_path)
+        {
+            log::warn!("writing journal '{}': {e}", journal.display());
+        }
+        // Changing the mode or group of a link would change its target instead.
+        if matches!(self.action, Action::Move | Action::Copy) && !is_link {
+            self.set_ownership(to_path).with_context(|| {
+                format!(
+                    "setting the mode and group of '{}' in bin '{}'",
+                    to_path.display(),
+                    self.name
+                )
+            })?;
+        }
+        self.finish(path, to_path, bytes, started);
+        Ok(())
+    }
+
+    /// Move or copy `path` to `target`, which exists already.
+    ///
+    /// If both are directories, every entry of `path` is merged into `target` one by one and
+    /// `path` is removed afterwards when moving, unless some of its entries were skipped. Any
+    /// other collision is handled by the `merge_override_action`.
+    fn merge(&self, path: &Path, target: &Path) -> JResult {
+        let is_dir = |p: &Path| symlink_metadata(p).is_ok_and(|m| m.is_dir());
+        if is_dir(path) && is_dir(target) {
+            log::debug!("merging '{}' into '{}'", path.display(), target.display());
+            for dentry in path.read_dir()? {
+                let dentry = dentry?;
+                let nested_target = target.join(dentry.file_name());
+                if symlink_metadata(&nested_target).is_ok() {
+                    self.merge(&dentry.path(), &nested_target)?;
                 } else {
-                    copy_dir(path, to_path, &fs_extra::dir::CopyOptions::new())?
-                };
+                    self.place(&dentry.path(), &nested_target)?;
+                }
+            }
+            if matches!(self.action, Action::Move) {
+                let _ = remove_dir(path);
+            }
+            return Ok(());
+        }
+
+        let target = match self.merge_override_action {
+            OverrideAction::Skip | OverrideAction::Merge => {
+                log::info!(
+                    "skipping '{}' while merging, because '{}' already exists",
+                    path.display(),
+                    target.display()
+                );
+                return Ok(());
+            }
+            OverrideAction::Rename => self.rename_seq(target, |p| symlink_metadata(p).is_ok())?,
+            OverrideAction::HashSuffix => {
+                let hashed = self.hash_suffixed(path, target, |p| symlink_metadata(p).is_ok())?;
+                if symlink_metadata(&hashed).is_ok() {
+                    log::info!(
+                        "skipping '{}' while merging, because '{}' has the same contents",
+                        path.display(),
+                        hashed.display()
+                    );
+                    return Ok(());
+                }
+                hashed
+            }
+            _ if !self.replaces(&self.merge_override_action, path, target)? => return Ok(()),
+            _ => {
+                // Files and directories can't replace each other in place.
+                if is_dir(target) {
+                    remove_dir_all(target)?;
+                } else if is_dir(path) {
+                    remove_file(target)?;
+                }
+                target.to_owned()
             }
         };
+        self.place(path, &target)
+    }
 
-        log::info!("'{}' put into bin '{}'.", path.display(), self.name);
+    /// Move or copy `path` to `target` as part of a merge.
+    fn place(&self, path: &Path, target: &Path) -> JResult {
+        let is_file = !symlink_metadata(path)?.is_dir();
+        match self.action {
+            Action::Copy => {
+                copy_path(path, target, is_file)?;
+                if self.preserve_metadata {
+                    copy_metadata(path, target)?;
+                }
+                Ok(())
+            }
+            _ => move_path(path, target, is_file),
+        }
+    }
+
+    /// Give `path` the `set_mode` and `set_group` of the bucket, for directories including
+    /// everything inside of them.
+    #[cfg(unix)]
+    fn set_ownership(&self, path: &Path) -> JResult {
+        use std::os::unix::fs::{PermissionsExt, chown};
+
+        if self.set_mode.is_none() && self._group.is_none() {
+            return Ok(());
+        }
+        let mut stack = vec![path.to_owned()];
+        while let Some(path) = stack.pop() {
+            let is_dir = symlink_metadata(&path)?.is_dir();
+            if is_dir {
+                for dentry in path.read_dir()? {
+                    stack.push(dentry?.path());
+                }
+            } else if let Some(mode) = self.set_mode {
+                set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+            }
+            if self._group.is_some() {
+                chown(&path, None, self._group)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Modes and groups are rejected by `init` on other platforms.
+    #[cfg(not(unix))]
+    fn set_ownership(&self, _path: &Path) -> JResult {
+        Ok(())
+    }
+
+    /// Apply the action on every file inside of the directory `path`, keeping on with the
+    /// remaining files if one of them fails.
+    fn apply_flattened(&self, path: &Path) -> JResult {
+        let mut res = Ok(());
+        let mut dirs = Vec::new();
+        let mut stack = vec![path.to_owned()];
+        while let Some(dir) = stack.pop() {
+            for dentry in dir.read_dir()? {
+                let dentry_path = dentry?.path();
+                if dentry_path.is_dir() && !dentry_path.is_symlink() {
+                    stack.push(dentry_path);
+                    continue;
+                }
+                let file_res = self.apply_action(&dentry_path, true);
+                if res.is_ok() {
+                    res = file_res;
+                }
+            }
+            dirs.push(dir);
+        }
+
+        if matches!(self.action, Action::Move) && self.remove_flattened_dirs {
+            // Subdirectories come after their parents, and only empty directories are removed.
+            for dir in dirs.iter().rev() {
+                let _ = remove_dir(dir);
+            }
+        }
+        res
+    }
+
+    /// Upload a path to a remote destination and remove it afterwards, if it's being moved.
+    fn upload(&self, path: &Path, remote: &SftpDestination, is_file: bool) -> JResult {
+        let started = Instant::now();
+        let bytes = size_of(path);
+        let name = path.file_name().unwrap_or_default();
+        if let Some(hook) = &self.pre_hook {
+            run_hook(hook, path, &remote.url(&remote.path.join(name)))?;
+        }
+
+        let key_file = self
+            .ssh_key
+            .as_ref()
+            .map(|key| PathBuf::from(key.resolve()));
+        let Some(uploaded) = remote.upload(path, key_file.as_deref(), &self.override_action)?
+        else {
+            return Ok(());
+        };
+        if matches!(self.action, Action::Move) {
+            if is_file {
+                remove_file(path)?
+            } else {
+                remove_dir_all(path)?
+            };
+        }
 
+        self.finish(path, &remote.url(&uploaded), bytes, started);
         Ok(())
     }
 
-    /// Initialize Regex matchers.
+    /// Log, count and announce a path of `bytes` bytes which was put at `to_path`, by an action
+    /// which started at `started`.
+    fn finish(&self, path: &Path, to_path: &Path, bytes: u64, started: Instant) {
+        let elapsed = started.elapsed();
+        log::trace!(
+            bucket = self.name.as_str(),
+            elapsed_ms = elapsed.as_millis() as u64;
+            "putting '{}' into bin '{}' took {}ms",
+            path.display(),
+            self.name,
+            elapsed.as_millis()
+        );
+        log::info!(
+            source:% = path.display(),
+            destination:% = to_path.display(),
+            bucket = self.name.as_str();
+            "'{}' put into bin '{}'.",
+            path.display(),
+            self.name
+        );
+        if let Some(hook) = &self.post_hook
+            && let Err(e) = run_hook(hook, path, to_path)
+        {
+            log::warn!("{e}");
+        }
+        stats::record(&self.name, &self.action, bytes, elapsed);
+        activity::record_action(&self.name);
+        feed::publish(
+            &self.name,
+            &self.action,
+            path.to_owned(),
+            to_path.to_owned(),
+            Outcome::Done,
+            None,
+        );
+        if self.notify_desktop.unwrap_or(false) {
+            notification::notify(path, &self.name);
+        }
+    }
+
+    /// Check whether the bucket is active at the given time of day.
+    pub fn is_active_at(&self, time: NaiveTime) -> bool {
+        self._active_hours.is_none_or(|hours| hours.contains(time))
+    }
+
+    /// Initialize Regex matchers and parse the active hours.
     pub fn init(&mut self) -> JResult {
+        let renames = std::iter::once(&self.action)
+            .chain(self.extension_actions.values())
+            .any(|action| matches!(action, Action::Rename));
+        if renames && self.rename_template.is_none() {
+            bail!(JError::MissingValue(format!(
+                "rename_template of bin '{}'",
+                self.name
+            )));
+        }
+        if cfg!(not(unix)) && (self.owner_uid.is_some() || self.owner_gid.is_some()) {
+            bail!(
+                "bin '{}' has owner filters, which are only supported on Unix",
+                self.name
+            );
+        }
+        if cfg!(not(unix)) && (self.set_mode.is_some() || self.set_group.is_some()) {
+            bail!(
+                "bin '{}' sets a mode or group, which is only supported on Unix",
+                self.name
+            );
+        }
+        if self.normalize_separator.contains(['/', '.']) {
+            bail!(
+                "normalize_separator of bin '{}' must not contain '/' or '.'",
+                self.name
+            );
+        }
+        let remote = SftpDestination::parse(&self.destination).is_some();
+        if remote && self.has_placeholders() {
+            bail!(
+                "bin '{}' has placeholders in its destination, which is not supported for sftp destinations",
+                self.name
+            );
+        }
+        // Placeholders are checked here, so that a mistake in one doesn't wait for the first path.
+        replace_placeholders(&self.destination.to_string_lossy(), |token| {
+            match token.strip_prefix("date:") {
+                _ if matches!(token, "ext" | "stem" | "date") => {}
+                Some(format)
+                    if !chrono::format::StrftimeItems::new(format)
+                        .any(|item| item == chrono::format::Item::Error) => {}
+                _ => bail!(JError::UnknownPlaceholder(
+                    self.name.clone(),
+                    token.to_string()
+                )),
+            }
+            Ok(String::new())
+        })?;
+        if self.action.links() && remote {
+            bail!(
+                "bin '{}' links files, which is not supported for sftp destinations",
+                self.name
+            );
+        }
+        if self.max_actions_per_minute == Some(0) {
+            bail!(
+                "max_actions_per_minute of bin '{}' has to be at least 1",
+                self.name
+            );
+        }
+        self._group = self.set_group.as_deref().map(group_id).transpose()?;
+        self._active_hours = self.active_hours.as_deref().map(str::parse).transpose()?;
         self._regexes.clear();
+        // Every filter is compiled, so that all of the invalid ones are reported at once.
+        let mut invalid = Vec::new();
         for filter in self.name_filters.iter() {
-            self._regexes.push(Regex::new(filter)?);
+            let pattern = if self.anchor_names {
+                Cow::Owned(format!("^(?:{filter})$"))
+            } else {
+                Cow::Borrowed(filter.as_str())
+            };
+            match RegexBuilder::new(&pattern)
+                .case_insensitive(self.case_insensitive_names)
+                .build()
+            {
+                Ok(regex) => self._regexes.push(regex),
+                Err(e) => invalid.push((self.name.clone(), filter.clone(), e.to_string())),
+            }
+        }
+        self._ext_regexes.clear();
+        for filter in self.extension_regex.iter() {
+            // The whole extension has to match, not just a part of it.
+            match Regex::new(&format!("^(?:{filter})$")) {
+                Ok(regex) => self._ext_regexes.push(regex),
+                Err(e) => invalid.push((self.name.clone(), filter.clone(), e.to_string())),
+            }
         }
 
-        Ok(())
+        self._filename_replace.clear();
+        for (filter, replacement) in self.filename_replace.iter() {
+            match Regex::new(filter) {
+                Ok(regex) => self._filename_replace.push((regex, replacement.clone())),
+                Err(e) => invalid.push((self.name.clone(), filter.clone(), e.to_string())),
+            }
+        }
+
+        match invalid.len() {
+            0 => Ok(()),
+            1 => {
+                let (name, filter, e) = invalid.remove(0);
+                bail!(JError::RegexCompile(name, filter, e))
+            }
+            _ => bail!(JError::InvalidNameFilters(invalid)),
+        }
+    }
+}
+
+/// Replace the `{...}` placeholders of `template` with what `fill` returns for the text between
+/// the braces. Capture groups like `${client}` are no placeholders and left alone, and so is a
+/// `{` without a closing `}`.
+fn replace_placeholders(
+    template: &str,
+    mut fill: impl FnMut(&str) -> JResult<String>,
+) -> JResult<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let (before, after) = rest.split_at(start);
+        output.push_str(before);
+        let Some(end) = after.find('}') else {
+            rest = after;
+            break;
+        };
+        if before.ends_with('$') {
+            output.push_str(&after[..=end]);
+        } else {
+            output.push_str(&fill(&after[1..end])?);
+        }
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// `target` with the first eight hex digits of the SHA-256 hash of the file `path` appended to
+/// its stem.
+pub(crate) fn hash_suffixed(path: &Path, target: &Path) -> JResult<PathBuf> {
+    let hash = manifest::hash_file(path)?;
+    let mut name = target.file_stem().unwrap_or_default().to_owned();
+    name.push(format!("-{}", &hash[..8]));
+    if let Some(ext) = target.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    Ok(target.with_file_name(name))
+}
+
+/// Check whether an extension filter, which may be `*` or the name of one of the
+/// `EXTENSION_GROUPS`, matches `extension`.
+fn extension_matches(filter: &str, extension: &str) -> bool {
+    if filter == "*" || filter == extension {
+        return true;
+    }
+    EXTENSION_GROUPS
+        .iter()
+        .any(|(group, extensions)| *group == filter && extensions.contains(&extension))
+}
+
+/// Check whether `to` names the same path as `from`, once the directories they are in are
+/// canonicalized.
+fn is_same_place(from: &Path, to: &Path) -> bool {
+    let dir = |path: &Path| {
+        path.parent()
+            .map(|p| {
+                if p.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    p
+                }
+            })
+            .and_then(|p| p.canonicalize().ok())
+    };
+    from.file_name() == to.file_name() && dir(from).is_some_and(|d| Some(d) == dir(to))
+}
+
+/// Check whether a MIME filter like `image/png` or `image/*` matches the MIME type `mime`.
+fn mime_matches(filter: &str, mime: &str) -> bool {
+    match filter.strip_suffix("/*") {
+        Some(category) => mime
+            .split_once('/')
+            .is_some_and(|(c, _)| c.eq_ignore_ascii_case(category)),
+        None => filter.eq_ignore_ascii_case(mime),
+    }
+}
+
+/// The buckets out of `buckets` which `path` fits into, from the best to the worst fitting one.
+///
+/// Buckets which don't take the kind of path, a file or a directory, are left out. The others are
+/// ordered by their priority first and by `tie_break` second.
+///
+/// A bucket which fails to check whether `path` fits, like a `content_predicate` which times
+/// out, fails the whole ranking, so that the path doesn't end up in a worse fitting bucket.
+pub fn rank_buckets<'a>(
+    buckets: impl IntoIterator<Item = &'a Bucket>,
+    path: &Path,
+    is_file: bool,
+    tie_break: TieBreak,
+) -> JResult<Vec<&'a Bucket>> {
+    let mut fitting = Vec::new();
+    for bucket in buckets
+        .into_iter()
+        .filter(|bucket| bucket.target.accepts(is_file))
+    {
+        if bucket.is_fitting(&path)? {
+            fitting.push(bucket);
+        }
+    }
+    sort_by_rank(&mut fitting, tie_break);
+    Ok(fitting)
+}
+
+/// Order `buckets` by their priority first and by `tie_break` second, the best one first.
+pub fn sort_by_rank(buckets: &mut [&Bucket], tie_break: TieBreak) {
+    match tie_break {
+        // The reverse of the order of buckets, so the highest priority and last name come first.
+        TieBreak::HighestPriority => buckets.sort_by(|a, b| b.cmp(a)),
+        TieBreak::FirstDefined => {
+            buckets.sort_by(|a, b| b.priority.cmp(&a.priority).then(a._index.cmp(&b._index)))
+        }
+        TieBreak::Name => {
+            buckets.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.name.cmp(&b.name)))
+        }
+    }
+}
+
+fn default_burst_window_secs() -> u64 {
+    60
+}
+
+fn default_predicate_timeout_secs() -> u64 {
+    10
+}
+
+fn default_separator() -> String {
+    "-".into()
+}
+
+/// Clean up a file name like `My File (1).PDF` into `my-file-1.pdf`.
+///
+/// Each part of the name between dots is normalized on its own: runs of characters which are
+/// neither alphanumeric nor `_` become a single `separator`, which is trimmed from both ends of
+/// the part. Empty parts are dropped, except for the leading one of hidden files. A name which
+/// would end up empty is kept as it is.
+pub fn normalize_name(name: &str, lowercase: bool, separator: &str) -> String {
+    let cased = if lowercase {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    };
+    let parts: Vec<String> = cased
+        .split('.')
+        .enumerate()
+        .filter_map(|(i, part)| {
+            let words: Vec<&str> = part
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|word| !word.is_empty())
+                .collect();
+            if words.is_empty() && i > 0 {
+                return None;
+            }
+            Some(words.join(separator))
+        })
+        .collect();
+    let normalized = parts.join(".");
+    if normalized.trim_matches('.').is_empty() {
+        return name.to_string();
+    }
+    normalized
+}
+
+/// The size of a file or the total size of a directory, or `0` if it can't be determined.
+fn size_of(path: &Path) -> u64 {
+    if path.is_dir() {
+        fs_extra::dir::get_size(path).unwrap_or(0)
+    } else {
+        path.metadata().map_or(0, |meta| meta.len())
+    }
+}
+
+/// Whether deletions have to be confirmed on the terminal, see `set_interactive`.
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Ask on the terminal before deleting anything in buckets with `confirm_deletes`.
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+}
+
+/// Ask the user whether `path` should be deleted. Anything but a yes is a no.
+fn confirm_delete(path: &Path, is_file: bool) -> JResult<bool> {
+    // Workers would otherwise ask at the same time.
+    static PROMPT: Mutex<()> = Mutex::new(());
+    let _prompt = PROMPT.lock().expect("prompt lock poisoned");
+
+    let mut stderr = std::io::stderr().lock();
+    write!(
+        stderr,
+        "Delete {} '{}'? [y/N] ",
+        if is_file { "file" } else { "directory" },
+        path.display()
+    )?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Run a hook command through the shell, replacing `{path}` and `{destination}` in it.
+fn run_hook(hook: &str, path: &Path, destination: &Path) -> JResult {
+    let command = hook
+        .replace("{path}", &shell_quote(path))
+        .replace("{destination}", &shell_quote(destination));
+    log::debug!("running hook: {command}");
+
+    let output = Command::new("sh").arg("-c").arg(&command).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(JError::HookFailed(
+            command,
+            format!("{}, {}", output.status, stderr.trim())
+        ));
+    }
+    Ok(())
+}
+
+/// Deserialize a file mode which is either an integer or a string with an octal number.
+fn deserialize_mode<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Number(u32),
+        Octal(String),
+    }
+
+    let mode = match Mode::deserialize(deserializer)? {
+        Mode::Number(mode) => mode,
+        Mode::Octal(text) => {
+            let digits = text.trim();
+            let digits = digits.strip_prefix("0o").unwrap_or(digits);
+            u32::from_str_radix(digits, 8).map_err(|_| {
+                serde::de::Error::custom(format!(
+                    "invalid mode '{text}', expected an octal number like \"644\""
+                ))
+            })?
+        }
+    };
+    if mode > 0o7777 {
+        return Err(serde::de::Error::custom(format!(
+            "invalid mode {mode:o}, it has more than four octal digits"
+        )));
+    }
+    Ok(Some(mode))
+}
+
+/// The ID of a group given by its name or ID.
+fn group_id(group: &str) -> JResult<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let c_group = CString::new(group)?;
+    let entry = unsafe { libc::getgrnam(c_group.as_ptr()) };
+    if entry.is_null() {
+        bail!("unknown group '{group}'");
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+/// Quote a path so that the shell passes it on as a single argument.
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// How many bytes are available to unprivileged users on the file system of `path`.
+fn available_space(path: &Path) -> JResult<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Move a file or directory to `to`, which is the full destination path.
+///
+/// A rename is attempted first. Renaming does not work across file systems, in which case the
+/// path is copied to the destination and removed afterwards.
+pub fn move_path(from: &Path, to: &Path, is_file: bool) -> JResult {
+    match rename(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => log::debug!(
+            "'{}' and '{}' are on different file systems, falling back to copy and remove",
+            from.display(),
+            to.display()
+        ),
+        Err(e) => return Err(e.into()),
+    }
+
+    copy_path(from, to, is_file)?;
+    if is_file {
+        remove_file(from)?;
+    } else {
+        remove_dir_all(from)?;
+    }
+
+    Ok(())
+}
+
+/// Copy a file or directory to `to`, which is the full destination path, replacing an existing
+/// file at `to`.
+///
+/// Copies of at least `PROGRESS_THRESHOLD` bytes log how far they got every `PROGRESS_INTERVAL`.
+fn copy_path(from: &Path, to: &Path, is_file: bool) -> JResult {
+    let mut progress = (size_of(from) >= PROGRESS_THRESHOLD).then(|| Progress::new(from));
+    if is_file {
+        let options = fs_extra::file::CopyOptions::new().overwrite(true);
+        match progress.as_mut() {
+            Some(progress) => {
+                file_copy_with_progress(from, to, &options, |process| {
+                    progress.update(process.copied_bytes, process.total_bytes)
+                })?;
+            }
+            None => {
+                copy(from, to, &options)?;
+            }
+        }
+    } else {
+        // `to` is the copy itself, not the directory to place it in.
+        let options = fs_extra::dir::CopyOptions::new()
+            .overwrite(true)
+            .content_only(true);
+        match progress.as_mut() {
+            Some(progress) => {
+                dir_copy_with_progress(from, to, &options, |process| {
+                    progress.update(process.copied_bytes, process.total_bytes);
+                    fs_extra::dir::TransitProcessResult::ContinueOrAbort
+                })?;
+            }
+            None => {
+                copy_dir(from, to, &options)?;
+            }
+        }
+    }
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    Ok(())
+}
+
+/// Copies of files and directories of at least this many bytes log their progress.
+const PROGRESS_THRESHOLD: u64 = 100 * 1024 * 1024;
+/// How often the progress of a copy is logged.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The progress of copying a large path, which is logged at most once per `PROGRESS_INTERVAL`.
+struct Progress<'a> {
+    from: &'a Path,
+    started: Instant,
+    logged: Instant,
+}
+
+impl<'a> Progress<'a> {
+    fn new(from: &'a Path) -> Self {
+        let now = Instant::now();
+        Self {
+            from,
+            started: now,
+            logged: now,
+        }
+    }
+
+    fn update(&mut self, copied: u64, total: u64) {
+        if self.logged.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        self.logged = Instant::now();
+        log::info!(
+            "copying '{}': {copied} of {total} bytes ({}%)",
+            self.from.display(),
+            (copied * 100).checked_div(total).unwrap_or(100)
+        );
+    }
+
+    fn finish(self) {
+        log::info!(
+            "copied '{}' in {}s",
+            self.from.display(),
+            self.started.elapsed().as_secs()
+        );
+    }
+}
+
+/// Create a hard or symbolic link, depending on `action`, at `to` which points to `from`,
+/// replacing an existing path at `to`.
+///
+/// Symbolic links point to the absolute path of `from`, so they keep working wherever `to` is.
+/// Directories can't be hard linked.
+fn link_path(from: &Path, to: &Path, action: &Action, is_file: bool) -> JResult {
+    remove_existing(to)?;
+    match action {
+        Action::Hardlink if !is_file => {
+            bail!("can't create a hard link to directory '{}'", from.display())
+        }
+        Action::Hardlink => hard_link(from, to)?,
+        _ => symlink(&std::path::absolute(from)?, to, is_file)?,
+    }
+    Ok(())
+}
+
+/// Create a symbolic link at `to` with the same target as the symbolic link `from`, replacing an
+/// existing path at `to`.
+fn copy_link(from: &Path, to: &Path) -> JResult {
+    remove_existing(to)?;
+    let target = read_link(from)?;
+    // Links to missing paths are created as links to files.
+    let is_file = !metadata(from).is_ok_and(|m| m.is_dir());
+    symlink(&target, to, is_file)?;
+    Ok(())
+}
+
+/// Remove whatever is at `path`, without following symbolic links.
+fn remove_existing(path: &Path) -> JResult {
+    // The override action already decided that an existing path may be replaced.
+    match symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => remove_dir_all(path)?,
+        Ok(_) => remove_file(path)?,
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path, _is_file: bool) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path, is_file: bool) -> std::io::Result<()> {
+    if is_file {
+        std::os::windows::fs::symlink_file(original, link)
+    } else {
+        std::os::windows::fs::symlink_dir(original, link)
+    }
+}
+
+/// Give `to` and, if it's a directory, everything inside of it the times and permissions of the
+/// corresponding paths in `from`.
+fn copy_metadata(from: &Path, to: &Path) -> JResult {
+    let meta = metadata(from)?;
+    if meta.is_dir() {
+        for dentry in from.read_dir()? {
+            let dentry = dentry?;
+            copy_metadata(&dentry.path(), &to.join(dentry.file_name()))?;
+        }
+    }
+
+    // Times are set last, because changing the contents of a directory changes its times too.
+    let times = FileTimes::new()
+        .set_accessed(meta.accessed()?)
+        .set_modified(meta.modified()?);
+    File::open(to)?.set_times(times)?;
+    set_permissions(to, meta.permissions())?;
+    Ok(())
+}
+
+/// Destination paths which actions are currently placing files at.
+///
+/// Actions can run concurrently, so a destination is reserved while its action is running. A
+/// reserved path counts as taken, which keeps two files from ending up at the same path.
+static RESERVED_PATHS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// A reserved destination path, which is released again when dropped.
+struct Reservation(Option<PathBuf>);
+
+impl Reservation {
+    fn path(&self) -> &Path {
+        self.0.as_deref().unwrap_or(Path::new(""))
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            RESERVED_PATHS
+                .lock()
+                .expect("reserved paths lock poisoned")
+                .remove(path);
+        }
     }
 }
@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{batch::Entry, watch_path::WatchPath};
+
+/// The paths which recently fit into buckets with a `burst_threshold`, with when they did, by
+/// bucket name.
+static BURSTS: Mutex<BTreeMap<String, Vec<(Entry, Instant)>>> = Mutex::new(BTreeMap::new());
+
+/// Add `path` to the recent paths of `bucket`, and remove and return all of them once at least
+/// `threshold` paths fit into it within `window`.
+///
+/// Paths which fit into the bucket longer than `window` ago are forgotten, they're left where
+/// they are.
+pub fn add(
+    bucket: &str,
+    threshold: usize,
+    window: Duration,
+    watch_path: &WatchPath,
+    path: PathBuf,
+    is_file: bool,
+) -> Option<Vec<Entry>> {
+    let mut bursts = BURSTS.lock().expect("burst lock poisoned");
+    let recent = bursts.entry(bucket.to_string()).or_default();
+    recent.retain(|((_, p, _), seen)| {
+        let expired = seen.elapsed() > window;
+        if expired {
+            log::debug!(
+                "'{}' is not part of a burst of bin '{bucket}', leaving it",
+                p.display()
+            );
+        }
+        !expired
+    });
+    if !recent.iter().any(|((_, p, _), _)| *p == path) {
+        recent.push(((watch_path.clone(), path, is_file), Instant::now()));
+    }
+    if recent.len() < threshold {
+        log::info!(
+            "bin '{bucket}' has {} of {threshold} paths within {}s, waiting for a burst",
+            recent.len(),
+            window.as_secs()
+        );
+        return None;
+    }
+    bursts
+        .remove(bucket)
+        .map(|recent| recent.into_iter().map(|(entry, _)| entry).collect())
+}
+
+/// Remove and return the paths which are waiting for a burst, by bucket name, leaving out the
+/// ones which fit into their bucket longer than `window_of` it ago.
+pub fn drain(window_of: impl Fn(&str) -> Duration) -> Vec<(String, Vec<Entry>)> {
+    let bursts = std::mem::take(&mut *BURSTS.lock().expect("burst lock poisoned"));
+    bursts
+        .into_iter()
+        .map(|(bucket, recent)| {
+            let window = window_of(&bucket);
+            let recent: Vec<Entry> = recent
+                .into_iter()
+                .filter(|(_, seen)| seen.elapsed() <= window)
+                .map(|(entry, _)| entry)
+                .collect();
+            (bucket, recent)
+        })
+        .filter(|(_, recent)| !recent.is_empty())
+        .collect()
+}
@@ -1,107 +1,1460 @@
-use std::{collections::HashSet, fs::read_to_string, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fs::{create_dir_all, read_to_string},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
 
-use crossbeam::channel::{Receiver, unbounded};
-use notify::{Error, Event, INotifyWatcher, RecursiveMode, Watcher, recommended_watcher};
+use crossbeam::channel::{Receiver, never, unbounded};
+use notify::{
+    Error, Event, EventHandler, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+    WatcherKind, recommended_watcher,
+};
+use regex::Regex;
 use resolve_path::PathResolveExt;
 use serde::Deserialize;
 
-use crate::{JResult, bucket::Bucket, watch_path::WatchPath};
+use anyhow::bail;
 
-pub const CONFIG_PATHS: [&str; 3] = [
+use crate::{
+    JResult, batch,
+    bucket::{Action, Bucket, OverrideAction, TieBreak, sort_by_rank},
+    burst, deadline,
+    errors::{JError, is_retryable},
+    manifest::{self, Fingerprint},
+    sftp::SftpDestination,
+    stats::{self, BucketStats},
+    units,
+    watch_path::{self, EmptyFileAction, RecMode, WatchPath},
+};
+
+/// The profile which is loaded if none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The environment variable which names the configuration file, instead of searching
+/// `CONFIG_PATHS` for it.
+pub const CONFIG_ENV: &str = "JANITORS_CONFIG";
+/// Where the configuration file is looked for, in order. The last one is used if none exists.
+pub const CONFIG_PATHS: [&str; 4] = [
+    "janitors.toml",
     "~/.config/janitors/config.toml",
     "~/.janitors.toml",
     "/etc/janitors/config.toml",
 ];
-type LoadConfigOutput = (Receiver<Result<Event, Error>>, Config, INotifyWatcher);
-type WatcherState = (Receiver<Result<Event, Error>>, WatchPath, INotifyWatcher);
+/// A commented example configuration, which covers every setting.
+///
+/// It lives next to this file and has to be updated together with `Config`, `WatchPath` and
+/// `Bucket`.
+pub const TEMPLATE: &str = include_str!("config.template.toml");
+
+/// The events of the configuration files and their watcher, which is `None` if they aren't
+/// watched, together with the configuration.
+type LoadConfigOutput = (Receiver<Result<Event, Error>>, Config, Option<FileWatcher>);
+/// The event channel of a watch path together with the watch path and its watcher.
+pub type WatcherState = (Receiver<Result<Event, Error>>, WatchPath, FileWatcher);
+
+/// File name suffixes of temporary and partially written files which are never acted upon,
+/// unless `default_ignores` is turned off.
+pub const DEFAULT_IGNORE_SUFFIXES: [&str; 7] = [
+    ".part",
+    ".partial",
+    ".crdownload",
+    ".download",
+    ".tmp",
+    ".swp",
+    "~",
+];
+
+/// Data which is derived from the configuration while it's being loaded.
+///
+/// It's never deserialized and it's ignored when comparing configurations.
+#[derive(Debug, Clone, Default)]
+pub struct Derived<T>(pub T);
+
+impl<T> PartialEq for Derived<T> {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl<T> Eq for Derived<T> {}
+
+/// Command line options which change how a configuration is loaded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Turn configuration warnings into load failures.
+    pub strict: bool,
+    /// Poll watch paths in this interval, in milliseconds, unless they set their own.
+    pub poll_interval_ms: Option<u64>,
+    /// Replaces the `once_per_ms` of the configuration.
+    pub once_per_ms: Option<u64>,
+    /// Turns on `skip_open_files`.
+    pub skip_open_files: bool,
+    /// Replaces the `workers` of the configuration.
+    pub jobs: Option<usize>,
+    /// Don't watch the configuration files, so the receiver of `load` never gets an event.
+    pub no_reload: bool,
+    /// The profile whose watch paths and buckets are loaded, see `Config::select_profile`.
+    pub profile: Option<String>,
+}
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default)]
 pub struct Config {
+    /// Other configuration files whose watch paths and buckets are merged into this one.
+    ///
+    /// Relative paths are resolved against the directory of the including file.
+    pub include: Vec<PathBuf>,
     pub watch: Vec<WatchPath>,
     pub bucket: Vec<Bucket>,
+    /// Named sets of watch paths and buckets, of which only one is loaded in addition to the
+    /// top-level ones.
+    pub profiles: BTreeMap<String, Profile>,
+    /// Filters which buckets share by listing the name of the set in `use_filter_sets`.
+    pub filter_set: Vec<FilterSet>,
+    /// Groups of extensions, by name, which extension filters can name like the built-in
+    /// `EXTENSION_GROUPS`. They take precedence over built-in groups of the same name.
+    pub extension_groups: BTreeMap<String, Vec<String>>,
+    /// How long a new path has to stay quiet, in milliseconds, before it's handled.
+    pub debounce_ms: u64,
+    /// How long, in milliseconds, the watchers which were added by a reload ignore their events.
+    pub reload_grace_ms: u64,
+    /// How long a path's size and modification time have to stay unchanged, in milliseconds,
+    /// before an action is applied to it.
+    pub stable_wait_ms: u64,
+    /// How long, in milliseconds, a path is waited for to become stable at most, before it's
+    /// tried again later.
+    pub stable_max_wait_ms: u64,
+    /// How long, in milliseconds, a path which was handled is ignored afterwards, so that
+    /// duplicate events for it are not acted upon twice. `0` turns this off.
+    pub once_per_ms: u64,
+    /// How many paths are handled in parallel.
+    pub workers: usize,
+    /// Whether files ending in one of the `DEFAULT_IGNORE_SUFFIXES` are ignored.
+    pub default_ignores: bool,
+    /// File name suffixes of files which are ignored by all watch paths.
+    pub ignore_suffixes: Vec<String>,
+    /// Regular expressions matching file names which are ignored by all watch paths.
+    pub ignore_patterns: Vec<String>,
+    /// Whether a desktop notification is shown after a path was put into a bucket, unless the
+    /// bucket says otherwise.
+    pub notify_desktop: bool,
+    /// How often, in minutes, the statistics of all buckets are logged while watching. `0` turns
+    /// the periodic summary off.
+    pub stats_interval_minutes: u64,
+    /// How often, in minutes, every watch path is scanned as a whole while watching, to catch
+    /// paths whose events were lost. `0` turns periodic scans off.
+    pub rescan_interval_minutes: u64,
+    /// How often, in minutes, maintenance runs happen while watching. `0` turns periodic
+    /// maintenance off.
+    pub maintain_interval_minutes: u64,
+    /// How often, in seconds, janitors tries to watch the watch paths of disconnected watchers
+    /// again, e.g. after a removable drive was plugged back in. `0` turns reconnecting off.
+    pub reconnect_interval_secs: u64,
+    /// After how many minutes without any event for a watch path a warning is logged, since its
+    /// watcher may have stopped working. `0` turns the warning off.
+    pub idle_warning_minutes: u64,
+    /// How often a failed action is retried, if the error looks like it's temporary.
+    pub max_retries: u32,
+    /// How long to wait, in milliseconds, before the first retry. Every following retry waits
+    /// twice as long as the one before it.
+    pub retry_delay_ms: u64,
+    /// A file which every move and copy is recorded in, so that they can be undone.
+    pub journal: Option<PathBuf>,
+    /// A file which records the name and a hash of the contents of every file that was put into
+    /// a bucket, so that files which show up again with the same name and contents are skipped.
+    pub manifest: Option<PathBuf>,
+    /// Files larger than this are neither looked up in nor recorded in the `manifest`, so that
+    /// huge files don't have to be hashed. `0` means any size.
+    #[serde(deserialize_with = "units::deserialize_size")]
+    pub manifest_max_size: u64,
+    /// A file which the paths that are waiting to be handled are kept in, so that they are
+    /// handled after a restart as well.
+    pub pending_file: Option<PathBuf>,
+    /// Watch paths by scanning them in this interval, in milliseconds, instead of waiting for
+    /// events of the operating system, which network file systems like NFS or SMB don't send.
+    pub poll_interval_ms: Option<u64>,
+    /// Whether watch paths which don't exist are created instead of being skipped until they
+    /// exist.
+    pub create_watch_dirs: bool,
+    /// Which bucket wins if a path fits into several buckets with the same priority.
+    pub tie_break: TieBreak,
+    /// Whether scans, like the ones of one-shot mode, leave out files which some process has
+    /// open for writing, and directories containing such files. Only supported on Linux.
+    pub skip_open_files: bool,
+    /// What happens to empty files of watch paths which don't set an `empty_file_action` of
+    /// their own.
+    pub empty_file_action: Option<EmptyFileAction>,
+    /// The `action` of buckets which don't set one of their own.
+    pub default_action: Option<Action>,
+    /// The `override_action` of buckets which don't set one of their own.
+    pub default_override_action: Option<OverrideAction>,
+    /// Whether one-shot runs only preview their actions, as long as some watch path has never
+    /// had a plan of a preview applied.
+    pub confirm_first_run: bool,
+    /// Where previews write their plan. Defaults to `plan.jsonl` in the state directory of the
+    /// user, see `plan_file()`.
+    pub plan_file: Option<PathBuf>,
+    #[serde(skip)]
+    pub _ignore_regexes: Derived<Vec<Regex>>,
+    /// The configuration file and every file it includes, as loaded by `load`.
+    #[serde(skip)]
+    pub _files: Derived<Vec<PathBuf>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            watch: Vec::new(),
+            bucket: Vec::new(),
+            profiles: BTreeMap::new(),
+            filter_set: Vec::new(),
+            extension_groups: BTreeMap::new(),
+            debounce_ms: 0,
+            reload_grace_ms: 0,
+            stable_wait_ms: 0,
+            stable_max_wait_ms: 30_000,
+            once_per_ms: 0,
+            workers: 1,
+            default_ignores: true,
+            ignore_suffixes: Vec::new(),
+            ignore_patterns: Vec::new(),
+            notify_desktop: false,
+            stats_interval_minutes: 0,
+            rescan_interval_minutes: 0,
+            maintain_interval_minutes: 0,
+            reconnect_interval_secs: 30,
+            idle_warning_minutes: 0,
+            max_retries: 0,
+            retry_delay_ms: 500,
+            journal: None,
+            manifest: None,
+            manifest_max_size: 1 << 30,
+            pending_file: None,
+            poll_interval_ms: None,
+            create_watch_dirs: false,
+            tie_break: TieBreak::default(),
+            skip_open_files: false,
+            empty_file_action: None,
+            default_action: None,
+            default_override_action: None,
+            confirm_first_run: false,
+            plan_file: None,
+            _ignore_regexes: Derived::default(),
+            _files: Derived::default(),
+        }
+    }
+}
+
+/// Watch paths and buckets which are only loaded if their profile is selected.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    #[serde(default)]
+    pub watch: Vec<WatchPath>,
+    #[serde(default)]
+    pub bucket: Vec<Bucket>,
+}
+
+/// Extension and name filters which are added to every bucket that uses the set.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct FilterSet {
+    pub name: String,
+    #[serde(default)]
+    pub extension_filters: Vec<String>,
+    #[serde(default)]
+    pub name_filters: Vec<String>,
+}
+
+/// A file watcher which either gets events from the operating system or polls for changes.
+#[derive(Debug)]
+pub enum FileWatcher {
+    Native(RecommendedWatcher),
+    Poll(PollWatcher),
+}
+
+impl FileWatcher {
+    /// Create a watcher which sends its events to `handler`, like the sender of a channel, and
+    /// which polls every `poll_interval_ms` milliseconds if set.
+    pub(crate) fn new(handler: impl EventHandler, poll_interval_ms: Option<u64>) -> JResult<Self> {
+        Ok(match poll_interval_ms {
+            Some(ms) => Self::Poll(PollWatcher::new(
+                handler,
+                notify::Config::default().with_poll_interval(Duration::from_millis(ms)),
+            )?),
+            None => Self::Native(recommended_watcher(handler)?),
+        })
+    }
+
+    pub(crate) fn watch(
+        &mut self,
+        path: &Path,
+        recursive_mode: RecursiveMode,
+    ) -> notify::Result<()> {
+        match self {
+            Self::Native(watcher) => watcher.watch(path, recursive_mode),
+            Self::Poll(watcher) => watcher.watch(path, recursive_mode),
+        }
+    }
+
+    /// The notify backend of the watcher.
+    pub(crate) fn kind(&self) -> WatcherKind {
+        match self {
+            Self::Native(_) => RecommendedWatcher::kind(),
+            Self::Poll(_) => PollWatcher::kind(),
+        }
+    }
+}
+
+/// Whether the notify backend `kind` watches whole directory trees by itself. Other backends
+/// add a watch for every subdirectory, or poll the tree.
+pub(crate) fn natively_recursive(kind: WatcherKind) -> bool {
+    matches!(
+        kind,
+        WatcherKind::Fsevent | WatcherKind::ReadDirectoryChangesWatcher
+    )
+}
+
+pub(crate) fn default_true() -> bool {
+    true
 }
 
 impl Config {
-    pub fn load(file_path: &str) -> JResult<LoadConfigOutput> {
-        let resolved_path = file_path.resolve();
-        let config_str = read_to_string(&resolved_path)?;
+    pub fn load(file_path: &str, options: &Options) -> JResult<LoadConfigOutput> {
+        let resolved_path = PathBuf::from(file_path.resolve());
+        let mut config_files = Vec::new();
+        let mut config = Self::read(&resolved_path, &mut config_files)?;
+        if options.poll_interval_ms.is_some() {
+            config.poll_interval_ms = options.poll_interval_ms;
+        }
+        if let Some(once_per_ms) = options.once_per_ms {
+            config.once_per_ms = once_per_ms;
+        }
+        config.skip_open_files |= options.skip_open_files;
+        if let Some(jobs) = options.jobs {
+            config.workers = jobs;
+        }
+        config.select_profile(options.profile.as_deref())?;
+        config.fill_bucket_defaults();
+        config.init()?;
+        config.validate(options)?;
+        config._files.0 = config_files.clone();
+
+        if options.no_reload {
+            return Ok((never(), config, None));
+        }
+        let (tx, rx) = unbounded();
+        let mut watcher = FileWatcher::new(tx, config.poll_interval_ms)?;
+        // Included files are watched as well, so that editing any of them reloads the config.
+        for path in config_files.iter() {
+            watcher.watch(path, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok((rx, config, Some(watcher)))
+    }
+
+    /// Put the paths waiting in the batches of buckets into them, if the batch was started at
+    /// least `batch_flush_minutes` ago or if `all` is set.
+    pub fn flush_batches(&self, all: bool) -> JResult {
+        let buckets: Vec<&Bucket> = self
+            .bucket
+            .iter()
+            .chain(self.watch.iter().flat_map(|w| w.buckets.iter()))
+            .collect();
+        let find = |name: &str| buckets.iter().copied().find(|b| b.name == name);
+        let overdue = batch::overdue(|name| {
+            if all {
+                return Some(Duration::ZERO);
+            }
+            let minutes = find(name)?.batch_flush_minutes?;
+            Some(Duration::from_secs(minutes * 60))
+        });
+
+        let mut results = Vec::new();
+        for (name, paths) in overdue {
+            let Some(bucket) = find(&name) else {
+                log::warn!(
+                    "bin '{name}' was removed, leaving its {} batched paths alone",
+                    paths.len()
+                );
+                continue;
+            };
+            results.push((
+                bucket.destination.clone(),
+                watch_path::apply_batch(bucket, paths, self),
+            ));
+        }
+        JError::combine(results)
+    }
+
+    /// Put the paths which are waiting for a burst into their buckets, as if the burst had
+    /// happened, which is done when janitors shuts down.
+    pub fn flush_bursts(&self) -> JResult {
+        let buckets: Vec<&Bucket> = self
+            .bucket
+            .iter()
+            .chain(self.watch.iter().flat_map(|w| w.buckets.iter()))
+            .collect();
+        let find = |name: &str| buckets.iter().copied().find(|b| b.name == name);
+        let pending = burst::drain(|name| {
+            find(name).map_or(Duration::ZERO, |b| Duration::from_secs(b.burst_window_secs))
+        });
+
+        let mut results = Vec::new();
+        for (name, paths) in pending {
+            let Some(bucket) = find(&name) else {
+                log::warn!(
+                    "bin '{name}' was removed, leaving its {} paths waiting for a burst alone",
+                    paths.len()
+                );
+                continue;
+            };
+            log::info!(
+                "bin '{name}' didn't see a burst, handling its {} recent paths before shutting down",
+                paths.len()
+            );
+            results.push((
+                bucket.destination.clone(),
+                watch_path::apply_batch(bucket, paths, self),
+            ));
+        }
+        JError::combine(results)
+    }
+
+    /// Add the watch paths and buckets of the profile `name` to the top-level ones and forget
+    /// the other profiles.
+    ///
+    /// Without a name, the profile called `default` is used if there is one, and otherwise only
+    /// the top-level watch paths and buckets are.
+    pub fn select_profile(&mut self, name: Option<&str>) -> JResult {
+        let mut profiles = std::mem::take(&mut self.profiles);
+        let name = match name {
+            Some(name) => name,
+            None if profiles.contains_key(DEFAULT_PROFILE) => DEFAULT_PROFILE,
+            None => return Ok(()),
+        };
+        let Some(profile) = profiles.remove(name) else {
+            bail!(JError::UnknownProfile(
+                name.to_string(),
+                profiles.into_keys().collect()
+            ))
+        };
+        log::debug!("loading profile '{name}'");
+        self.watch.extend(profile.watch);
+        self.bucket.extend(profile.bucket);
+        Ok(())
+    }
+
+    /// Give every bucket which doesn't set its `action` or `override_action` the
+    /// `default_action` and `default_override_action`, if there are ones.
+    pub fn fill_bucket_defaults(&mut self) {
+        let inline = self.watch.iter_mut().flat_map(|w| w.buckets.iter_mut());
+        for bucket in self.bucket.iter_mut().chain(inline) {
+            if bucket._action.is_none()
+                && let Some(action) = &self.default_action
+            {
+                bucket.action = action.clone();
+            }
+            if bucket._override_action.is_none()
+                && let Some(override_action) = &self.default_override_action
+            {
+                bucket.override_action = override_action.clone();
+            }
+        }
+    }
+
+    /// Split watch paths with multiple paths, then resolve paths, add the filter sets to the
+    /// buckets using them and compile the matchers of the configuration and its buckets.
+    pub fn init(&mut self) -> JResult {
+        let mut watch = Vec::with_capacity(self.watch.len());
+        for w in self.watch.iter() {
+            watch.extend(w.clone().split()?);
+        }
+        self.watch = watch;
+
+        for w in self.watch.iter_mut() {
+            w.path = expand_env(&w.path)?;
+            if let Some(destination) = w.default_destination.as_mut() {
+                *destination = expand_env(destination)?;
+            }
+            if w.poll_interval_ms.is_none() {
+                w.poll_interval_ms = self.poll_interval_ms;
+            }
+            if w.empty_file_action.is_none() {
+                w.empty_file_action = self.empty_file_action;
+            }
+            w.init()?;
+        }
+        let journal = match &self.journal {
+            Some(journal) => Some(PathBuf::from(expand_env(journal)?.resolve())),
+            None => None,
+        };
+        if let Some(file) = self.pending_file.as_mut() {
+            *file = expand_env(file)?.resolve().into();
+        }
+        if let Some(file) = self.manifest.as_mut() {
+            *file = expand_env(file)?.resolve().into();
+        }
+        if let Some(file) = self.plan_file.as_mut() {
+            *file = expand_env(file)?.resolve().into();
+        }
+        let inline = self.watch.iter_mut().flat_map(|w| {
+            let path = w.path.display().to_string();
+            w.buckets.iter_mut().enumerate().map(move |(i, b)| {
+                if b.name.is_empty() {
+                    b.name = format!("{path}#{}", i + 1);
+                }
+                b
+            })
+        });
+        // Name filters of every bucket are compiled before failing, to report all invalid ones.
+        let mut invalid = Vec::new();
+        for (index, b) in self.bucket.iter_mut().chain(inline).enumerate() {
+            b._index = index;
+            for set_name in b.use_filter_sets.iter() {
+                let Some(set) = self.filter_set.iter().find(|set| &set.name == set_name) else {
+                    bail!(JError::UnknownFilterSet(b.name.clone(), set_name.clone()));
+                };
+                // Initializing twice must not add the filters twice.
+                for extension in set.extension_filters.iter() {
+                    if !b.extension_filters.contains(extension) {
+                        b.extension_filters.push(extension.clone());
+                    }
+                }
+                for filter in set.name_filters.iter() {
+                    if !b.name_filters.contains(filter) {
+                        b.name_filters.push(filter.clone());
+                    }
+                }
+            }
+            // Groups are replaced by their extensions, so the built-in groups don't see them.
+            let extension_filters = std::mem::take(&mut b.extension_filters);
+            for filter in extension_filters {
+                match self.extension_groups.get(&filter) {
+                    Some(extensions) => b.extension_filters.extend(extensions.iter().cloned()),
+                    None => b.extension_filters.push(filter),
+                }
+            }
+            // Named capture groups of the name filters may be used in the destination.
+            let regexes: Vec<Regex> = b
+                .name_filters
+                .iter()
+                .filter_map(|filter| Regex::new(filter).ok())
+                .collect();
+            let groups: Vec<&str> = regexes
+                .iter()
+                .flat_map(|r| r.capture_names().flatten())
+                .collect();
+            b.destination = expand_env_keeping(&b.destination, &groups)?;
+            b._journal = journal.clone();
+            b.notify_desktop.get_or_insert(self.notify_desktop);
+            if let Err(e) = b.init() {
+                match e.downcast::<JError>() {
+                    Ok(JError::RegexCompile(name, filter, e)) => invalid.push((name, filter, e)),
+                    Ok(JError::InvalidNameFilters(filters)) => invalid.extend(filters),
+                    Ok(e) => bail!(e),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if !invalid.is_empty() {
+            bail!(JError::InvalidNameFilters(invalid));
+        }
+
+        self._ignore_regexes.0.clear();
+        for pattern in self.ignore_patterns.iter() {
+            self._ignore_regexes.0.push(Regex::new(pattern)?);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether a path is a temporary or otherwise ignored file, which should never be
+    /// placed into any bucket.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Some(fname) = path.file_name().and_then(|f| f.to_str()) else {
+            return false;
+        };
+        let default_suffixes = DEFAULT_IGNORE_SUFFIXES
+            .iter()
+            .filter(|_| self.default_ignores)
+            .copied();
+
+        default_suffixes
+            .chain(self.ignore_suffixes.iter().map(String::as_str))
+            .any(|suffix| fname.ends_with(suffix))
+            || self._ignore_regexes.0.iter().any(|r| r.is_match(fname))
+    }
+
+    /// Make sure that deleting `path` doesn't remove a watch path or a bucket destination.
+    pub fn ensure_deletable(&self, path: &Path) -> JResult {
+        let path = path.resolve();
+        let protected = self
+            .watch
+            .iter()
+            .map(|w| w.path.resolve().into_owned())
+            .chain(
+                self.bucket
+                    .iter()
+                    .chain(self.watch.iter().flat_map(|w| w.buckets.iter()))
+                    .filter(|b| !matches!(b.action, Action::Delete))
+                    .map(|b| b.destination_root().resolve().into_owned()),
+            );
+        for protected in protected {
+            if protected.starts_with(&path) {
+                bail!(JError::ProtectedPath(path.into()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The local destinations of the buckets of `watch_path` and its default destination, which
+    /// lie inside of the watch path.
+    pub fn destinations_inside(&self, watch_path: &WatchPath) -> Vec<PathBuf> {
+        let root = PathBuf::from(watch_path.path.resolve());
+        let destinations = watch_path
+            .buckets(self)
+            .filter(|b| {
+                matches!(b.action, Action::Move | Action::Copy | Action::Extract)
+                    || b.action.links()
+            })
+            .filter(|b| SftpDestination::parse(&b.destination).is_none())
+            .map(|b| b.destination_root())
+            .chain(watch_path.default_destination.clone());
+        destinations
+            .filter(|d| !d.as_os_str().is_empty())
+            .map(|d| PathBuf::from(d.resolve()))
+            .filter(|d| d.starts_with(&root) && *d != root)
+            .collect()
+    }
+
+    /// Check whether `path` is inside of one of the `destinations_inside` of `watch_path`, which
+    /// means that it was put there and must not be handled again.
+    pub fn is_inside_destination(&self, watch_path: &WatchPath, path: &Path) -> bool {
+        let path = path.resolve();
+        self.destinations_inside(watch_path)
+            .iter()
+            .any(|destination| path.starts_with(destination))
+    }
+
+    /// Check the merged configuration for mistakes which would make bucket selection ambiguous.
+    fn validate(&self, options: &Options) -> JResult {
+        let mut names = HashSet::new();
+        for bucket in self.bucket.iter() {
+            if bucket.name.is_empty() {
+                bail!(JError::MissingValue("name of a bin".into()));
+            }
+            if !names.insert(&bucket.name) {
+                bail!(JError::DuplicateBucket(bucket.name.clone()));
+            }
+        }
+        let inline = self.watch.iter().flat_map(|w| w.buckets.iter());
+        for bucket in self.bucket.iter().chain(inline) {
+            if bucket.needs_destination() && bucket.destination.as_os_str().is_empty() {
+                bail!(JError::MissingValue(format!(
+                    "destination of bin '{}'",
+                    bucket.name
+                )));
+            }
+        }
+        for watch_path in self.watch.iter() {
+            if watch_path.path.as_os_str().is_empty() {
+                bail!(JError::MissingValue("path of a watch path".into()));
+            }
+        }
+        // Inline buckets only have to be unique among the buckets of their watch path.
+        for watch_path in self.watch.iter() {
+            let mut scope: HashSet<&String> = watch_path.bucket_names.iter().collect();
+            for bucket in watch_path.buckets.iter() {
+                if !scope.insert(&bucket.name) {
+                    bail!(JError::DuplicateBucket(bucket.name.clone()));
+                }
+            }
+        }
+
+        for watch_path in self.watch.iter() {
+            for name in watch_path.bucket_names.iter() {
+                if names.contains(name) {
+                    continue;
+                }
+                let err = JError::UnknownBucket(watch_path.path.clone(), name.clone());
+                if options.strict {
+                    bail!(err);
+                }
+                log::warn!("{err}");
+            }
+        }
+
+        // The ranking silently decides which of such buckets gets the paths which fit into both.
+        let mut reported = HashSet::new();
+        for watch_path in self.enabled_watch_paths() {
+            if watch_path.apply_all_matching {
+                continue;
+            }
+            let buckets: Vec<Cow<Bucket>> = watch_path
+                .prioritized_buckets(self)
+                .filter(|bucket| bucket.enabled)
+                .collect();
+            for (i, a) in buckets.iter().enumerate() {
+                for b in buckets[i + 1..].iter() {
+                    if a.destination == b.destination {
+                        continue;
+                    }
+                    let Some(filter) = a.shared_filter(b) else {
+                        continue;
+                    };
+                    let mut ranked = [a.as_ref(), b.as_ref()];
+                    sort_by_rank(&mut ranked, self.tie_break);
+                    let [winner, loser] = ranked;
+                    if !reported.insert((winner.name.clone(), loser.name.clone())) {
+                        continue;
+                    }
+                    let err =
+                        JError::OverlappingBuckets(winner.name.clone(), loser.name.clone(), filter);
+                    if options.strict {
+                        bail!(err);
+                    }
+                    log::warn!("{err}");
+                }
+            }
+        }
+
+        // Paths put into such a destination are skipped, but they'd cause an event each.
+        for watch_path in self.enabled_watch_paths() {
+            if watch_path.recursive_mode == RecMode::NonRecursive {
+                continue;
+            }
+            for destination in self.destinations_inside(watch_path) {
+                let err = JError::DestinationInsideWatchPath(destination, watch_path.path.clone());
+                if options.strict {
+                    bail!(err);
+                }
+                log::warn!("{err}");
+            }
+        }
 
+        for watch_path in self.enabled_watch_paths() {
+            if !self.create_watch_dirs && !watch_path.path.resolve().exists() {
+                let err = JError::MissingWatchPath(watch_path.path.clone());
+                if options.strict {
+                    bail!(err);
+                }
+                log::warn!("{err}, it's skipped until it exists");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a single configuration file and merge all of its includes into it.
+    ///
+    /// Every file that was read is appended to `config_files`, which is also used to detect
+    /// include cycles.
+    fn read(path: &Path, config_files: &mut Vec<PathBuf>) -> JResult<Config> {
+        let config_str = read_to_string(path)?;
         let mut config: Config = toml::from_str(&config_str)?;
+        config_files.push(path.to_owned());
+
+        for include in config.include.iter() {
+            let include_path = PathBuf::from(expand_env(include)?.resolve_in(path));
+            if config_files.contains(&include_path) {
+                bail!(
+                    "config file '{}' is included more than once",
+                    include_path.display()
+                );
+            }
 
-        for b in config.bucket.iter_mut() {
-            b.init()?;
+            let included = Self::read(&include_path, config_files)?;
+            config.bucket.extend(included.bucket);
+            config.watch.extend(included.watch);
+            config.filter_set.extend(included.filter_set);
+            for (name, profile) in included.profiles {
+                let merged = config.profiles.entry(name).or_default();
+                merged.watch.extend(profile.watch);
+                merged.bucket.extend(profile.bucket);
+            }
+            // Groups of the including file win over the ones of the included file.
+            for (name, extensions) in included.extension_groups {
+                config.extension_groups.entry(name).or_insert(extensions);
+            }
         }
 
-        let (tx, rx) = unbounded();
-        let mut watcher = recommended_watcher(tx)?;
-        watcher.watch(&PathBuf::from(resolved_path), RecursiveMode::NonRecursive)?;
+        Ok(config)
+    }
+
+    /// The statistics of every bucket of the configuration, by bucket name.
+    pub fn stats(&self) -> BTreeMap<String, BucketStats> {
+        self.bucket
+            .iter()
+            .map(|bucket| (bucket.name.clone(), stats::get(&bucket.name)))
+            .collect()
+    }
+
+    /// The fingerprint of `path` in the `manifest`, if there is a manifest and `path` is a file
+    /// which is small enough to be hashed.
+    pub fn manifest_fingerprint(&self, path: &Path, is_file: bool) -> Option<Fingerprint> {
+        self.manifest.as_ref()?;
+        if !is_file {
+            return None;
+        }
+        manifest::fingerprint(path, self.manifest_max_size)
+    }
+
+    /// Where previews write their plan: the `plan_file`, or else `janitors/plan.jsonl` in the
+    /// state directory of the user, like `~/.local/state` on Linux.
+    pub fn plan_file(&self) -> PathBuf {
+        match &self.plan_file {
+            Some(file) => file.clone(),
+            None => dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .unwrap_or_else(std::env::temp_dir)
+                .join("janitors/plan.jsonl"),
+        }
+    }
+
+    /// The file which lists the watch paths that had a plan applied, next to the `plan_file()`.
+    pub fn reviewed_file(&self) -> PathBuf {
+        self.plan_file().with_extension("reviewed")
+    }
+
+    /// How often the statistics are logged, if at all.
+    pub fn stats_interval(&self) -> Option<Duration> {
+        (self.stats_interval_minutes > 0)
+            .then(|| Duration::from_secs(self.stats_interval_minutes * 60))
+    }
+
+    /// How long a watch path may go without events before a warning is logged, if at all.
+    pub fn idle_warning(&self) -> Option<Duration> {
+        (self.idle_warning_minutes > 0).then(|| Duration::from_secs(self.idle_warning_minutes * 60))
+    }
+
+    /// How often all watch paths are scanned, if at all.
+    pub fn rescan_interval(&self) -> Option<Duration> {
+        (self.rescan_interval_minutes > 0)
+            .then(|| Duration::from_secs(self.rescan_interval_minutes * 60))
+    }
+
+    /// How long a handled path is ignored afterwards, if at all.
+    pub fn once_per(&self) -> Option<Duration> {
+        (self.once_per_ms > 0).then(|| Duration::from_millis(self.once_per_ms))
+    }
+
+    /// How often disconnected watchers are re-established, if at all.
+    pub fn reconnect_interval(&self) -> Option<Duration> {
+        (self.reconnect_interval_secs > 0)
+            .then(|| Duration::from_secs(self.reconnect_interval_secs))
+    }
+
+    /// How often maintenance runs happen, if at all.
+    pub fn maintain_interval(&self) -> Option<Duration> {
+        (self.maintain_interval_minutes > 0)
+            .then(|| Duration::from_secs(self.maintain_interval_minutes * 60))
+    }
 
-        Ok((rx, config, watcher))
+    /// Run `f` until it succeeds, fails with a permanent error or runs out of retries.
+    ///
+    /// `what` describes the operation in the log message of every retry.
+    pub fn with_retries<T>(&self, what: &str, mut f: impl FnMut() -> JResult<T>) -> JResult<T> {
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < self.max_retries && is_retryable(&e) => {
+                    let delay = Duration::from_millis(self.retry_delay_ms)
+                        .saturating_mul(2u32.saturating_pow(attempt));
+                    attempt += 1;
+                    log::warn!(
+                        "{what} failed: {e}; retrying in {delay:?} (attempt {attempt} of {})",
+                        self.max_retries
+                    );
+                    std::thread::sleep(delay);
+                }
+                res => return res,
+            }
+        }
     }
 
+    /// The debounce window for newly created paths.
+    pub fn debounce_window(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+
+    /// How long the watchers which were added by a reload ignore their events.
+    pub fn reload_grace(&self) -> Duration {
+        Duration::from_millis(self.reload_grace_ms)
+    }
+
+    /// Bring the file watchers in line with the watch paths of the configuration.
+    ///
+    /// Watchers of watch paths whose path and recursive mode did not change are kept along with
+    /// their channels, so that no events are lost for them. Only watchers of new or changed watch
+    /// paths are created and watchers of watch paths which are gone are dropped. Watch paths
+    /// which don't exist are created with `create_watch_dirs`, otherwise their watchers start out
+    /// disconnected and `reconnect_watchers` picks them up once they exist.
+    ///
+    /// All new watchers are created before any of the running ones is touched, so if creating
+    /// one of them fails, `watchers` and `remove_indecies` are left as they were.
+    ///
+    /// Returns the indices of the watchers which were added and are watching.
     pub fn setup_watchers(
         &self,
         watchers: &mut Vec<WatcherState>,
         remove_indecies: &mut HashSet<usize>,
-    ) -> JResult<()> {
-        watchers.clear();
-        remove_indecies.clear();
+    ) -> JResult<Vec<usize>> {
+        /// What becomes of the watcher of a single watch path.
+        enum Planned {
+            Keep(usize),
+            Add(Receiver<Result<Event, Error>>, FileWatcher),
+            /// The watch path doesn't exist, so the watcher starts out disconnected.
+            Missing(Receiver<Result<Event, Error>>, FileWatcher),
+        }
+
+        let native = RecommendedWatcher::kind();
+        // The backend never changes, so only the first setup reports it at the info level.
+        let level = if watchers.is_empty() {
+            log::Level::Info
+        } else {
+            log::Level::Debug
+        };
+        log::log!(
+            level,
+            "notify backend: {native:?}, recursive watching is {}",
+            if natively_recursive(native) {
+                "native"
+            } else {
+                "emulated"
+            }
+        );
+
+        let mut claimed = HashSet::new();
+        let mut planned = Vec::new();
+        for watch_path in self.enabled_watch_paths() {
+            // Disconnected watchers are never reused.
+            let existing = watchers.iter().enumerate().position(|(idx, (_, w, _))| {
+                !remove_indecies.contains(&idx)
+                    && !claimed.contains(&idx)
+                    && w.path.resolve() == watch_path.path.resolve()
+                    && w.recursive_mode == watch_path.recursive_mode
+                    && w.poll_interval_ms == watch_path.poll_interval_ms
+            });
+            if let Some(idx) = existing {
+                claimed.insert(idx);
+                planned.push((watch_path, Planned::Keep(idx)));
+                continue;
+            }
 
-        for watch_path in self.watch.iter() {
             let (tx, rx) = unbounded();
-            let mut watcher = recommended_watcher(tx)?;
-            watcher.watch(&watch_path.path.resolve(), watch_path.recursive_mode.into())?;
+            let mut watcher = FileWatcher::new(tx, watch_path.poll_interval_ms)?;
+            if RecursiveMode::from(watch_path.recursive_mode) == RecursiveMode::Recursive
+                && watcher.kind() == WatcherKind::PollWatcher
+            {
+                log::warn!(
+                    "watch path '{}' is recursive, but its whole tree is polled for changes",
+                    watch_path.path.display()
+                );
+            }
+            let path = watch_path.path.resolve();
+            if !path.exists() && self.create_watch_dirs {
+                log::info!("creating watch path '{}'", watch_path.path.display());
+                create_dir_all(&path)?;
+            } else if !path.exists() {
+                log::debug!(
+                    "watch path '{}' does not exist, it's watched once it exists",
+                    watch_path.path.display()
+                );
+                planned.push((watch_path, Planned::Missing(rx, watcher)));
+                continue;
+            }
+            watcher.watch(&path, watch_path.recursive_mode.into())?;
+            planned.push((watch_path, Planned::Add(rx, watcher)));
+        }
 
+        let mut old: Vec<Option<WatcherState>> = watchers.drain(..).map(Some).collect();
+        remove_indecies.clear();
+        let (mut kept, mut missing) = (0, 0);
+        let mut added = Vec::new();
+        for (watch_path, plan) in planned {
             // If the watcher gets dropped the channel closes, so we have to return it here.
-            watchers.push((rx, watch_path.clone(), watcher));
+            match plan {
+                Planned::Keep(idx) => {
+                    let (rx, _, watcher) = old[idx].take().expect("watcher is kept twice");
+                    watchers.push((rx, watch_path.clone(), watcher));
+                    kept += 1;
+                }
+                Planned::Add(rx, watcher) => {
+                    added.push(watchers.len());
+                    watchers.push((rx, watch_path.clone(), watcher));
+                }
+                Planned::Missing(rx, watcher) => {
+                    remove_indecies.insert(watchers.len());
+                    watchers.push((rx, watch_path.clone(), watcher));
+                    missing += 1;
+                }
+            }
         }
 
-        Ok(())
+        let removed = old.iter().flatten().count();
+        log::debug!(
+            "file watchers: {kept} kept, {} added, {missing} missing, {removed} removed",
+            added.len()
+        );
+        Ok(added)
+    }
+
+    /// Watch the watch paths of disconnected watchers again, if they exist again, and treat
+    /// watchers whose watch path disappeared as disconnected.
+    ///
+    /// Returns the indices of the watchers which were re-established.
+    pub fn reconnect_watchers(
+        &self,
+        watchers: &mut [WatcherState],
+        remove_indecies: &mut HashSet<usize>,
+    ) -> Vec<usize> {
+        for (idx, (_, watch_path, _)) in watchers.iter().enumerate() {
+            if !remove_indecies.contains(&idx) && !watch_path.path.resolve().exists() {
+                log::warn!(
+                    "watch path '{}' disappeared, it's watched again once it exists",
+                    watch_path.path.display()
+                );
+                remove_indecies.insert(idx);
+            }
+        }
+
+        let mut reconnected = Vec::new();
+        for idx in remove_indecies.iter().copied() {
+            let (_, watch_path, _) = &watchers[idx];
+            let path = watch_path.path.resolve();
+            if !path.exists() {
+                continue;
+            }
+            log::debug!("trying to watch '{}' again", watch_path.path.display());
+            let (tx, rx) = unbounded();
+            let res = FileWatcher::new(tx, watch_path.poll_interval_ms).and_then(|mut watcher| {
+                watcher.watch(&path, watch_path.recursive_mode.into())?;
+                Ok(watcher)
+            });
+            match res {
+                Ok(watcher) => {
+                    log::info!("watching '{}' again", watch_path.path.display());
+                    watchers[idx] = (rx, watch_path.clone(), watcher);
+                    reconnected.push(idx);
+                }
+                Err(e) => log::debug!("watching '{}' again: {e}", watch_path.path.display()),
+            }
+        }
+        for idx in reconnected.iter() {
+            remove_indecies.remove(idx);
+        }
+        reconnected
+    }
+
+    /// The watch paths which are not disabled.
+    pub fn enabled_watch_paths(&self) -> impl Iterator<Item = &WatchPath> {
+        self.watch.iter().filter(|watch_path| watch_path.enabled)
     }
 
+    /// Scan every enabled watch path once.
+    ///
+    /// A path which can't be handled doesn't stop the run, all other paths are still handled and
+    /// the run fails with `FailedPaths` at the end.
     pub fn one_shot(&self) -> JResult {
-        for watch_path in self.watch.iter() {
+        JError::combine(self.enabled_watch_paths().map(|watch_path| {
             log::trace!("one-shotting watch path: {watch_path:?}");
-            let recursive = matches!(
-                watch_path.recursive_mode,
-                crate::watch_path::RecMode::Recursive
-            );
-            let mut stack = vec![std::path::PathBuf::from(watch_path.path.resolve())];
-            let mut file_paths = Vec::new();
-            let mut dir_paths = Vec::new();
-
-            while let Some(p) = stack.pop() {
-                if p.is_file() {
-                    file_paths.push(p.clone());
-                } else if p.is_dir() {
-                    for dentry in p.read_dir()?.map_while(Result::ok) {
-                        // Skip current and previous directory entries.
-                        if let Some(fname) = dentry.path().file_name() {
-                            if fname.to_string_lossy() == "." || fname.to_string_lossy() == ".." {
-                                continue;
-                            }
-                        }
-                        // Ignore bucket directories.
-                        if self
-                            .bucket
-                            .iter()
-                            .any(|b| b.destination.resolve() == dentry.path().resolve())
-                        {
-                            continue;
-                        } else if recursive {
-                            stack.push(dentry.path().resolve().into());
-                        } else if dentry.path().is_dir() {
-                            dir_paths.push(dentry.path().resolve().into());
-                        } else if dentry.path().is_file() {
-                            file_paths.push(dentry.path().resolve().into())
-                        }
+            (watch_path.path.clone(), self.scan(watch_path))
+        }))
+    }
+
+    /// Handle the paths which are inside of the watch paths with `initial_sweep` already, or of
+    /// every watch path if `all` is set, like `one_shot` does. Watch paths which don't exist are
+    /// left out.
+    pub fn sweep_on_start(&self, all: bool) -> JResult {
+        JError::combine(
+            self.enabled_watch_paths()
+                .filter(|watch_path| {
+                    (all || watch_path.initial_sweep) && watch_path.path.resolve().exists()
+                })
+                .map(|watch_path| {
+                    log::info!(
+                        "Sweeping '{}' before watching it.",
+                        watch_path.path.display()
+                    );
+                    (watch_path.path.clone(), self.scan(watch_path))
+                }),
+        )
+    }
+
+    /// Put the old paths inside of every watch path into the buckets with a `min_age_days`.
+    ///
+    /// Other buckets and default destinations are left out, so that paths which are not old
+    /// enough yet stay where they are. Returns how many paths were handled, or `FailedPaths` if
+    /// some of them could not be handled.
+    pub fn maintain(&self) -> JResult<u64> {
+        let handled = || {
+            self.stats()
+                .values()
+                .map(|s| s.moved + s.copied + s.deleted + s.renamed + s.linked + s.extracted)
+                .sum::<u64>()
+        };
+        let before = handled();
+        let mut results = Vec::new();
+        for watch_path in self.enabled_watch_paths() {
+            let bucket_names: Vec<String> = watch_path
+                .bucket_names
+                .iter()
+                .filter(|name| {
+                    self.bucket
+                        .iter()
+                        .any(|b| &b.name == *name && b.min_age_days.is_some())
+                })
+                .cloned()
+                .collect();
+            let buckets: Vec<Bucket> = watch_path
+                .buckets
+                .iter()
+                .filter(|b| b.min_age_days.is_some())
+                .cloned()
+                .collect();
+            if bucket_names.is_empty() && buckets.is_empty() {
+                continue;
+            }
+            let watch_path = WatchPath {
+                bucket_names,
+                buckets,
+                default_destination: None,
+                _default_bucket: Derived::default(),
+                ..watch_path.clone()
+            };
+            log::debug!("maintaining watch path '{}'", watch_path.path.display());
+            results.push((watch_path.path.clone(), self.scan(&watch_path)));
+        }
+
+        let count = handled() - before;
+        log::info!("maintenance put {count} old paths into bins");
+        JError::combine(results)?;
+        Ok(count)
+    }
+
+    /// Put every path inside of the directory `path` into the buckets of the configuration, as if
+    /// it was a watch path using all of them.
+    pub fn sweep(&self, path: &Path, recursive_mode: RecMode) -> JResult {
+        let mut watch_path = WatchPath {
+            path: expand_env(path)?,
+            recursive_mode,
+            bucket_names: self.bucket.iter().map(|b| b.name.clone()).collect(),
+            ..Default::default()
+        };
+        if !watch_path.path.resolve().is_dir() {
+            bail!(JError::InvalidPath(watch_path.path));
+        }
+        watch_path.init()?;
+        self.scan(&watch_path)
+    }
+
+    /// Handle every path inside of a watch path, as if all of them had just been created.
+    ///
+    /// Paths and directories which fail are skipped and listed by the `FailedPaths` error at the
+    /// end.
+    pub fn scan(&self, watch_path: &WatchPath) -> JResult {
+        let recursive = matches!(
+            watch_path.recursive_mode,
+            RecMode::Recursive | RecMode::Depth(_)
+        );
+        let root = PathBuf::from(watch_path.path.resolve());
+        let mut file_paths = Vec::new();
+        let mut dir_paths = Vec::new();
+        // Directories which have been read already, so that followed links can't loop.
+        let visited = Mutex::new(HashSet::new());
+        // Directories which could not be read don't stop the scan of the others.
+        let mut unreadable = Vec::new();
+
+        // The directories of one level are read by the workers in parallel, each paired with the
+        // depth of the entries it contains.
+        let mut level = Vec::new();
+        if root.is_file() {
+            file_paths.push(root);
+        } else {
+            level.push((root, 0));
+        }
+        while !level.is_empty() {
+            if deadline::passed() {
+                log::warn!(
+                    "the maximum runtime has passed, '{}' was not scanned to the end",
+                    watch_path.path.display()
+                );
+                break;
+            }
+            let listings = self.in_parallel(level, |(dir, depth)| {
+                let listing = self.list_dir(watch_path, &dir, depth, recursive, &visited);
+                (dir, listing)
+            });
+            level = Vec::new();
+            for (dir, listing) in listings {
+                match listing {
+                    Ok(listing) => {
+                        level.extend(listing.subdirs);
+                        file_paths.extend(listing.files);
+                        dir_paths.extend(listing.dirs);
+                    }
+                    Err(e) => {
+                        log::error!("could not read '{}': {e}", dir.display());
+                        unreadable.push((dir, Err(e.into())));
                     }
                 }
             }
+        }
+        // Workers finish in any order, sorting keeps the order in which paths are handled, and
+        // so the names `rename` picks, the same from one run to the next.
+        file_paths.sort();
+        dir_paths.sort();
 
-            watch_path.handle_paths(file_paths.into_iter(), true, self)?;
-            watch_path.handle_paths(dir_paths.into_iter(), false, self)?;
+        if self.skip_open_files {
+            let open = files_open_for_writing();
+            let is_open = |path: &PathBuf| {
+                let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                let open = open.iter().find(|open| open.starts_with(&path));
+                if let Some(open) = open {
+                    log::info!(
+                        "skipping '{}', '{}' is open for writing",
+                        path.display(),
+                        open.display()
+                    );
+                }
+                open.is_some()
+            };
+            file_paths.retain(|path| !is_open(path));
+            dir_paths.retain(|path| !is_open(path));
         }
-        Ok(())
+        if watch_path.dedup_within_run {
+            file_paths = watch_path.dedup(file_paths, self);
+        }
+        let path = watch_path.path.clone();
+        let files = watch_path.handle_paths(file_paths, true, self);
+        let dirs = watch_path.handle_paths(dir_paths, false, self);
+        JError::combine(
+            unreadable
+                .into_iter()
+                .chain([(path.clone(), files), (path, dirs)]),
+        )
+    }
+
+    /// Read the directory `dir` of a scan, whose entries are at `depth` inside of the watch path.
+    ///
+    /// Directories which were read already give an empty listing.
+    fn list_dir(
+        &self,
+        watch_path: &WatchPath,
+        dir: &Path,
+        depth: usize,
+        recursive: bool,
+        visited: &Mutex<HashSet<PathBuf>>,
+    ) -> std::io::Result<Listing> {
+        let mut listing = Listing::default();
+        let canonical = dir.canonicalize().unwrap_or_else(|_| dir.to_owned());
+        if !visited
+            .lock()
+            .expect("visited lock poisoned")
+            .insert(canonical)
+        {
+            return Ok(listing);
+        }
+        for dentry in dir.read_dir()?.map_while(Result::ok) {
+            // Skip current and previous directory entries.
+            if let Some(fname) = dentry.path().file_name()
+                && (fname.to_string_lossy() == "." || fname.to_string_lossy() == "..")
+            {
+                continue;
+            }
+            // Ignore bucket directories, symbolic links which are not followed and paths
+            // excluded by the ignore file.
+            let is_dir = dentry.path().is_dir();
+            if watch_path.is_skipped_symlink(&dentry.path()) {
+                log::trace!("skipping symbolic link '{}'", dentry.path().display());
+                continue;
+            } else if watch_path.is_ignored(&dentry.path(), is_dir) {
+                log::trace!("ignoring '{}'", dentry.path().display());
+                continue;
+            } else if self
+                .bucket
+                .iter()
+                .chain(watch_path.buckets.iter())
+                .any(|b| b.destination_root().resolve() == dentry.path().resolve())
+                || watch_path.is_default_destination(&dentry.path())
+            {
+                continue;
+            }
+            let path = PathBuf::from(dentry.path().resolve());
+            if recursive && is_dir {
+                if watch_path.depth_limit().is_some_and(|max| depth >= max) {
+                    continue;
+                }
+                listing.subdirs.push((path, depth + 1));
+            } else if is_dir {
+                listing.dirs.push(path);
+            } else if path.is_file() {
+                listing.files.push(path);
+            }
+        }
+        Ok(listing)
+    }
+
+    /// Run `f` on every one of `items`, on as many threads as there are `workers`.
+    ///
+    /// The results are in no particular order unless there is a single worker.
+    pub fn in_parallel<T, R, I>(&self, items: I, f: impl Fn(T) -> R + Sync) -> Vec<R>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send,
+        R: Send,
+    {
+        if self.workers <= 1 {
+            return items.into_iter().map(f).collect();
+        }
+        let (tx, rx) = unbounded();
+        for item in items {
+            let _ = tx.send(item);
+        }
+        drop(tx);
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..self.workers)
+                .map(|_| scope.spawn(|| rx.iter().map(&f).collect::<Vec<_>>()))
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("worker thread panicked"))
+                .collect()
+        })
+    }
+}
+
+/// The entries of a directory read during a scan.
+#[derive(Default)]
+struct Listing {
+    /// Directories to read next, with the depth of their entries.
+    subdirs: Vec<(PathBuf, usize)>,
+    files: Vec<PathBuf>,
+    /// Directories which are handled as a whole, outside of recursive watch paths.
+    dirs: Vec<PathBuf>,
+}
+
+/// The files which processes have open for writing.
+///
+/// Every open file descriptor in `/proc` is looked at, so this is only a best effort: files of
+/// processes of other users are missing and files may be opened right after looking.
+#[cfg(target_os = "linux")]
+pub fn files_open_for_writing() -> HashSet<PathBuf> {
+    let mut open = HashSet::new();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return open;
+    };
+    for process in processes.map_while(Result::ok) {
+        let Ok(fds) = process.path().join("fd").read_dir() else {
+            continue;
+        };
+        for fd in fds.map_while(Result::ok) {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            // The access mode is in the lowest two bits of the octal flags.
+            let fdinfo = process.path().join("fdinfo").join(fd.file_name());
+            let writing = std::fs::read_to_string(fdinfo).is_ok_and(|info| {
+                info.lines()
+                    .find_map(|line| line.strip_prefix("flags:"))
+                    .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+                    .is_some_and(|flags| flags & 0o3 != 0)
+            });
+            if writing && target.is_absolute() {
+                open.insert(target);
+            }
+        }
+    }
+    open
+}
+
+/// Open files can't be told on other platforms, so no file counts as open.
+#[cfg(not(target_os = "linux"))]
+pub fn files_open_for_writing() -> HashSet<PathBuf> {
+    HashSet::new()
+}
+
+/// Expand `$VAR` and `${VAR}` occurrences in a path using the process environment.
+///
+/// Names of user directories, like `$DOWNLOADS`, which are not set in the environment are
+/// replaced by that user directory, see `user_dir`.
+///
+/// A `~` is left untouched, so tilde resolution still happens when the path is resolved. A `$`
+/// which is not followed by a variable name is kept as is, and so are names starting with a
+/// digit, like `$1`, which can't be variables.
+pub fn expand_env(path: &Path) -> JResult<PathBuf> {
+    expand_env_keeping(path, &[])
+}
+
+/// Expand the variables of a path like `expand_env`, but keep the names in `keep` as they are.
+pub fn expand_env_keeping(path: &Path, keep: &[&str]) -> JResult<PathBuf> {
+    let Some(input) = path.to_str() else {
+        bail!(JError::InvalidPath(path.to_owned()))
+    };
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(idx) = rest.find('$') {
+        output.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        let (name, remainder) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => bail!(JError::InvalidPath(path.to_owned())),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        if name.is_empty() {
+            output.push('$');
+            continue;
+        }
+        if name.starts_with(|c: char| c.is_ascii_digit()) || keep.contains(&name) {
+            output.push('$');
+            output.push_str(&rest[..rest.len() - remainder.len()]);
+            rest = remainder;
+            continue;
+        }
+        match std::env::var(name) {
+            Ok(value) => output.push_str(&value),
+            Err(_) => match user_dir(name)? {
+                Some(dir) => output.push_str(&dir.to_string_lossy()),
+                None => bail!(JError::UndefinedVariable(name.to_string())),
+            },
+        }
+        rest = remainder;
+    }
+    output.push_str(rest);
+
+    Ok(output.into())
+}
+
+/// The user directory which `name`, like `DOWNLOADS` or `PICTURES`, stands for, wherever the
+/// current platform keeps it, or `None` if `name` doesn't stand for a user directory.
+fn user_dir(name: &str) -> JResult<Option<PathBuf>> {
+    let dir = match name {
+        "DESKTOP" => dirs::desktop_dir(),
+        "DOCUMENTS" => dirs::document_dir(),
+        "DOWNLOADS" => dirs::download_dir(),
+        "MUSIC" => dirs::audio_dir(),
+        "PICTURES" => dirs::picture_dir(),
+        "PUBLIC" => dirs::public_dir(),
+        "TEMPLATES" => dirs::template_dir(),
+        "VIDEOS" => dirs::video_dir(),
+        _ => return Ok(None),
+    };
+    match dir {
+        Some(dir) => Ok(Some(dir)),
+        None => bail!(JError::UnknownUserDir(name.to_string())),
     }
 }
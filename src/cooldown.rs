@@ -0,0 +1,30 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// When each path was handled the last time.
+static HANDLED: Mutex<BTreeMap<PathBuf, Instant>> = Mutex::new(BTreeMap::new());
+
+/// Check whether `path` was not handled within the last `ttl`, and remember that it's handled
+/// now if so.
+///
+/// Paths which were handled longer than `ttl` ago are forgotten on every call, so the cache
+/// only holds the paths of the last `ttl`.
+pub fn claim(path: &Path, ttl: Duration) -> bool {
+    let mut handled = HANDLED.lock().expect("cooldown lock poisoned");
+    let now = Instant::now();
+    handled.retain(|_, at| now.duration_since(*at) < ttl);
+    if handled.contains_key(path) {
+        return false;
+    }
+    handled.insert(path.to_owned(), now);
+    true
+}
+
+/// Forget that `path` was handled, so that the next `claim` for it succeeds.
+pub fn release(path: &Path) {
+    HANDLED.lock().expect("cooldown lock poisoned").remove(path);
+}
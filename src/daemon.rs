@@ -0,0 +1,55 @@
+use std::{
+    fs::{OpenOptions, read_to_string, remove_file},
+    path::Path,
+};
+
+use anyhow::bail;
+use daemonize::Daemonize;
+
+use janitors::errors::{JError, JResult};
+
+/// Detach from the terminal and continue running in the background.
+///
+/// The PID of the daemon is written into `pid_file` and everything written to the standard
+/// output and error streams, like panic messages, is appended to `log_file`. Fails if another daemon
+/// using the same PID file is still alive.
+pub fn daemonize(pid_file: &Path, log_file: &Path) -> JResult {
+    if let Some(pid) = running_pid(pid_file) {
+        bail!(JError::AlreadyRunning(pid));
+    }
+
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+    Daemonize::new()
+        .pid_file(pid_file)
+        // Keep relative config and watch paths working.
+        .working_directory(std::env::current_dir()?)
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .start()?;
+
+    Ok(())
+}
+
+/// Remove the PID file of the daemon when shutting down.
+pub fn remove_pid_file(pid_file: &Path) {
+    if let Err(e) = remove_file(pid_file) {
+        log::warn!("removing PID file '{}': {e}", pid_file.display());
+    }
+}
+
+/// Return the PID stored in `pid_file` if that process is still alive.
+fn running_pid(pid_file: &Path) -> Option<i32> {
+    let pid = read_to_string(pid_file)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        // `kill` takes `0` and negative PIDs as process groups, so they'd always look alive.
+        .filter(|pid| *pid > 0)?;
+    // Signal 0 only checks whether the process exists.
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    alive.then_some(pid)
+}
@@ -0,0 +1,47 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// When paths stop being handled, if they do, as set by `--max-runtime`.
+static DEADLINE: Mutex<Option<Instant>> = Mutex::new(None);
+/// How many paths were handled before the deadline.
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+/// How many paths were left alone, because they came up after the deadline.
+static LEFT: AtomicUsize = AtomicUsize::new(0);
+
+/// Stop handling new paths once `max_runtime` has passed from now. Paths which are being
+/// handled by then are finished.
+pub fn set(max_runtime: Duration) {
+    *DEADLINE.lock().expect("deadline lock poisoned") = Some(Instant::now() + max_runtime);
+}
+
+/// Whether the deadline has passed.
+pub fn passed() -> bool {
+    DEADLINE
+        .lock()
+        .expect("deadline lock poisoned")
+        .is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Decide whether another path is handled, which it is unless the deadline has passed, and
+/// count it either way.
+pub fn claim() -> bool {
+    if passed() {
+        LEFT.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    HANDLED.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// How many paths were handled before the deadline and how many were left alone after it.
+pub fn counts() -> (usize, usize) {
+    (
+        HANDLED.load(Ordering::Relaxed),
+        LEFT.load(Ordering::Relaxed),
+    )
+}
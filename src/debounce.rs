@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::watch_path::WatchPath;
+
+/// A path waiting for its debounce window to pass.
+#[derive(Debug, Clone)]
+struct Pending {
+    watch_path: WatchPath,
+    is_file: bool,
+    last_event: Instant,
+}
+
+/// A `Debouncer` coalesces file system events per path.
+///
+/// New paths are pushed into the debouncer and every further event for the same path restarts
+/// its window. A path is only released once no event for it has arrived for the whole window,
+/// so files which are still being written are not acted upon.
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    window: Duration,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Change the debounce window, e.g. after the configuration has been reloaded.
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Start tracking a new path, or restart the window if the path is already tracked.
+    pub fn push(&mut self, watch_path: &WatchPath, path: PathBuf, is_file: bool) {
+        self.pending.insert(
+            path,
+            Pending {
+                watch_path: watch_path.clone(),
+                is_file,
+                last_event: Instant::now(),
+            },
+        );
+    }
+
+    /// Restart the window of a path, if it's being tracked.
+    pub fn touch(&mut self, path: &Path) {
+        if let Some(pending) = self.pending.get_mut(path) {
+            pending.last_event = Instant::now();
+        }
+    }
+
+    /// Stop tracking a path, e.g. because it was moved away.
+    pub fn remove(&mut self, path: &Path) {
+        self.pending.remove(path);
+    }
+
+    /// Remove and return all paths which have been quiet for the entire window.
+    pub fn ready(&mut self) -> Vec<(WatchPath, PathBuf, bool)> {
+        let now = Instant::now();
+        let ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.last_event) >= self.window)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        ready
+            .into_iter()
+            .filter_map(|path| {
+                let pending = self.pending.remove(&path)?;
+                Some((pending.watch_path, path, pending.is_file))
+            })
+            .collect()
+    }
+
+    /// All tracked paths, with their watch path and whether they are files.
+    pub fn tracked(&self) -> impl Iterator<Item = (&WatchPath, &Path, bool)> {
+        self.pending
+            .iter()
+            .map(|(path, pending)| (&pending.watch_path, path.as_path(), pending.is_file))
+    }
+
+    /// How long until the next tracked path becomes ready, if any paths are tracked.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.pending
+            .values()
+            .map(|pending| (pending.last_event + self.window).saturating_duration_since(now))
+            .min()
+    }
+}
+
+/// Group the paths of `ready` by their watch path and whether they are files, keeping their
+/// order, so that each group can be handled by a single `WatchPath::handle_paths` call.
+pub fn group(
+    ready: impl IntoIterator<Item = (WatchPath, PathBuf, bool)>,
+) -> Vec<(WatchPath, Vec<PathBuf>, bool)> {
+    let mut groups: Vec<(WatchPath, Vec<PathBuf>, bool)> = Vec::new();
+    for (watch_path, path, is_file) in ready {
+        match groups
+            .iter_mut()
+            .find(|(w, _, f)| *w == watch_path && *f == is_file)
+        {
+            Some((_, paths, _)) => paths.push(path),
+            None => groups.push((watch_path, vec![path], is_file)),
+        }
+    }
+    groups
+}
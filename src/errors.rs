@@ -6,6 +6,37 @@ pub type JResult<T = ()> = anyhow::Result<T>;
 pub enum JError {
     MissingValue(String),
     InvalidPath(PathBuf),
+    UndefinedVariable(String),
+    /// A user directory like `$PICTURES` which this system doesn't have.
+    UnknownUserDir(String),
+    DuplicateBucket(String),
+    UnknownBucket(PathBuf, String),
+    MissingWatchPath(PathBuf),
+    UnknownFilterSet(String, String),
+    DestinationInsideWatchPath(PathBuf, PathBuf),
+    AlreadyRunning(i32),
+    InsufficientSpace(PathBuf, u64, u64),
+    HookFailed(String, String),
+    ProtectedPath(PathBuf),
+    ConflictingBuckets(PathBuf, Vec<String>),
+    /// The bin, the path it failed on and why.
+    ActionFailed(String, PathBuf, String),
+    /// The bin, the name filter or extension regex which doesn't compile and why.
+    RegexCompile(String, String, String),
+    /// Several name filters or extension regexes which don't compile, as in `RegexCompile`.
+    InvalidNameFilters(Vec<(String, String, String)>),
+    /// Paths which could not be handled, each with the error it failed with.
+    FailedPaths(Vec<(PathBuf, String)>),
+    /// A path which the `extract` action can't unpack, because it's no zip, tar or gzip file.
+    UnknownArchive(PathBuf),
+    /// The profile which was selected and the ones the configuration has.
+    UnknownProfile(String, Vec<String>),
+    /// The bin which wins, the bin which loses and the filter they share.
+    OverlappingBuckets(String, String, String),
+    /// The bin and a placeholder of its destination which isn't known or has an invalid format.
+    UnknownPlaceholder(String, String),
+    /// The content predicate of a bin which didn't finish in time, and its timeout in seconds.
+    PredicateTimedOut(String, u64),
 }
 
 impl std::error::Error for JError {}
@@ -15,10 +46,149 @@ impl Display for JError {
         match self {
             Self::MissingValue(v) => write!(f, "Missing value: {v}"),
             Self::InvalidPath(v) => write!(f, "Invalid path: {}", v.display()),
+            Self::UndefinedVariable(v) => write!(f, "Undefined environment variable: {v}"),
+            Self::UnknownUserDir(v) => write!(f, "There is no user directory for ${v} here"),
+            Self::DuplicateBucket(v) => write!(f, "Duplicate bucket name: {v}"),
+            Self::UnknownBucket(p, v) => {
+                write!(f, "Watch path '{}' uses unknown bucket: {v}", p.display())
+            }
+            Self::MissingWatchPath(v) => write!(f, "Watch path does not exist: {}", v.display()),
+            Self::UnknownFilterSet(b, v) => write!(f, "Bin '{b}' uses unknown filter set: {v}"),
+            Self::DestinationInsideWatchPath(d, w) => write!(
+                f,
+                "Destination '{}' lies inside of the recursively watched '{}', paths put there are not handled again",
+                d.display(),
+                w.display()
+            ),
+            Self::AlreadyRunning(v) => write!(f, "Daemon is already running with PID {v}"),
+            Self::InsufficientSpace(p, needed, available) => write!(
+                f,
+                "Not enough free space in '{}': {needed} bytes needed, {available} bytes available",
+                p.display()
+            ),
+            Self::HookFailed(cmd, status) => write!(f, "Hook '{cmd}' failed: {status}"),
+            Self::ProtectedPath(v) => write!(
+                f,
+                "Refusing to delete '{}', it is or contains a watch path or bucket destination",
+                v.display()
+            ),
+            Self::ConflictingBuckets(p, v) => write!(
+                f,
+                "'{}' fits into several bins which move, rename or delete it: {}",
+                p.display(),
+                v.join(", ")
+            ),
+            Self::UnknownArchive(v) => {
+                write!(f, "'{}' is not a zip, tar or gzip archive", v.display())
+            }
+            Self::UnknownProfile(v, known) if known.is_empty() => {
+                write!(f, "Unknown profile: {v}, the configuration has no profiles")
+            }
+            Self::UnknownProfile(v, known) => write!(
+                f,
+                "Unknown profile: {v}, the configuration has {}",
+                known.join(", ")
+            ),
+            Self::OverlappingBuckets(a, b, v) => write!(
+                f,
+                "Bins '{a}' and '{b}' both have the {v} but different destinations, '{a}' wins for the paths which fit into both"
+            ),
+            Self::UnknownPlaceholder(b, v) => write!(
+                f,
+                "Bin '{b}' has an unknown placeholder in its destination: {{{v}}}, expected {{ext}}, {{stem}}, {{date}} or {{date:FORMAT}}"
+            ),
+            Self::PredicateTimedOut(cmd, secs) => {
+                write!(f, "Content predicate '{cmd}' did not finish within {secs}s")
+            }
+            Self::ActionFailed(b, p, v) => {
+                write!(f, "Bin '{b}' failed on '{}': {v}", p.display())
+            }
+            Self::RegexCompile(b, p, v) => {
+                write!(f, "Bin '{b}' has an invalid filter '{p}': {v}")
+            }
+            Self::InvalidNameFilters(v) => {
+                write!(f, "{} filters are invalid:", v.len())?;
+                for (b, p, e) in v {
+                    write!(f, "\n  bin '{b}', filter '{p}': {e}")?;
+                }
+                Ok(())
+            }
+            Self::FailedPaths(v) => {
+                let paths: Vec<String> = v
+                    .iter()
+                    .map(|(p, _)| format!("'{}'", p.display()))
+                    .collect();
+                write!(
+                    f,
+                    "Could not handle {} paths: {}",
+                    v.len(),
+                    paths.join(", ")
+                )
+            }
         }
     }
 }
 
+impl JError {
+    /// Combine the results of handling several paths into one, which fails with
+    /// `FailedPaths` if any of them failed.
+    ///
+    /// Errors which are `FailedPaths` themselves are flattened, so that the combined error lists
+    /// the individual paths instead of the directories they were found in.
+    pub fn combine(results: impl IntoIterator<Item = (PathBuf, JResult)>) -> JResult {
+        let mut failed = Vec::new();
+        for (path, res) in results {
+            let Err(e) = res else {
+                continue;
+            };
+            match e.downcast::<JError>() {
+                Ok(JError::FailedPaths(paths)) => failed.extend(paths),
+                Ok(e) => failed.push((path, e.to_string())),
+                Err(e) => failed.push((path, e.to_string())),
+            }
+        }
+        if failed.is_empty() {
+            return Ok(());
+        }
+        Err(JError::FailedPaths(failed).into())
+    }
+}
+
+/// Check whether an error is likely to go away on its own, like an unavailable network mount.
+///
+/// Errors caused by the configuration or the file itself, like invalid or missing paths and
+/// missing permissions, are never retryable.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    use std::io::ErrorKind;
+
+    let kind = if let Some(e) = err.downcast_ref::<std::io::Error>() {
+        e.kind()
+    } else if let Some(e) = err.downcast_ref::<fs_extra::error::Error>() {
+        match &e.kind {
+            fs_extra::error::ErrorKind::Io(e) => e.kind(),
+            fs_extra::error::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            _ => return false,
+        }
+    } else {
+        return false;
+    };
+
+    matches!(
+        kind,
+        ErrorKind::Interrupted
+            | ErrorKind::TimedOut
+            | ErrorKind::WouldBlock
+            | ErrorKind::ResourceBusy
+            | ErrorKind::StaleNetworkFileHandle
+            | ErrorKind::NetworkDown
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NotConnected
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+    )
+}
+
 impl<Ref: AsRef<str>> From<&Ref> for JError {
     fn from(value: &Ref) -> Self {
         let value = value.as_ref();
@@ -0,0 +1,71 @@
+use std::{
+    fs::{remove_file, symlink_metadata},
+    io::{ErrorKind, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::bail;
+use janitors::{errors::JResult, feed};
+
+/// Write every action to the clients which connect to a Unix domain socket at `path`, one JSON
+/// object per line, from background threads.
+///
+/// A socket which is left over from an earlier run is replaced, anything else at `path` is an
+/// error. Clients which can't take a line right away are disconnected, so that a stuck client
+/// never holds up the actions.
+pub fn serve(path: &Path) -> JResult {
+    match symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => remove_file(path)?,
+        Ok(_) => bail!(
+            "refusing to replace '{}' with the event socket, it is not a socket",
+            path.display()
+        ),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let listener = UnixListener::bind(path)?;
+    log::info!("writing actions to the socket '{}'", path.display());
+    let clients: Arc<Mutex<Vec<UnixStream>>> = Arc::default();
+
+    let accepting = Arc::clone(&clients);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream.and_then(|stream| stream.set_nonblocking(true).map(|()| stream)) {
+                Ok(stream) => accepting.lock().expect("socket lock poisoned").push(stream),
+                Err(e) => log::debug!("accepting a client of the event socket: {e}"),
+            }
+        }
+    });
+
+    let entries = feed::subscribe();
+    std::thread::spawn(move || {
+        for entry in entries.iter() {
+            let mut line = match serde_json::to_string(&entry) {
+                Ok(line) => line,
+                Err(e) => {
+                    log::warn!("serializing an action for the event socket: {e}");
+                    continue;
+                }
+            };
+            line.push('\n');
+            clients
+                .lock()
+                .expect("socket lock poisoned")
+                .retain_mut(|client| match client.write_all(line.as_bytes()) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        if e.kind() == ErrorKind::WouldBlock {
+                            log::info!("disconnecting a client of the event socket, it's too slow");
+                        }
+                        false
+                    }
+                });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use std::{borrow::Cow, path::Path};
+
+use resolve_path::PathResolveExt;
+
+use crate::{
+    bucket::{Bucket, MatchMode, TieBreak, rank_buckets},
+    config::Config,
+    schedule,
+    watch_path::RecMode,
+};
+
+/// Describe which bucket `path` would be put into and why the other buckets were not picked.
+///
+/// The same rules as for handling paths are applied, but nothing is touched. Every returned line
+/// is one finding, starting with the watch path and ignore rules which apply to `path`.
+pub fn explain(config: &Config, path: &Path) -> Vec<String> {
+    let mut lines = Vec::new();
+    let is_dir = path.is_dir();
+
+    let watch_path = config
+        .watch
+        .iter()
+        .find(|watch_path| path.starts_with(watch_path.path.resolve()));
+    let candidates: Vec<Cow<Bucket>> = match watch_path {
+        Some(watch_path) => {
+            lines.push(format!("inside watch path '{}'", watch_path.path.display()));
+            if !watch_path.enabled {
+                lines.push("the watch path is disabled, so it is never handled".into());
+            }
+            if watch_path.recursive_mode == RecMode::NonRecursive
+                && watch_path.depth(path).is_some_and(|depth| depth > 0)
+            {
+                lines.push(
+                    "not directly inside the non-recursive watch path, so it is never handled"
+                        .into(),
+                );
+            }
+            if watch_path.is_nested_file(path, !is_dir) {
+                lines.push(
+                    "a file below the top level of the watch path, which only takes directories there, so it is never handled"
+                        .into(),
+                );
+            }
+            if watch_path.exceeds_max_depth(path) {
+                lines.push("deeper than the watch path's max_depth or recursive_mode allow, so it is never handled".into());
+            }
+            if watch_path.is_hidden(path) {
+                lines.push(
+                    "hidden, and the watch path doesn't set include_hidden, so it is never handled"
+                        .into(),
+                );
+            } else if watch_path.is_ignored(path, is_dir) {
+                lines.push(format!(
+                    "excluded by the ignore file '{}'",
+                    watch_path.ignore_file
+                ));
+            }
+            if watch_path.is_skipped_symlink(path) {
+                lines.push("a symbolic link which is not followed".into());
+            }
+            watch_path.prioritized_buckets(config).collect()
+        }
+        None => {
+            lines.push("not inside any watch path, considering all bins".into());
+            config.bucket.iter().map(Cow::Borrowed).collect()
+        }
+    };
+    if config.is_ignored(path) {
+        lines.push("ignored because of its name, see ignore_suffixes and ignore_patterns".into());
+    }
+    let (candidates, disabled): (Vec<Cow<Bucket>>, _) =
+        candidates.into_iter().partition(|bucket| bucket.enabled);
+    let candidates: Vec<&Bucket> = candidates.iter().map(AsRef::as_ref).collect();
+    for bucket in disabled {
+        lines.push(format!("bin '{}' is disabled", bucket.name));
+    }
+
+    let now = schedule::now().time();
+    for bucket in candidates.iter().filter(|b| !b.is_active_at(now)) {
+        lines.push(format!(
+            "bin '{}' is dormant right now, because it's only active at {}",
+            bucket.name,
+            bucket.active_hours.as_deref().unwrap_or_default()
+        ));
+    }
+
+    let ranked = match rank_buckets(candidates.iter().copied(), path, !is_dir, config.tie_break) {
+        Ok(ranked) => ranked,
+        Err(e) => {
+            lines.push(format!("would fail, because checking the bins failed: {e}"));
+            return lines;
+        }
+    };
+    let Some(best) = ranked.first() else {
+        match watch_path.and_then(|w| w.default_destination.as_ref()) {
+            Some(destination) => lines.push(format!(
+                "does not fit into any bin, so it would be moved into the default destination '{}'",
+                destination.display()
+            )),
+            None => lines.push("does not fit into any bin".into()),
+        }
+        for bucket in candidates {
+            if let Some(line) = wrong_target(bucket, is_dir) {
+                lines.push(line);
+                continue;
+            }
+            lines.push(format!(
+                "bin '{}' does not match, {}",
+                bucket.name,
+                mismatch(bucket, path)
+            ));
+        }
+        return lines;
+    };
+    lines.push(format!(
+        "would be put into bin '{}' with action '{}', because its {}",
+        best.name,
+        format!(
+            "{:?}",
+            watch_path
+                .and_then(|w| w.action_override.as_ref())
+                .unwrap_or(&best.for_path(path).action)
+        )
+        .to_lowercase(),
+        match_reason(best, path).unwrap_or_default()
+    ));
+
+    for bucket in candidates.into_iter().filter(|b| b.name != best.name) {
+        if let Some(line) = wrong_target(bucket, is_dir) {
+            lines.push(line);
+            continue;
+        }
+        let Some(reason) = match_reason(bucket, path) else {
+            lines.push(format!(
+                "bin '{}' does not match, {}",
+                bucket.name,
+                mismatch(bucket, path)
+            ));
+            continue;
+        };
+        if watch_path.is_some_and(|w| w.apply_all_matching) && best.is_active_at(now) {
+            lines.push(format!(
+                "bin '{}' also matches, because its {reason}, and is applied as well, because the watch path applies all matching bins",
+                bucket.name
+            ));
+            continue;
+        }
+        let lost = if bucket.priority < best.priority {
+            format!(
+                "has a lower priority than '{}' ({} < {})",
+                best.name, bucket.priority, best.priority
+            )
+        } else {
+            let by = match config.tie_break {
+                TieBreak::HighestPriority | TieBreak::Name => "by name",
+                TieBreak::FirstDefined => "because it's defined later",
+            };
+            format!(
+                "has the same priority as '{}' and loses the tie-break {by}",
+                best.name
+            )
+        };
+        lines.push(format!(
+            "bin '{}' also matches, because its {reason}, but {lost}",
+            bucket.name
+        ));
+    }
+
+    lines
+}
+
+/// Describe every bucket of `config`, including the inline buckets of watch paths, as it's
+/// matched against paths: with the filters of its filter sets, the extensions of its extension
+/// groups and its name filters as they were compiled.
+///
+/// Buckets with higher priorities come first, and every bucket starts a new line which isn't
+/// indented.
+pub fn list_buckets(config: &Config) -> Vec<String> {
+    let mut buckets: Vec<&Bucket> = config
+        .bucket
+        .iter()
+        .chain(config.watch.iter().flat_map(|w| w.buckets.iter()))
+        .collect();
+    buckets.sort_by(|a, b| b.cmp(a));
+
+    let mut lines = Vec::new();
+    for bucket in buckets {
+        let action = |action| format!("{action:?}").to_lowercase();
+        lines.push(format!(
+            "{} (priority {}, {}{})",
+            bucket.name,
+            bucket.priority,
+            action(&bucket.action),
+            if bucket.enabled { "" } else { ", disabled" }
+        ));
+        if bucket.needs_destination() {
+            lines.push(format!("  destination: {}", bucket.destination.display()));
+        }
+        let extensions = bucket.extensions();
+        let patterns = |regexes: &[regex::Regex]| {
+            regexes
+                .iter()
+                .map(|r| r.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        for (label, values) in [
+            ("extensions", extensions.join(", ")),
+            ("extension patterns", patterns(&bucket._ext_regexes)),
+            ("name filters", patterns(&bucket._regexes)),
+            ("content types", bucket.mime_filters.join(", ")),
+            (
+                "content predicate",
+                bucket.content_predicate.clone().unwrap_or_default(),
+            ),
+        ] {
+            if !values.is_empty() {
+                lines.push(format!("  {label}: {values}"));
+            }
+        }
+        if bucket.match_mode == MatchMode::All {
+            lines.push("  every kind of filter has to match".into());
+        }
+        for (extension, other) in bucket.extension_actions.iter() {
+            lines.push(format!("  .{extension} files: {}", action(other)));
+        }
+    }
+    lines
+}
+
+/// Why `path` fits into `bucket`, if it does.
+fn match_reason(bucket: &Bucket, path: &Path) -> Option<String> {
+    bucket
+        .match_reason(&path)
+        .ok()
+        .flatten()
+        .map(|reason| reason.to_string())
+}
+
+/// Why `bucket` is not considered for a path, if it doesn't take its kind of path.
+fn wrong_target(bucket: &Bucket, is_dir: bool) -> Option<String> {
+    (!bucket.target.accepts(!is_dir)).then(|| {
+        format!(
+            "bin '{}' does not take {}",
+            bucket.name,
+            if is_dir { "directories" } else { "files" }
+        )
+    })
+}
+
+fn mismatch(bucket: &Bucket, path: &Path) -> String {
+    if !bucket.is_old_enough(path).unwrap_or(true) {
+        return format!(
+            "'{}' was modified less than {} days ago",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            bucket.min_age_days.unwrap_or_default()
+        );
+    }
+    if !bucket.owner_matches(path).unwrap_or(true) {
+        return format!(
+            "'{}' is not owned by the user or group of its owner filters",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+    }
+    let fname = path.file_name().unwrap_or_default().to_string_lossy();
+    if let Some(predicate) = &bucket.content_predicate
+        && (!bucket.has_filters() || bucket.filter_reason(path).is_ok_and(|r| r.is_some()))
+    {
+        return format!("content predicate '{predicate}' fails for '{fname}'");
+    }
+    match (bucket.match_mode, bucket.mime_filters.is_empty()) {
+        (MatchMode::Any, true) => {
+            format!("neither the extension nor the name of '{fname}' match its filters")
+        }
+        (MatchMode::Any, false) => format!(
+            "neither the extension, the name nor the content type of '{fname}' match its filters"
+        ),
+        (MatchMode::All, true) => format!(
+            "the extension and the name of '{fname}' don't both match its filters, which its match_mode requires"
+        ),
+        (MatchMode::All, false) => format!(
+            "the extension, the name and the content type of '{fname}' don't all match its filters, which its match_mode requires"
+        ),
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    fs::{File, create_dir_all},
+    io::{BufReader, Read, Seek, SeekFrom, copy},
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use flate2::read::GzDecoder;
+
+use crate::errors::{JError, JResult};
+
+/// The formats of archives which the `extract` action can unpack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    /// A gzip compressed tar archive.
+    TarGz,
+    /// A single gzip compressed file.
+    Gz,
+}
+
+/// Where the contents of a tar archive name their format, and what they say there.
+const TAR_MAGIC: (usize, &[u8]) = (257, b"ustar");
+
+/// The name of the directory the archive `path` is extracted into: its name without the
+/// extension, so `photos.tar.gz` and `photos.tgz` both become `photos`.
+pub fn archive_stem(path: &Path) -> PathBuf {
+    let stem = Path::new(path.file_stem().unwrap_or_default());
+    match stem.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("tar") => stem.with_extension(""),
+        _ => stem.to_owned(),
+    }
+}
+
+/// Unpack the archive `path` into the directory `to`, which is created if needed.
+///
+/// The format is told by the contents of the archive, not by its name. Entries which would end
+/// up outside of `to`, like `../escaped`, are rejected by the zip and tar readers.
+pub fn extract(path: &Path, to: &Path) -> JResult {
+    let mut file = File::open(path)?;
+    let format = detect(&mut file, path)?;
+    log::debug!(
+        "extracting '{}' as {format:?} into '{}'",
+        path.display(),
+        to.display()
+    );
+    create_dir_all(to)?;
+    match format {
+        Format::Zip => zip::ZipArchive::new(BufReader::new(file))?.extract(to)?,
+        Format::Tar => tar::Archive::new(BufReader::new(file)).unpack(to)?,
+        Format::TarGz => tar::Archive::new(GzDecoder::new(BufReader::new(file))).unpack(to)?,
+        Format::Gz => {
+            let name = path.file_stem().unwrap_or_default();
+            let mut decoder = GzDecoder::new(BufReader::new(file));
+            copy(&mut decoder, &mut File::create(to.join(name))?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find out the format of the archive `file` from its first bytes, and rewind it afterwards.
+fn detect(file: &mut File, path: &Path) -> JResult<Format> {
+    let mut head = Vec::new();
+    file.by_ref().take(512).read_to_end(&mut head)?;
+    file.seek(SeekFrom::Start(0))?;
+    let format = if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        Format::Zip
+    } else if is_tar(&head) {
+        Format::Tar
+    } else if head.starts_with(&[0x1f, 0x8b]) {
+        // Only the start of the decompressed contents is needed to tell a tar archive apart.
+        let mut inner = Vec::new();
+        GzDecoder::new(file.by_ref())
+            .take(512)
+            .read_to_end(&mut inner)?;
+        file.seek(SeekFrom::Start(0))?;
+        if is_tar(&inner) {
+            Format::TarGz
+        } else {
+            Format::Gz
+        }
+    } else {
+        bail!(JError::UnknownArchive(path.to_owned()))
+    };
+    Ok(format)
+}
+
+fn is_tar(head: &[u8]) -> bool {
+    let (offset, magic) = TAR_MAGIC;
+    head.get(offset..offset + magic.len()) == Some(magic)
+}
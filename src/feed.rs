@@ -0,0 +1,80 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use crossbeam::channel::{Receiver, Sender, TrySendError, bounded};
+use serde::Serialize;
+
+use crate::bucket::Action;
+
+/// How many actions a subscriber may fall behind before further ones are dropped for it.
+const BACKLOG: usize = 256;
+
+/// The channels of everyone who subscribed to the feed.
+static SUBSCRIBERS: Mutex<Vec<Sender<FeedEntry>>> = Mutex::new(Vec::new());
+
+/// What became of a path which a bucket applied its action to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Done,
+    /// The path was left alone, e.g. because its destination was taken.
+    Skipped,
+    Failed,
+}
+
+/// An action which a bucket applied to a path, as it's sent to subscribers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeedEntry {
+    pub bucket: String,
+    pub action: String,
+    pub source: PathBuf,
+    /// Where the path was put, or would have been put if it wasn't done. Empty for deletions and
+    /// failures before the destination was known.
+    pub destination: PathBuf,
+    pub outcome: Outcome,
+    /// Why the action failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub timestamp: String,
+}
+
+/// Receive every action which is applied from now on.
+///
+/// Subscribers which don't keep up miss actions instead of holding up the buckets. Dropping the
+/// receiver ends the subscription.
+pub fn subscribe() -> Receiver<FeedEntry> {
+    let (tx, rx) = bounded(BACKLOG);
+    SUBSCRIBERS.lock().expect("feed lock poisoned").push(tx);
+    rx
+}
+
+/// Send an action of `bucket` on `source` to every subscriber.
+pub fn publish(
+    bucket: &str,
+    action: &Action,
+    source: PathBuf,
+    destination: PathBuf,
+    outcome: Outcome,
+    error: Option<String>,
+) {
+    let mut subscribers = SUBSCRIBERS.lock().expect("feed lock poisoned");
+    if subscribers.is_empty() {
+        return;
+    }
+    let entry = FeedEntry {
+        bucket: bucket.to_string(),
+        action: format!("{action:?}").to_lowercase(),
+        source,
+        destination,
+        outcome,
+        error,
+        timestamp: chrono::Local::now().to_rfc3339(),
+    };
+    subscribers.retain(|tx| match tx.try_send(entry.clone()) {
+        Ok(()) => true,
+        Err(TrySendError::Full(_)) => {
+            log::debug!("a subscriber of the feed is falling behind, dropping an action for it");
+            true
+        }
+        Err(TrySendError::Disconnected(_)) => false,
+    });
+}
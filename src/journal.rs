@@ -0,0 +1,183 @@
+use std::{
+    fs::{OpenOptions, create_dir_all, read_to_string, remove_dir_all, remove_file, write},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bucket::{Action, move_path},
+    errors::JResult,
+};
+
+/// A move or copy which was recorded in the journal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: String,
+    pub bucket: String,
+    pub action: Action,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// Size and modification time of the destination right after the action, used to tell
+    /// whether it was changed since.
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Serializes appending to the journal, so that lines of parallel workers don't mix.
+static JOURNAL: Mutex<()> = Mutex::new(());
+
+/// Append an entry for a path which `bucket` moved or copied from `source` to `destination`.
+pub fn record(
+    journal: &Path,
+    bucket: &str,
+    action: &Action,
+    source: &Path,
+    destination: &Path,
+) -> JResult {
+    let (size, modified) = snapshot(destination);
+    let entry = Entry {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        bucket: bucket.to_string(),
+        action: action.clone(),
+        source: source.to_owned(),
+        destination: destination.to_owned(),
+        size,
+        modified,
+    };
+    let line = serde_json::to_string(&entry)? + "\n";
+
+    let _lock = JOURNAL.lock().expect("journal lock poisoned");
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Reverse the entries of a journal, newest first.
+///
+/// Moved paths are moved back to where they came from and copies are removed. Entries whose
+/// destination is gone or was modified since are skipped, as are moves whose source path is
+/// taken again. Entries which failed with an error are kept in the journal, so that undoing them
+/// can be tried again; all others are removed. Returns the number of undone and skipped entries.
+pub fn undo(journal: &Path) -> JResult<(usize, usize)> {
+    let _lock = JOURNAL.lock().expect("journal lock poisoned");
+    let mut entries = Vec::new();
+    for line in read_to_string(journal)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+    {
+        entries.push(serde_json::from_str::<Entry>(line)?);
+    }
+
+    let (mut undone, mut skipped) = (0, 0);
+    let mut failed = Vec::new();
+    for entry in entries.into_iter().rev() {
+        match undo_entry(&entry) {
+            Ok(true) => undone += 1,
+            Ok(false) => skipped += 1,
+            Err(e) => {
+                log::error!(
+                    "undoing '{}' -> '{}': {e}",
+                    entry.source.display(),
+                    entry.destination.display()
+                );
+                failed.push(entry);
+            }
+        }
+    }
+
+    let mut remaining = String::new();
+    for entry in failed.iter().rev() {
+        remaining += &(serde_json::to_string(entry)? + "\n");
+    }
+    write(journal, remaining)?;
+
+    Ok((undone, skipped))
+}
+
+/// Undo a single entry, returning `false` if it had to be skipped.
+fn undo_entry(entry: &Entry) -> JResult<bool> {
+    let destination = &entry.destination;
+    if !destination.exists() {
+        log::info!(
+            "skipping '{}', it does not exist anymore",
+            destination.display()
+        );
+        return Ok(false);
+    }
+    if snapshot(destination) != (entry.size, entry.modified) {
+        log::info!("skipping '{}', it was modified", destination.display());
+        return Ok(false);
+    }
+
+    match entry.action {
+        Action::Move | Action::Rename => {
+            if entry.source.exists() {
+                log::info!(
+                    "skipping '{}', because '{}' exists again",
+                    destination.display(),
+                    entry.source.display()
+                );
+                return Ok(false);
+            }
+            if let Some(parent) = entry.source.parent() {
+                create_dir_all(parent)?;
+            }
+            move_path(destination, &entry.source, destination.is_file())?;
+            log::info!(
+                "moved '{}' back to '{}'",
+                destination.display(),
+                entry.source.display()
+            );
+        }
+        Action::Copy => {
+            if destination.is_dir() {
+                remove_dir_all(destination)?;
+            } else {
+                remove_file(destination)?;
+            }
+            log::info!("removed copy '{}'", destination.display());
+        }
+        // Without the archive, the extracted contents are all that's left of it.
+        Action::Extract if !entry.source.exists() => {
+            log::info!(
+                "skipping '{}', because '{}' is gone",
+                destination.display(),
+                entry.source.display()
+            );
+            return Ok(false);
+        }
+        Action::Extract => {
+            remove_dir_all(destination)?;
+            log::info!("removed extraction '{}'", destination.display());
+        }
+        Action::Hardlink | Action::Symlink => {
+            if !entry.source.exists() {
+                log::info!(
+                    "skipping '{}', because '{}' is gone",
+                    destination.display(),
+                    entry.source.display()
+                );
+                return Ok(false);
+            }
+            remove_file(destination)?;
+            log::info!("removed link '{}'", destination.display());
+        }
+        Action::Delete => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The size and modification time of a path.
+fn snapshot(path: &Path) -> (u64, Option<SystemTime>) {
+    match path.metadata() {
+        Ok(meta) => (meta.len(), meta.modified().ok()),
+        Err(_) => (0, None),
+    }
+}
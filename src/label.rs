@@ -0,0 +1,26 @@
+use std::cell::RefCell;
+
+thread_local! {
+    /// The label of the watch path whose paths the current thread is handling.
+    static LABEL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Restores the label of the current thread from before `enter` when dropped.
+pub struct Scope(Option<String>);
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        LABEL.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Label the log lines of the current thread with `label` until the returned scope is dropped.
+pub fn enter(label: impl Into<String>) -> Scope {
+    Scope(LABEL.with(|current| current.replace(Some(label.into()))))
+}
+
+/// The label of the current thread, if it's handling the paths of a watch path.
+pub fn current() -> Option<String> {
+    LABEL.with(|current| current.borrow().clone())
+}
@@ -0,0 +1,42 @@
+//! Sort files from watched directories into buckets.
+//!
+//! The `janitors` binary is a thin command line interface over this library. A [`Config`] is
+//! loaded with [`Config::load`], after which [`Config::one_shot`] sweeps all of its watch paths
+//! once and [`WatchPath::handle_paths`] puts paths into the best fitting [`Bucket`], which in
+//! turn applies its action with [`Bucket::apply_action`].
+
+pub mod activity;
+#[cfg(feature = "tokio")]
+pub mod async_loop;
+pub mod batch;
+pub mod bucket;
+pub mod burst;
+pub mod config;
+mod cooldown;
+pub mod deadline;
+pub mod debounce;
+pub mod errors;
+pub mod explain;
+mod extract;
+pub mod feed;
+pub mod journal;
+pub mod label;
+pub mod manifest;
+mod notification;
+pub mod pending;
+pub mod preview;
+pub mod reload;
+pub mod schedule;
+pub mod sftp;
+pub mod stats;
+#[cfg(test)]
+mod tests;
+pub mod throttle;
+mod trash;
+pub mod units;
+pub mod watch_path;
+
+pub use bucket::{Action, Bucket, MatchMode, MatchReason, OverrideAction, Target, TieBreak};
+pub use config::{Config, Options};
+pub use errors::{JError, JResult};
+pub use watch_path::{RecMode, WatchPath};
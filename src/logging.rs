@@ -0,0 +1,305 @@
+use std::{
+    fs::{File, OpenOptions, remove_file, rename},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+use log::{
+    Level, LevelFilter, Log, Metadata, Record,
+    kv::{Error, Key, Value, VisitSource},
+};
+use serde_json::{Map, Value as JsonValue};
+
+use janitors::errors::JResult;
+
+/// How log records are written to the standard error stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// How precise the timestamps of log records are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogTimestamp {
+    /// No timestamps at all.
+    Off,
+    /// Whole seconds.
+    #[default]
+    Second,
+    /// Milliseconds.
+    Millisecond,
+    /// Microseconds.
+    Microsecond,
+}
+
+impl LogTimestamp {
+    /// The current time with this precision, if any.
+    fn now(&self) -> Option<String> {
+        let format = match self {
+            Self::Off => return None,
+            Self::Second => chrono::SecondsFormat::Secs,
+            Self::Millisecond => chrono::SecondsFormat::Millis,
+            Self::Microsecond => chrono::SecondsFormat::Micros,
+        };
+        Some(chrono::Local::now().to_rfc3339_opts(format, false))
+    }
+}
+
+impl From<LogTimestamp> for stderrlog::Timestamp {
+    fn from(timestamp: LogTimestamp) -> Self {
+        match timestamp {
+            LogTimestamp::Off => Self::Off,
+            LogTimestamp::Second => Self::Second,
+            LogTimestamp::Millisecond => Self::Millisecond,
+            LogTimestamp::Microsecond => Self::Microsecond,
+        }
+    }
+}
+
+/// A log file which is rotated once it grows past `max_size` bytes.
+///
+/// Rotated files get a numeric suffix like `janitors.log.1`, where a higher number means an
+/// older file. Only the `keep` most recent rotated files are retained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+    pub path: PathBuf,
+    pub max_size: u64,
+    pub keep: usize,
+}
+
+/// Initialize the logging facility.
+///
+/// The `verbosity` is interpreted the same way for every format: `0` only shows errors, each
+/// following level adds warnings, info, debug and trace messages. Only records from this crate
+/// are logged. Logs are written to the standard error stream and, if given, to `log_file`. When
+/// `detached` is set, the standard error stream is not used at all. Records have a `timestamp`
+/// of the given precision, or none.
+pub fn init(
+    verbosity: usize,
+    format: LogFormat,
+    timestamp: LogTimestamp,
+    log_file: Option<LogFile>,
+    detached: bool,
+) -> JResult {
+    if format == LogFormat::Text && log_file.is_none() {
+        let mut logger = stderrlog::new();
+        logger
+            .verbosity(stderrlog::LogLevelNum::from(verbosity))
+            .timestamp(timestamp.into())
+            .module(env!("CARGO_CRATE_NAME"))
+            .color(if detached {
+                stderrlog::ColorChoice::Never
+            } else {
+                stderrlog::ColorChoice::Auto
+            });
+        log::set_max_level(level_filter(verbosity));
+        log::set_boxed_logger(Box::new(Labeled(logger)))?;
+        return Ok(());
+    }
+
+    let level = level_filter(verbosity);
+    let file = log_file
+        .map(RotatingFile::open)
+        .transpose()?
+        .map(Mutex::new);
+    log::set_boxed_logger(Box::new(Logger {
+        level,
+        format,
+        timestamp,
+        file,
+        stderr: !detached,
+    }))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+fn level_filter(verbosity: usize) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Error,
+        1 => LevelFilter::Warn,
+        2 => LevelFilter::Info,
+        3 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// A logger which writes to the standard error stream, a log file or both.
+///
+/// In the JSON format, every record becomes a single object with `timestamp`, `level` and
+/// `message` fields, and all key-value pairs attached to the record, like `source`,
+/// `destination` or `bucket`, become fields of the object as well.
+struct Logger {
+    level: LevelFilter,
+    format: LogFormat,
+    timestamp: LogTimestamp,
+    file: Option<Mutex<RotatingFile>>,
+    stderr: bool,
+}
+
+impl Logger {
+    fn format(&self, record: &Record) -> String {
+        let label = janitors::label::current();
+        let timestamp = self.timestamp.now();
+        match self.format {
+            LogFormat::Text => format!(
+                "{}{} {}{}",
+                timestamp.map(|t| format!("{t} - ")).unwrap_or_default(),
+                record.level(),
+                label.map(|l| format!("[{l}] ")).unwrap_or_default(),
+                record.args()
+            ),
+            LogFormat::Json => {
+                let mut fields = Map::new();
+                if let Some(timestamp) = timestamp {
+                    fields.insert("timestamp".into(), timestamp.into());
+                }
+                fields.insert("level".into(), level_name(record.level()).into());
+                fields.insert("message".into(), record.args().to_string().into());
+                if let Some(label) = label {
+                    fields.insert("label".into(), label.into());
+                }
+                let _ = record.key_values().visit(&mut FieldVisitor(&mut fields));
+                JsonValue::Object(fields).to_string()
+            }
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = self.format(record);
+        if self.stderr {
+            let _ = writeln!(std::io::stderr().lock(), "{line}");
+        }
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+            && let Err(e) = file.write_line(&line)
+            && self.stderr
+        {
+            let _ = writeln!(std::io::stderr().lock(), "writing log file: {e}");
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Prefixes the messages of another logger with the label of the watch path being handled.
+struct Labeled<L>(L);
+
+impl<L: Log> Log for Labeled<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        match janitors::label::current() {
+            Some(label) => self.0.log(
+                &record
+                    .to_builder()
+                    .args(format_args!("[{label}] {}", record.args()))
+                    .build(),
+            ),
+            None => self.0.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+/// An open log file together with its rotation settings.
+struct RotatingFile {
+    config: LogFile,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(config: LogFile) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { config, file, size })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.config.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shift all rotated files by one, dropping the oldest one, and start a new file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let rotated = |n: usize| {
+            let mut path = self.config.path.clone().into_os_string();
+            path.push(format!(".{n}"));
+            PathBuf::from(path)
+        };
+
+        if self.config.keep == 0 {
+            remove_file(&self.config.path)?;
+        } else {
+            let _ = remove_file(rotated(self.config.keep));
+            for n in (1..self.config.keep).rev() {
+                let _ = rename(rotated(n), rotated(n + 1));
+            }
+            rename(&self.config.path, rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+/// Collects the key-value pairs of a record into JSON fields.
+struct FieldVisitor<'a>(&'a mut Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.insert(key.to_string(), value.to_string().into());
+        Ok(())
+    }
+}
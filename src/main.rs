@@ -1,18 +1,39 @@
-mod bucket;
-mod config;
-mod errors;
-#[cfg(test)]
-mod tests;
-mod watch_path;
-
-use clap::Parser;
-use config::{CONFIG_PATHS, Config};
+mod daemon;
+mod event_socket;
+mod logging;
+mod metrics;
+mod systemd;
+
+use anyhow::bail;
+use clap::{ArgAction, Parser, Subcommand};
 use crossbeam::channel::Select;
+use janitors::{
+    activity, batch, bucket, config,
+    config::{CONFIG_ENV, CONFIG_PATHS, Config, Options, WatcherState},
+    deadline,
+    debounce::{self, Debouncer},
+    explain, journal, manifest, pending, preview,
+    reload::{RELOAD_DELAY, Reloader},
+    schedule, stats, throttle, units,
+};
 use notify::EventKind;
 use resolve_path::PathResolveExt;
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use janitors::{JResult, RecMode, WatchPath};
+use logging::{LogFile, LogFormat, LogTimestamp};
 
-use errors::JResult;
+const DEFAULT_LOG_FILE: &str = "/tmp/janitors.log";
+/// The exit code of a one-shot run in which some paths could not be handled.
+const FAILED_PATHS_EXIT_CODE: i32 = 2;
 
 #[derive(Parser)]
 struct Cli {
@@ -20,88 +41,591 @@ struct Cli {
     one_shot: bool,
     #[arg(
         long,
-        default_value_t = 5,
-        help = "how verbose do we want to be with logs"
+        default_value = "5",
+        value_parser = units::parse_verbosity,
+        help = "how verbose do we want to be with logs, a number from 0 or one of error, warn, info, debug and trace"
     )]
     verbosity: usize,
+    #[arg(
+        short,
+        action = ArgAction::Count,
+        conflicts_with = "verbosity",
+        help = "log more with every -v, -v only adds warnings to errors and -vvvv logs everything"
+    )]
+    verbose: u8,
+    #[arg(
+        long,
+        conflicts_with_all = ["verbosity", "verbose"],
+        help = "only log errors, same as --verbosity 0"
+    )]
+    quiet: bool,
+    #[arg(
+        long,
+        requires = "one_shot",
+        help = "print a JSON object with what the one-shot run did to the standard output"
+    )]
+    json_summary: bool,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = units::parse_duration,
+        requires = "one_shot",
+        help = "stop a one-shot run after DURATION, like 90, 30s, 15m or 2h, finishing the paths which are being handled"
+    )]
+    max_runtime: Option<Duration>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "how log records are formatted"
+    )]
+    log_format: LogFormat,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "how precise the timestamps of log records are"
+    )]
+    timestamp: LogTimestamp,
+    #[arg(
+        long,
+        conflicts_with_all = ["one_shot", "daemon", "sweep_on_start"],
+        help = "plan what a one-shot run would do and write it into the plan file instead of doing it"
+    )]
+    preview: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["one_shot", "preview", "daemon", "sweep_on_start"],
+        help = "apply the plan which --preview wrote"
+    )]
+    apply: bool,
+    #[arg(
+        long,
+        help = "forget every file recorded in the manifest before running"
+    )]
+    reset_manifest: bool,
+    #[arg(long, help = "treat configuration warnings as errors")]
+    strict: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "load the watch paths and bins of this profile, instead of the profile called default"
+    )]
+    profile: Option<String>,
+    #[arg(
+        long,
+        value_name = "MS",
+        help = "poll watch paths every MS milliseconds instead of relying on file system events"
+    )]
+    poll: Option<u64>,
+    #[arg(
+        long,
+        value_name = "MS",
+        help = "ignore a path for MS milliseconds after it was handled, to skip duplicate events"
+    )]
+    once_per: Option<u64>,
+    #[arg(
+        long,
+        conflicts_with = "one_shot",
+        help = "handle the paths inside of all watch paths once before watching them"
+    )]
+    sweep_on_start: bool,
+    #[arg(
+        long,
+        help = "leave out files which are open for writing when scanning watch paths, Linux only"
+    )]
+    skip_open_files: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "handle up to N paths and read up to N directories in parallel, replacing `workers`"
+    )]
+    jobs: Option<usize>,
+    #[arg(long, conflicts_with = "daemon", help = "ask before deleting anything")]
+    interactive: bool,
+    #[arg(
+        long,
+        conflicts_with = "one_shot",
+        help = "detach from the terminal and keep running in the background"
+    )]
+    daemon: bool,
+    #[arg(
+        long,
+        default_value = "/tmp/janitors.pid",
+        help = "where to store the PID when running as a daemon"
+    )]
+    pid_file: PathBuf,
+    #[arg(
+        long,
+        help = "also write logs to this file, defaults to /tmp/janitors.log when running as a daemon"
+    )]
+    log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "10MiB",
+        value_parser = units::parse_size,
+        help = "rotate the log file once it grows past this size, like 512KiB or 10MB"
+    )]
+    log_max_size: u64,
+    #[arg(long, default_value_t = 5, help = "how many rotated log files to keep")]
+    log_keep: usize,
+    #[arg(
+        long,
+        value_name = "SECS",
+        conflicts_with = "no_reload",
+        help = "also check every SECS seconds whether the config files were modified, for file systems without events"
+    )]
+    config_check_interval: Option<u64>,
+    #[arg(
+        long,
+        help = "don't watch the config files and never reload them, for configs which don't change"
+    )]
+    no_reload: bool,
+    #[arg(
+        long,
+        value_name = "HOST:PORT",
+        help = "serve Prometheus metrics on /metrics and a health check on /health while watching"
+    )]
+    metrics_addr: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "write a JSON line for every action to the clients of a Unix domain socket at PATH while watching"
+    )]
+    event_socket: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
     config: Option<String>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Show which bin a path would be put into and why the other bins were not picked.
+    Explain { path: PathBuf },
+    /// Undo the moves and copies recorded in the journal, newest first.
+    Undo,
+    /// Print a commented example configuration covering every setting.
+    GenerateConfig,
+    /// Print which configuration file is used and the configuration after includes, environment
+    /// variables and defaults were applied.
+    ShowConfig,
+    /// List every bin, highest priority first, with the filters, actions and destination it's
+    /// applied with.
+    ListBuckets,
+    /// Put old paths inside of the watch paths into the bins with a min_age_days once.
+    Maintain,
+    /// Put the paths inside of a directory into the configured bins once, ignoring the watch
+    /// paths of the configuration.
+    Sweep {
+        path: PathBuf,
+        #[arg(long, help = "also sweep all subdirectories")]
+        recursive: bool,
+    },
+}
+
 fn main() -> JResult {
     let cli = Cli::parse();
-    // Initialize the logging facility.
-    stderrlog::new()
-        .verbosity(stderrlog::LogLevelNum::from(cli.verbosity))
-        .timestamp(stderrlog::Timestamp::Second)
-        .module(module_path!())
-        .color(stderrlog::ColorChoice::Auto)
-        .init()?;
-
-    let config_file_path = if cli.config.is_some() {
-        cli.config.unwrap()
-    } else {
-        let mut final_path = CONFIG_PATHS[2].into();
-        for path in CONFIG_PATHS.iter() {
-            if std::fs::exists(path.resolve())? {
-                final_path = path.to_string();
-                break;
-            }
-        }
-        final_path
+    let log_file = cli
+        .log_file
+        .clone()
+        .or_else(|| cli.daemon.then(|| PathBuf::from(DEFAULT_LOG_FILE)))
+        .map(|path| LogFile {
+            path: path.resolve().into(),
+            max_size: cli.log_max_size,
+            keep: cli.log_keep,
+        });
+    if cli.daemon {
+        // The standard streams of the daemon only catch output which bypasses the logger.
+        let path = log_file
+            .as_ref()
+            .map_or(Path::new(DEFAULT_LOG_FILE), |f| &f.path);
+        daemon::daemonize(&cli.pid_file, path)?;
+    }
+
+    // Initialize the logging facility, a daemon only logs into the log file.
+    logging::init(
+        if cli.quiet {
+            0
+        } else if cli.verbose > 0 {
+            cli.verbose.into()
+        } else {
+            cli.verbosity
+        },
+        cli.log_format,
+        cli.timestamp,
+        log_file,
+        cli.daemon,
+    )?;
+
+    if let Some(Command::GenerateConfig) = &cli.command {
+        print!("{}", config::TEMPLATE);
+        return Ok(());
+    }
+
+    let config_file_path = match cli.config {
+        Some(path) => path,
+        None => find_config_file()?,
     };
 
     log::info!("using config: {}", config_file_path);
 
-    let (mut rx, mut config, mut _watcher) = Config::load(&config_file_path)?;
+    bucket::set_interactive(cli.interactive);
+    let options = Options {
+        strict: cli.strict,
+        poll_interval_ms: cli.poll,
+        once_per_ms: cli.once_per,
+        skip_open_files: cli.skip_open_files,
+        jobs: cli.jobs,
+        no_reload: cli.no_reload,
+        profile: cli.profile.clone(),
+    };
+    let (mut rx, mut config, mut _watcher) = Config::load(&config_file_path, &options)?;
     log::info!("Loaded initial configuration.");
+    if cli.reset_manifest {
+        match &config.manifest {
+            Some(manifest) => {
+                manifest::reset(manifest)?;
+                log::info!("Reset the manifest '{}'.", manifest.display());
+            }
+            None => {
+                log::warn!("there is no manifest to reset, set `manifest` in the configuration")
+            }
+        }
+    }
+    if let Some(Command::ShowConfig) = &cli.command {
+        println!("# {}", PathBuf::from(config_file_path.resolve()).display());
+        println!("{config:#?}");
+        return Ok(());
+    }
+    if let Some(Command::ListBuckets) = &cli.command {
+        for line in explain::list_buckets(&config) {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    if let Some(Command::Explain { path }) = &cli.command {
+        let path: PathBuf = path.resolve().into();
+        println!("{}:", path.display());
+        for line in explain::explain(&config, &path) {
+            println!("  {line}");
+        }
+        return Ok(());
+    }
+    if let Some(Command::Undo) = &cli.command {
+        let Some(journal) = &config.journal else {
+            bail!("there is no journal to undo, set `journal` in the configuration");
+        };
+        let (undone, skipped) = journal::undo(&journal.resolve())?;
+        log::info!("Undid {undone} actions, skipped {skipped}.");
+        return Ok(());
+    }
+    if let Some(Command::Maintain) = &cli.command {
+        config.maintain()?;
+        stats::log_summary(&config.stats());
+        return Ok(());
+    }
+    if let Some(Command::Sweep { path, recursive }) = &cli.command {
+        log::info!("Sweeping '{}'.", path.display());
+        let recursive_mode = if *recursive {
+            RecMode::Recursive
+        } else {
+            RecMode::NonRecursive
+        };
+        config.sweep(path, recursive_mode)?;
+        stats::log_summary(&config.stats());
+        return Ok(());
+    }
+    let first_run = cli.one_shot && config.confirm_first_run && {
+        let unreviewed = preview::unreviewed(&config, &config.reviewed_file());
+        for watch_path in unreviewed.iter() {
+            log::info!(
+                "'{}' never had a plan applied, previewing instead of running",
+                watch_path.path.display()
+            );
+        }
+        !unreviewed.is_empty()
+    };
+    if cli.preview || first_run {
+        log::info!("Previewing a one-shot run.");
+        let plan = preview::Plan::default();
+        let res = {
+            let _preview = preview::enter(Some(plan.clone()));
+            config.one_shot()
+        };
+        let plan = plan.entries();
+        let plan_file = config.plan_file();
+        preview::write_plan(&plan_file, &plan)?;
+        log::info!(
+            "Planned {} actions into '{}', run janitors with --apply to apply them.",
+            plan.len(),
+            plan_file.display()
+        );
+        if let Err(e) = res {
+            log::error!("{e}");
+            log::logger().flush();
+            std::process::exit(FAILED_PATHS_EXIT_CODE);
+        }
+        return Ok(());
+    }
+    if cli.apply {
+        let plan_file = config.plan_file();
+        if !plan_file.exists() {
+            bail!(
+                "there is no plan in '{}', run janitors with --preview first",
+                plan_file.display()
+            );
+        }
+        let plan = preview::read_plan(&plan_file)?;
+        let res = preview::apply(&config, &plan);
+        stats::log_summary(&config.stats());
+        preview::mark_reviewed(&config, &config.reviewed_file())?;
+        match res {
+            Ok((handled, skipped)) => {
+                log::info!("Applied the plan to {handled} paths, skipped {skipped}.");
+                std::fs::remove_file(&plan_file)?;
+            }
+            // The plan is kept, so that the paths which failed can be applied again.
+            Err(e) => {
+                log::error!("{e}");
+                log::logger().flush();
+                std::process::exit(FAILED_PATHS_EXIT_CODE);
+            }
+        }
+        return Ok(());
+    }
     if cli.one_shot {
         log::info!("Running in one-shot mode.");
-        config.one_shot()?;
+        if let Some(max_runtime) = cli.max_runtime {
+            deadline::set(max_runtime);
+        }
+        let res = config.one_shot();
+        if deadline::passed() {
+            let (handled, left) = deadline::counts();
+            log::warn!(
+                "stopped after the maximum runtime of {}s, {handled} paths were handled and {left} were left for the next run",
+                cli.max_runtime.unwrap_or_default().as_secs()
+            );
+        }
+        stats::log_summary(&config.stats());
+        if cli.json_summary {
+            println!("{}", json_summary());
+        }
+        if let Err(e) = res {
+            log::error!("{e}");
+            log::logger().flush();
+            std::process::exit(FAILED_PATHS_EXIT_CODE);
+        }
         return Ok(());
     }
 
+    // Only flip a flag on termination signals, so that the action which is currently being
+    // applied can finish before the event loop exits.
+    let terminate = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&terminate))?;
+    }
+
     let mut watchers = Vec::new();
     let mut remove_indecies = HashSet::new();
 
     config.setup_watchers(&mut watchers, &mut remove_indecies)?;
     log::info!("File watchers have been setup.");
+    // The event loop picks deferred paths up again, so throttled paths don't have to block it.
+    throttle::set_queueing(true);
+    report_watchers(&watchers, &remove_indecies);
+    systemd::ready();
+    if let Some(addr) = &cli.metrics_addr {
+        metrics::serve(addr)?;
+    }
+    if let Some(path) = &cli.event_socket {
+        event_socket::serve(path)?;
+    }
+    if let Some(file) = &config.pending_file
+        && let Err(e) = pending::resume(&config, file)
+    {
+        log::error!("resuming pending paths: {e}");
+    }
+    // Paths which arrived while janitors wasn't running are handled now that they are watched.
+    if let Err(e) = config.sweep_on_start(cli.sweep_on_start) {
+        log::error!("sweeping watch paths: {e}");
+    }
+    // What was last written into the pending file, `None` until it's written the first time.
+    let mut saved_pending = None;
     let mut sel = Select::new();
+    let mut debouncer = Debouncer::new(config.debounce_window());
+    let mut last_summary = Instant::now();
+    let mut last_rescan = Instant::now();
+    let mut last_maintenance = Instant::now();
+    let mut last_reconnect = Instant::now();
+    let mut reloader = Reloader::new(RELOAD_DELAY, &config._files.0);
+    let mut last_config_check = Instant::now();
+    let watching_since = SystemTime::now();
+    // Watch paths which were warned about for not receiving events, until they receive one.
+    let mut idle_warned = HashSet::new();
+    // The watchers which were added by the last reload and when, while they ignore their events.
+    let mut grace: Option<(Instant, HashSet<usize>)> = None;
 
     for (rx_, _, _) in watchers.iter() {
         sel.recv(rx_);
     }
 
     loop {
-        if let Ok(Ok(ev)) = rx.try_recv() {
-            if let EventKind::Modify(mev) = ev.kind {
-                log::warn!(
-                    "Config file '{}' has been modified.",
-                    ev.paths.first().unwrap().display()
-                );
+        systemd::watchdog();
+        if terminate.load(Ordering::SeqCst) {
+            log::info!("Received termination signal, shutting down.");
+            if let Err(e) = config.flush_batches(true) {
+                log::error!("flushing batches: {e}");
+            }
+            if let Err(e) = config.flush_bursts() {
+                log::error!("flushing bursts: {e}");
+            }
+            stats::log_summary(&config.stats());
+            if cli.daemon {
+                daemon::remove_pid_file(&cli.pid_file);
+            }
+            return Ok(());
+        }
+
+        if let Some(interval) = cli.config_check_interval.map(Duration::from_secs)
+            && last_config_check.elapsed() >= interval
+        {
+            if reloader.check_times(&config._files.0, Path::new(&config_file_path)) {
+                log::debug!("Config files were modified without an event.");
+            }
+            last_config_check = Instant::now();
+        }
+
+        // Editors save a file in several steps, so it's only reloaded once it stays quiet.
+        while let Ok(res) = rx.try_recv() {
+            if let Ok(ev) = res
+                && let EventKind::Modify(mev) = ev.kind
+            {
                 log::trace!("Config file modify event: {:?}", mev);
-                let res = Config::load(&config_file_path);
-                if let Err(e) = &res {
+                let path = ev.paths.first().cloned().unwrap_or_default();
+                reloader.modified(path);
+            }
+        }
+        if let Some(path) = reloader.due() {
+            log::warn!("Config file '{}' has been modified.", path.display());
+            reloader.loaded(&config._files.0);
+            let (new_rx, new_config, new_watcher) = match Config::load(&config_file_path, &options)
+            {
+                Ok(loaded) => loaded,
+                Err(e) => {
                     log::error!("reloading config: {e}");
                     log::warn!(
                         "config is not loaded, please fix the issues as soon as possible and save the config file to apply changes."
                     );
                     continue;
                 }
-                (rx, config, _watcher) = res?;
+            };
+            // The running watchers are only replaced if all of the new ones could be set up.
+            match new_config.setup_watchers(&mut watchers, &mut remove_indecies) {
+                Ok(added) => {
+                    (rx, config, _watcher) = (new_rx, new_config, new_watcher);
+                    grace = (!config.reload_grace().is_zero() && !added.is_empty())
+                        .then(|| (Instant::now(), added.into_iter().collect()));
+                    reloader.loaded(&config._files.0);
+                    debouncer.set_window(config.debounce_window());
+                }
+                Err(e) => {
+                    log::error!("setting up file watchers: {e}");
+                    log::warn!("keeping the previous configuration and its file watchers.");
+                }
+            }
 
-                let res = config.setup_watchers(&mut watchers, &mut remove_indecies);
-                if let Err(e) = &res {
-                    log::error!("setting up file watchers: {}", e);
+            report_watchers(&watchers, &remove_indecies);
+            sel = Select::new();
+            for (rx_, _, _) in watchers.iter() {
+                sel.recv(rx_);
+            }
+        }
+
+        if let Some(interval) = config.stats_interval()
+            && last_summary.elapsed() >= interval
+        {
+            stats::log_summary(&config.stats());
+            last_summary = Instant::now();
+        }
+
+        if let Some(interval) = config.rescan_interval()
+            && last_rescan.elapsed() >= interval
+        {
+            log::debug!("Rescanning all watch paths.");
+            for watch_path in config.enabled_watch_paths() {
+                if let Err(e) = config.scan(watch_path) {
+                    log::error!("rescanning '{}': {e}", watch_path.path.display());
                 }
-                res?;
+            }
+            last_rescan = Instant::now();
+        }
+
+        if let Some(interval) = config.maintain_interval()
+            && last_maintenance.elapsed() >= interval
+        {
+            if let Err(e) = config.maintain() {
+                log::error!("maintaining watch paths: {e}");
+            }
+            last_maintenance = Instant::now();
+        }
+
+        if let Some(idle) = config.idle_warning() {
+            warn_idle_watch_paths(&config, idle, watching_since, &mut idle_warned);
+        }
+
+        if let Err(e) = config.flush_batches(false) {
+            log::error!("flushing batches: {e}");
+        }
 
-                sel = Select::new();
-                for (rx_, _, _) in watchers.iter() {
-                    sel.recv(rx_);
+        if let Some(interval) = config.reconnect_interval()
+            && last_reconnect.elapsed() >= interval
+        {
+            let disconnected = remove_indecies.len();
+            let reconnected = config.reconnect_watchers(&mut watchers, &mut remove_indecies);
+            if !reconnected.is_empty() || remove_indecies.len() != disconnected {
+                report_watchers(&watchers, &remove_indecies);
+            }
+            sel = Select::new();
+            for (rx_, _, _) in watchers.iter() {
+                sel.recv(rx_);
+            }
+            // Paths which were created while nothing was watching are handled right away.
+            for idx in reconnected {
+                let watch_path = &watchers[idx].1;
+                if let Err(e) = config.scan(watch_path) {
+                    log::error!("rescanning '{}': {e}", watch_path.path.display());
                 }
             }
+            last_reconnect = Instant::now();
         }
-        let res = sel.select_timeout(Duration::from_secs(1));
+
+        let ready: Vec<_> = debouncer
+            .ready()
+            .into_iter()
+            .chain(schedule::due())
+            .collect();
+        if let Some(file) = &config.pending_file {
+            // Paths which are about to be handled are still pending until they are handled.
+            save_pending(file, &debouncer, &ready, &mut saved_pending);
+        }
+        // Paths which became ready at the same time share one call, which sets up the buckets only
+        // once. Paths which fail are logged without stopping the others.
+        for (watch_path, paths, is_file) in debounce::group(ready) {
+            let res = watch_path.handle_paths(paths, is_file, &config);
+            if let Err(e) = &res {
+                log::error!(
+                    "Error occured when handling event: {e}; make sure the destination path exists."
+                );
+            }
+        }
+
+        let reload_deadline = reloader.remaining();
+        let timeout = debouncer
+            .next_deadline()
+            .into_iter()
+            .chain(reload_deadline)
+            .fold(Duration::from_secs(1), Duration::min);
+        let res = sel.select_timeout(timeout);
         if let Ok(op) = res {
             let idx = op.index();
             let (rx_, watch_path, _) = &watchers[idx];
@@ -113,24 +637,44 @@ fn main() -> JResult {
                 let _ = op.recv(rx_);
                 continue;
             }
+            if let Some((reloaded, added)) = &grace {
+                if reloaded.elapsed() >= config.reload_grace() {
+                    grace = None;
+                } else if added.contains(&idx) {
+                    log::debug!(
+                        "Ignoring events of '{}' during the reload grace period.",
+                        watch_path.path.display()
+                    );
+                    let _ = op.recv(rx_);
+                    rx_.try_iter().for_each(drop);
+                    continue;
+                }
+            }
 
             let res = op.recv(rx_);
             match res {
-                Ok(e) => {
-                    let res = e;
-                    if let Err(e) = &res {
-                        log::error!("Notify event error: {e}");
-                        continue;
-                    }
-                    let ev = res?;
-                    let res = watch_path.handle_event(ev, &config);
-                    if let Err(e) = &res {
-                        log::error!(
-                            "Error occured when handling event: {e}; make sure the destination path exists."
-                        );
-                        continue;
+                Ok(first) => {
+                    // Take every event which is ready as well, so that the paths of a burst are
+                    // handled together.
+                    for res in std::iter::once(first).chain(rx_.try_iter()) {
+                        if let Err(e) = &res {
+                            log::error!("Notify event error: {e}");
+                            continue;
+                        }
+                        let ev = res?;
+                        metrics::record_event();
+                        if ev.need_rescan() {
+                            log::warn!(
+                                "Events of '{}' were lost, rescanning it.",
+                                watch_path.path.display()
+                            );
+                            if let Err(e) = config.scan(watch_path) {
+                                log::error!("rescanning '{}': {e}", watch_path.path.display());
+                            }
+                            continue;
+                        }
+                        watch_path.handle_event(ev, &mut debouncer, &config);
                     }
-                    res?;
                 }
                 Err(e) => {
                     log::error!("Recv error received: {e}");
@@ -138,8 +682,117 @@ fn main() -> JResult {
                         "Transmitter with index '{idx}' disconnected, ignoring all further messages."
                     );
                     remove_indecies.insert(idx);
+                    report_watchers(&watchers, &remove_indecies);
                 }
             }
         }
     }
 }
+
+/// What was done since janitors was started, for `--json-summary`.
+fn json_summary() -> serde_json::Value {
+    let total = stats::total(&stats::all());
+    serde_json::json!({
+        "moved": total.moved,
+        "copied": total.copied,
+        "deleted": total.deleted,
+        "renamed": total.renamed,
+        "linked": total.linked,
+        "extracted": total.extracted,
+        "skipped": stats::skipped(),
+        "errored": stats::failures(),
+        "bytes": total.bytes,
+    })
+}
+
+/// The configuration file named by `CONFIG_ENV`, or else the first of `CONFIG_PATHS` which
+/// exists.
+fn find_config_file() -> JResult<String> {
+    if let Ok(path) = std::env::var(CONFIG_ENV) {
+        log::info!("using config '{path}' from ${CONFIG_ENV}");
+        return Ok(path);
+    }
+    for path in CONFIG_PATHS {
+        if std::fs::exists(path.resolve())? {
+            log::info!("found config '{path}'");
+            return Ok(path.into());
+        }
+        log::info!("no config at '{path}'");
+    }
+    let fallback = CONFIG_PATHS[CONFIG_PATHS.len() - 1];
+    log::info!("none of the configs exist, falling back to '{fallback}'");
+    Ok(fallback.into())
+}
+
+/// Warn once about every watch path which didn't receive any events for `idle`, counting from
+/// `since` for watch paths which never received one.
+fn warn_idle_watch_paths(
+    config: &Config,
+    idle: Duration,
+    since: SystemTime,
+    warned: &mut HashSet<PathBuf>,
+) {
+    let last_events = activity::last_events();
+    for watch_path in config.enabled_watch_paths() {
+        let last = last_events.get(&watch_path.path).copied().unwrap_or(since);
+        let quiet = last.elapsed().unwrap_or_default();
+        if quiet < idle {
+            warned.remove(&watch_path.path);
+        } else if warned.insert(watch_path.path.clone()) {
+            log::warn!(
+                "'{}' didn't receive any events for {} minutes, its watcher may have stopped working.",
+                watch_path.path.display(),
+                quiet.as_secs() / 60
+            );
+        }
+    }
+}
+
+/// Tell the metrics which watchers are running and which of them are disconnected.
+fn report_watchers(watchers: &[WatcherState], remove_indecies: &HashSet<usize>) {
+    metrics::set_watchers(
+        watchers
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, watch_path, _))| {
+                (
+                    watch_path.path.display().to_string(),
+                    !remove_indecies.contains(&idx),
+                )
+            })
+            .collect(),
+    );
+}
+
+/// Write all paths which are waiting to be handled into the pending file, unless they are the
+/// same as the last time.
+fn save_pending(
+    file: &Path,
+    debouncer: &Debouncer,
+    ready: &[(WatchPath, PathBuf, bool)],
+    saved: &mut Option<Vec<pending::Entry>>,
+) {
+    let deferred: Vec<_> = schedule::deferred()
+        .into_iter()
+        .chain(batch::batched())
+        .collect();
+    let mut entries: Vec<pending::Entry> = debouncer
+        .tracked()
+        .chain(
+            ready
+                .iter()
+                .chain(deferred.iter())
+                .map(|(w, p, f)| (w, p.as_path(), *f)),
+        )
+        .map(|(watch_path, path, is_file)| pending::Entry::new(watch_path, path, is_file))
+        .collect();
+    entries.sort();
+    entries.dedup();
+    if saved.as_ref() == Some(&entries) {
+        return;
+    }
+    if let Err(e) = pending::save(file, &entries) {
+        log::error!("saving pending paths into '{}': {e}", file.display());
+    }
+    *saved = Some(entries);
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions, read_to_string, remove_file},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{errors::JResult, preview};
+
+/// A file name and the hash of its contents, which identify a file in a manifest.
+pub type Fingerprint = (String, String);
+
+/// A file which was handled, as recorded in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// The SHA-256 hash of the contents, in hex.
+    pub hash: String,
+    pub timestamp: String,
+}
+
+/// The entries of every manifest which was read, by manifest file. The timestamp of the most
+/// recent entry wins if a fingerprint was recorded several times.
+static MANIFESTS: Mutex<BTreeMap<PathBuf, HashMap<Fingerprint, String>>> =
+    Mutex::new(BTreeMap::new());
+
+/// The fingerprint of the file at `path`, or `None` if it's larger than `max_size` bytes, where
+/// `0` means any size, or can't be read.
+pub fn fingerprint(path: &Path, max_size: u64) -> Option<Fingerprint> {
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    let len = path.metadata().ok()?.len();
+    if max_size > 0 && len > max_size {
+        log::trace!(
+            "not hashing '{}', it's larger than {max_size} bytes",
+            path.display()
+        );
+        return None;
+    }
+    Some((name, hash_file(path).ok()?))
+}
+
+/// The SHA-256 hash of the contents of the file at `path`, in hex.
+///
+/// The file is hashed in chunks, so its contents never have to fit into memory.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// When a file with `fingerprint` was recorded in `manifest`, if ever.
+pub fn seen(manifest: &Path, fingerprint: &Fingerprint) -> JResult<Option<String>> {
+    let mut manifests = MANIFESTS.lock().expect("manifest lock poisoned");
+    if !manifests.contains_key(manifest) {
+        manifests.insert(manifest.to_owned(), read(manifest)?);
+    }
+    Ok(manifests[manifest].get(fingerprint).cloned())
+}
+
+/// Record that a file with `fingerprint` was handled just now. Nothing is recorded while a
+/// preview is running.
+pub fn record(manifest: &Path, fingerprint: Fingerprint) -> JResult {
+    if preview::is_active() {
+        return Ok(());
+    }
+    let mut manifests = MANIFESTS.lock().expect("manifest lock poisoned");
+    if !manifests.contains_key(manifest) {
+        manifests.insert(manifest.to_owned(), read(manifest)?);
+    }
+    let entry = Entry {
+        name: fingerprint.0.clone(),
+        hash: fingerprint.1.clone(),
+        timestamp: chrono::Local::now().to_rfc3339(),
+    };
+    let line = serde_json::to_string(&entry)? + "\n";
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(manifest)?
+        .write_all(line.as_bytes())?;
+    manifests
+        .get_mut(manifest)
+        .expect("manifest was read above")
+        .insert(fingerprint, entry.timestamp);
+    Ok(())
+}
+
+/// Forget every file recorded in `manifest` by removing it.
+pub fn reset(manifest: &Path) -> JResult {
+    MANIFESTS
+        .lock()
+        .expect("manifest lock poisoned")
+        .remove(manifest);
+    match remove_file(manifest) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Read the entries of `manifest`, which has none if it doesn't exist yet.
+fn read(manifest: &Path) -> JResult<HashMap<Fingerprint, String>> {
+    let contents = match read_to_string(manifest) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = HashMap::new();
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: Entry = serde_json::from_str(line)?;
+        entries.insert((entry.name, entry.hash), entry.timestamp);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,189 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        Mutex,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use janitors::{activity, errors::JResult, stats};
+
+/// How long a client may take to send its request or to read the response, so that one which
+/// sends nothing can't hold up the others.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// When the last file system event was received, in seconds since the Unix epoch, or `0`.
+static LAST_EVENT: AtomicI64 = AtomicI64::new(0);
+/// Every watched path and whether its watcher is still connected.
+static WATCHERS: Mutex<Vec<(String, bool)>> = Mutex::new(Vec::new());
+
+/// Remember that a file system event was just received.
+pub fn record_event() {
+    LAST_EVENT.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+}
+
+/// Replace the watched paths, each with whether its watcher is still connected.
+pub fn set_watchers(watchers: Vec<(String, bool)>) {
+    *WATCHERS.lock().expect("metrics lock poisoned") = watchers;
+}
+
+/// Serve the metrics on `addr` from a background thread.
+///
+/// `GET /metrics` returns them in the Prometheus text format and `GET /health` fails with a
+/// `503` status as soon as any watcher is disconnected. Binding to `addr` happens right away, so
+/// that an address which is in use is reported on startup.
+pub fn serve(addr: &str) -> JResult {
+    let listener = TcpListener::bind(addr)?;
+    log::info!(
+        "serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let res = stream.map_err(anyhow::Error::from).and_then(respond);
+            if let Err(e) = res {
+                log::debug!("answering a metrics request: {e}");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Answer a single HTTP request.
+fn respond(mut stream: TcpStream) -> JResult {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut request_line = String::new();
+    // Only the request line is looked at, a client which sends an endless one is cut off.
+    BufReader::new(Read::by_ref(&mut stream).take(8192)).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        (Some("GET"), Some("/health")) => {
+            let disconnected: Vec<String> = WATCHERS
+                .lock()
+                .expect("metrics lock poisoned")
+                .iter()
+                .filter(|(_, connected)| !connected)
+                .map(|(path, _)| path.clone())
+                .collect();
+            if disconnected.is_empty() {
+                ("200 OK", "ok\n".to_string())
+            } else {
+                (
+                    "503 Service Unavailable",
+                    format!("disconnected watchers: {}\n", disconnected.join(", ")),
+                )
+            }
+        }
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// All metrics in the Prometheus text format.
+fn render() -> String {
+    let mut out = String::new();
+    let all = stats::all();
+
+    out.push_str("# HELP janitors_paths_total Paths handled by a bin, by action.\n");
+    out.push_str("# TYPE janitors_paths_total counter\n");
+    for (name, s) in all.iter() {
+        let name = escape(name);
+        for (action, count) in [
+            ("move", s.moved),
+            ("copy", s.copied),
+            ("delete", s.deleted),
+            ("rename", s.renamed),
+            ("link", s.linked),
+            ("extract", s.extracted),
+        ] {
+            out.push_str(&format!(
+                "janitors_paths_total{{bucket=\"{name}\",action=\"{action}\"}} {count}\n"
+            ));
+        }
+    }
+
+    out.push_str("# HELP janitors_bytes_total Total size of the paths handled by a bin.\n");
+    out.push_str("# TYPE janitors_bytes_total counter\n");
+    for (name, s) in all.iter() {
+        out.push_str(&format!(
+            "janitors_bytes_total{{bucket=\"{}\"}} {}\n",
+            escape(name),
+            s.bytes
+        ));
+    }
+
+    out.push_str("# HELP janitors_failed_paths_total Paths which could not be handled.\n");
+    out.push_str("# TYPE janitors_failed_paths_total counter\n");
+    out.push_str(&format!(
+        "janitors_failed_paths_total {}\n",
+        stats::failures()
+    ));
+
+    out.push_str(
+        "# HELP janitors_last_event_timestamp_seconds When the last file system event was received.\n",
+    );
+    out.push_str("# TYPE janitors_last_event_timestamp_seconds gauge\n");
+    out.push_str(&format!(
+        "janitors_last_event_timestamp_seconds {}\n",
+        LAST_EVENT.load(Ordering::Relaxed)
+    ));
+
+    out.push_str(
+        "# HELP janitors_watch_path_last_event_timestamp_seconds When a watch path received its last event.\n",
+    );
+    out.push_str("# TYPE janitors_watch_path_last_event_timestamp_seconds gauge\n");
+    for (path, time) in activity::last_events() {
+        out.push_str(&format!(
+            "janitors_watch_path_last_event_timestamp_seconds{{path=\"{}\"}} {}\n",
+            escape(&path.display().to_string()),
+            timestamp(time)
+        ));
+    }
+
+    out.push_str(
+        "# HELP janitors_bucket_last_action_timestamp_seconds When a bin applied its last action.\n",
+    );
+    out.push_str("# TYPE janitors_bucket_last_action_timestamp_seconds gauge\n");
+    for (name, time) in activity::last_actions() {
+        out.push_str(&format!(
+            "janitors_bucket_last_action_timestamp_seconds{{bucket=\"{}\"}} {}\n",
+            escape(&name),
+            timestamp(time)
+        ));
+    }
+
+    out.push_str("# HELP janitors_watcher_up Whether the watcher of a watch path is connected.\n");
+    out.push_str("# TYPE janitors_watcher_up gauge\n");
+    for (path, connected) in WATCHERS.lock().expect("metrics lock poisoned").iter() {
+        out.push_str(&format!(
+            "janitors_watcher_up{{path=\"{}\"}} {}\n",
+            escape(path),
+            u8::from(*connected)
+        ));
+    }
+    out
+}
+
+/// Seconds since the Unix epoch.
+fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Escape a label value of the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,70 @@
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The shortest time between two desktop notifications.
+const MIN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Rate limiting state shared by all buckets.
+struct Notifier {
+    last_sent: Option<Instant>,
+    /// Actions which happened since the last notification without a notification of their own.
+    suppressed: usize,
+}
+
+static NOTIFIER: Mutex<Notifier> = Mutex::new(Notifier {
+    last_sent: None,
+    suppressed: 0,
+});
+
+/// Tell the user through a desktop notification that `path` was put into `bucket`.
+///
+/// At most one notification is shown every `MIN_INTERVAL`. Actions in between are counted and
+/// mentioned by the next notification, so that handling hundreds of files at once doesn't flood
+/// the desktop. Failing to show a notification is only logged.
+pub fn notify(path: &Path, bucket: &str) {
+    let mut notifier = NOTIFIER.lock().expect("notifier lock poisoned");
+    if notifier
+        .last_sent
+        .is_some_and(|last| last.elapsed() < MIN_INTERVAL)
+    {
+        notifier.suppressed += 1;
+        return;
+    }
+
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let mut body = format!("'{name}' put into bin '{bucket}'.");
+    if notifier.suppressed > 0 {
+        body.push_str(&format!(
+            " {} more files were handled.",
+            notifier.suppressed
+        ));
+    }
+    notifier.last_sent = Some(Instant::now());
+    notifier.suppressed = 0;
+    drop(notifier);
+
+    show(&body);
+}
+
+#[cfg(feature = "desktop-notifications")]
+fn show(body: &str) {
+    if let Err(e) = notify_rust::Notification::new()
+        .appname(env!("CARGO_PKG_NAME"))
+        .summary("janitors")
+        .body(body)
+        .show()
+    {
+        log::warn!("showing desktop notification: {e}");
+    }
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+fn show(_body: &str) {
+    log::debug!("not showing desktop notification, because janitors was built without them");
+}
@@ -0,0 +1,83 @@
+use std::{
+    fs::{read_to_string, rename, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    errors::{JError, JResult},
+    watch_path::WatchPath,
+};
+
+/// A path which is waiting to be handled, e.g. for its debounce window to pass.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Entry {
+    /// The path of the watch path which the path was found in.
+    pub watch_path: PathBuf,
+    pub path: PathBuf,
+    pub is_file: bool,
+}
+
+impl Entry {
+    pub fn new(watch_path: &WatchPath, path: &Path, is_file: bool) -> Self {
+        Self {
+            watch_path: watch_path.path.clone(),
+            path: path.to_owned(),
+            is_file,
+        }
+    }
+}
+
+/// Replace the contents of the pending file with `entries`.
+///
+/// The entries are written into a temporary file next to it first, so that a crash while
+/// writing doesn't leave a truncated file behind.
+pub fn save(file: &Path, entries: &[Entry]) -> JResult {
+    let mut temporary = file.as_os_str().to_owned();
+    temporary.push(".tmp");
+    write(&temporary, serde_json::to_string(entries)?)?;
+    rename(&temporary, file)?;
+    Ok(())
+}
+
+/// Read the entries of the pending file, which are none if it doesn't exist.
+pub fn load(file: &Path) -> JResult<Vec<Entry>> {
+    match read_to_string(file) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Handle the paths of the pending file, which were still waiting when janitors stopped.
+///
+/// Paths which don't exist anymore and paths whose watch path is not part of the configuration
+/// anymore are skipped.
+pub fn resume(config: &Config, file: &Path) -> JResult {
+    let entries = load(file)?;
+    if !entries.is_empty() {
+        log::info!("resuming {} pending paths", entries.len());
+    }
+    let results = entries.into_iter().filter_map(|entry| {
+        let Some(watch_path) = config
+            .enabled_watch_paths()
+            .find(|w| w.path == entry.watch_path)
+        else {
+            log::debug!(
+                "skipping pending '{}', its watch path is gone",
+                entry.path.display()
+            );
+            return None;
+        };
+        if !entry.path.exists() {
+            log::debug!("skipping pending '{}', it's gone", entry.path.display());
+            return None;
+        }
+        let res = watch_path.handle_paths([entry.path.clone()], entry.is_file, config);
+        Some((entry.path, res))
+    });
+    JError::combine(results)
+}
@@ -0,0 +1,220 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use resolve_path::PathResolveExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bucket::Action,
+    config::Config,
+    errors::{JError, JResult},
+    watch_path::WatchPath,
+};
+
+/// An action which a preview found would be applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub bucket: String,
+    pub action: Action,
+    pub source: PathBuf,
+    /// Where the path would end up, empty for deletions.
+    pub destination: PathBuf,
+}
+
+/// The actions planned by a preview, shared by the threads working on it.
+#[derive(Debug, Clone, Default)]
+pub struct Plan(Arc<Mutex<Vec<Entry>>>);
+
+impl Plan {
+    /// The planned actions in the order of their source paths.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = self.0.lock().expect("plan lock poisoned").clone();
+        entries.sort_by(|a, b| a.source.cmp(&b.source));
+        entries
+    }
+}
+
+thread_local! {
+    /// The plan which the current thread adds its actions to instead of applying them.
+    static PLAN: RefCell<Option<Plan>> = const { RefCell::new(None) };
+}
+
+/// Restores the plan of the current thread from before `enter` when dropped.
+pub struct Scope(Option<Plan>);
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        PLAN.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Add the actions of the current thread to `plan` instead of applying them, until the returned
+/// scope is dropped. With `None`, actions are applied again.
+pub fn enter(plan: Option<Plan>) -> Scope {
+    Scope(PLAN.with(|current| current.replace(plan)))
+}
+
+/// The plan of the current thread, if it's running a preview.
+pub fn current() -> Option<Plan> {
+    PLAN.with(|current| current.borrow().clone())
+}
+
+/// Whether the current thread is running a preview, in which case nothing may be changed on
+/// disk.
+pub fn is_active() -> bool {
+    PLAN.with(|current| current.borrow().is_some())
+}
+
+/// Record that `bucket` would apply `action` to `source`, if the current thread is running a
+/// preview.
+///
+/// Returns whether the action was planned, in which case it must not be applied.
+pub fn plan(bucket: &str, action: &Action, source: &Path, destination: &Path) -> bool {
+    let Some(plan) = current() else {
+        return false;
+    };
+    log_planned(bucket, action, source, destination);
+    plan.0.lock().expect("plan lock poisoned").push(Entry {
+        bucket: bucket.to_string(),
+        action: action.clone(),
+        source: source.to_owned(),
+        destination: destination.to_owned(),
+    });
+    true
+}
+
+/// Log that `bucket` would apply `action` to `source`, instead of applying it.
+pub fn log_planned(bucket: &str, action: &Action, source: &Path, destination: &Path) {
+    let verb = format!("{action:?}").to_lowercase();
+    match action {
+        Action::Delete => log::info!("would delete '{}' (bin '{bucket}')", source.display()),
+        _ => log::info!(
+            "would {verb} '{}' to '{}' (bin '{bucket}')",
+            source.display(),
+            destination.display()
+        ),
+    }
+}
+
+/// Write `entries` into the plan file `file`, one JSON object per line.
+pub fn write_plan(file: &Path, entries: &[Entry]) -> JResult {
+    if let Some(parent) = file.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut contents = String::new();
+    for entry in entries {
+        contents += &(serde_json::to_string(entry)? + "\n");
+    }
+    write(file, contents)?;
+    Ok(())
+}
+
+/// Read the entries of the plan file `file`.
+pub fn read_plan(file: &Path) -> JResult<Vec<Entry>> {
+    read_to_string(file)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Handle the source paths of `entries` once more, this time applying the actions.
+///
+/// Every path is handled by the watch path it was found in, just like in a one-shot run, so
+/// paths which changed since the preview may end up somewhere else. Paths which are gone are
+/// skipped and paths which were not part of the plan are left alone. Returns how many paths
+/// were handled and how many were skipped.
+pub fn apply(config: &Config, entries: &[Entry]) -> JResult<(usize, usize)> {
+    // A path which fits into several buckets has several entries, but is handled only once.
+    let mut by_watch_path: BTreeMap<PathBuf, (&WatchPath, Vec<PathBuf>)> = BTreeMap::new();
+    let mut skipped = 0;
+    for entry in entries {
+        let source = &entry.source;
+        if by_watch_path
+            .values()
+            .any(|(_, sources)| sources.contains(source))
+        {
+            continue;
+        }
+        if source.symlink_metadata().is_err() {
+            log::info!(
+                "skipping '{}', it's gone since the preview",
+                source.display()
+            );
+            skipped += 1;
+            continue;
+        }
+        let Some(watch_path) = config
+            .enabled_watch_paths()
+            .filter(|watch_path| source.starts_with(watch_path.path.resolve()))
+            .max_by_key(|watch_path| watch_path.path.resolve().components().count())
+        else {
+            log::warn!(
+                "skipping '{}', it's not inside of any watch path anymore",
+                source.display()
+            );
+            skipped += 1;
+            continue;
+        };
+        by_watch_path
+            .entry(watch_path.path.clone())
+            .or_insert_with(|| (watch_path, Vec::new()))
+            .1
+            .push(source.clone());
+    }
+
+    let handled = by_watch_path
+        .values()
+        .map(|(_, sources)| sources.len())
+        .sum();
+    JError::combine(
+        by_watch_path
+            .into_values()
+            .flat_map(|(watch_path, sources)| {
+                sources.into_iter().map(move |source| {
+                    let is_file = source.is_file();
+                    let res = watch_path.handle_paths([source.clone()], is_file, config);
+                    (source, res)
+                })
+            }),
+    )?;
+    Ok((handled, skipped))
+}
+
+/// The enabled watch paths of `config` which are not listed in the `reviewed` file, because no
+/// plan of theirs was applied yet.
+pub fn unreviewed<'a>(config: &'a Config, reviewed: &Path) -> Vec<&'a WatchPath> {
+    let listed = read_to_string(reviewed).unwrap_or_default();
+    let listed: Vec<&Path> = listed.lines().map(Path::new).collect();
+    config
+        .enabled_watch_paths()
+        .filter(|watch_path| !listed.contains(&watch_path.path.as_path()))
+        .collect()
+}
+
+/// Add every enabled watch path of `config` to the `reviewed` file.
+pub fn mark_reviewed(config: &Config, reviewed: &Path) -> JResult {
+    let mut listed: Vec<PathBuf> = read_to_string(reviewed)
+        .unwrap_or_default()
+        .lines()
+        .map(PathBuf::from)
+        .collect();
+    listed.extend(config.enabled_watch_paths().map(|w| w.path.clone()));
+    listed.sort();
+    listed.dedup();
+    if let Some(parent) = reviewed.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut contents = String::new();
+    for path in listed {
+        contents += &format!("{}\n", path.display());
+    }
+    write(reviewed, contents)?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How long the config file has to stay unchanged after it was modified before it's reloaded.
+pub const RELOAD_DELAY: Duration = Duration::from_millis(300);
+
+/// Decides when modified configuration files are reloaded.
+///
+/// Editors save a file in several steps, so every modification restarts the quiet period and the
+/// files are only reloaded once, after they stayed unchanged for `delay`.
+#[derive(Debug)]
+pub struct Reloader {
+    delay: Duration,
+    /// The config file which was modified last and when, until it's reloaded.
+    modified: Option<(PathBuf, Instant)>,
+    /// When each of the watched files was modified last, for file systems without events.
+    times: Vec<Option<SystemTime>>,
+}
+
+impl Reloader {
+    /// A reloader for `files`, the configuration file and the files it includes.
+    pub fn new(delay: Duration, files: &[PathBuf]) -> Self {
+        Self {
+            delay,
+            modified: None,
+            times: modification_times(files),
+        }
+    }
+
+    /// Remember that `path` was just modified, which restarts the quiet period.
+    pub fn modified(&mut self, path: PathBuf) {
+        self.modified = Some((path, Instant::now()));
+    }
+
+    /// Look at the modification times of `files`, and treat `config_file` as modified if any of
+    /// them changed without an event. Returns whether one did.
+    ///
+    /// Unlike an event, this doesn't restart a quiet period which is running already.
+    pub fn check_times(&mut self, files: &[PathBuf], config_file: &Path) -> bool {
+        let times = modification_times(files);
+        if times == self.times {
+            return false;
+        }
+        self.times = times;
+        self.modified
+            .get_or_insert_with(|| (config_file.to_owned(), Instant::now()));
+        true
+    }
+
+    /// The modified file, if the quiet period after its last modification has passed, after
+    /// which it's forgotten until the next modification.
+    pub fn due(&mut self) -> Option<PathBuf> {
+        match &self.modified {
+            Some((_, modified)) if modified.elapsed() >= self.delay => {
+                self.modified.take().map(|(path, _)| path)
+            }
+            _ => None,
+        }
+    }
+
+    /// How long it takes until the quiet period has passed, if a file was modified.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.modified
+            .as_ref()
+            .map(|(_, modified)| self.delay.saturating_sub(modified.elapsed()))
+    }
+
+    /// Take the modification times of the newly loaded `files`, so that they don't count as
+    /// modified.
+    pub fn loaded(&mut self, files: &[PathBuf]) {
+        self.times = modification_times(files);
+    }
+}
+
+/// When each of `files` was modified last, `None` for files which can't be read.
+pub fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
@@ -0,0 +1,110 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow};
+use chrono::{Days, Local, NaiveDateTime, NaiveTime};
+
+use crate::{errors::JResult, watch_path::WatchPath};
+
+/// A daily time window like `22:00-06:00`, which may span midnight.
+///
+/// The start is part of the window, the end is not. A window which starts and ends at the same
+/// time covers the whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl FromStr for ActiveHours {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> JResult<Self> {
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M");
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("missing '-'"))
+            .and_then(|(start, end)| Ok((parse(start)?, parse(end)?)))
+            .with_context(|| {
+                format!("invalid active hours '{s}', expected e.g. \"22:00-06:00\"")
+            })?;
+        Ok(Self { start, end })
+    }
+}
+
+impl ActiveHours {
+    /// Check whether `time` lies inside the window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start == self.end || (self.start <= time && time < self.end)
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// How long it takes from `now` until the window opens the next time.
+    pub fn until_start(&self, now: NaiveDateTime) -> Duration {
+        let mut start = now.date().and_time(self.start);
+        if start <= now {
+            start = start + Days::new(1);
+        }
+        (start - now).to_std().unwrap_or_default()
+    }
+}
+
+/// The current local time, without a time zone.
+pub fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+/// A path which is handled again once its time has come.
+struct Deferred {
+    watch_path: WatchPath,
+    path: PathBuf,
+    is_file: bool,
+    due: Instant,
+}
+
+/// Paths whose bucket was dormant when they were handled.
+static DEFERRED: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+
+/// Handle `path` again after `delay`.
+pub fn defer(watch_path: &WatchPath, path: PathBuf, is_file: bool, delay: Duration) {
+    DEFERRED
+        .lock()
+        .expect("deferred lock poisoned")
+        .push(Deferred {
+            watch_path: watch_path.clone(),
+            path,
+            is_file,
+            due: Instant::now() + delay,
+        });
+}
+
+/// Remove and return all deferred paths which are due.
+pub fn due() -> Vec<(WatchPath, PathBuf, bool)> {
+    let now = Instant::now();
+    let mut deferred = DEFERRED.lock().expect("deferred lock poisoned");
+    let (due, pending) = std::mem::take(&mut *deferred)
+        .into_iter()
+        .partition(|d: &Deferred| d.due <= now);
+    *deferred = pending;
+
+    due.into_iter()
+        .map(|d| (d.watch_path, d.path, d.is_file))
+        .collect()
+}
+
+/// All deferred paths, whether they are due or not.
+pub fn deferred() -> Vec<(WatchPath, PathBuf, bool)> {
+    DEFERRED
+        .lock()
+        .expect("deferred lock poisoned")
+        .iter()
+        .map(|d| (d.watch_path.clone(), d.path.clone(), d.is_file))
+        .collect()
+}
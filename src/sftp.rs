@@ -0,0 +1,208 @@
+use std::path::{Path, PathBuf};
+
+use crate::{bucket::OverrideAction, errors::JResult};
+
+/// A remote bucket destination of the form `sftp://[user@]host[:port]/path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpDestination {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    /// Absolute path of the destination directory on the remote host.
+    pub path: PathBuf,
+}
+
+impl SftpDestination {
+    /// Parse a bucket destination, returning `None` if it's not an `sftp://` URL.
+    ///
+    /// Without a user in the URL, the name of the local user is used.
+    pub fn parse(destination: &Path) -> Option<Self> {
+        let rest = destination.to_str()?.strip_prefix("sftp://")?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (user.to_string(), host),
+            None => (std::env::var("USER").unwrap_or_default(), authority),
+        };
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (host, 22),
+        };
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            user,
+            host: host.to_string(),
+            port,
+            path: PathBuf::from(if path.is_empty() { "/" } else { path }),
+        })
+    }
+
+    /// The URL of a path inside the remote destination, for logging.
+    pub fn url(&self, path: &Path) -> PathBuf {
+        format!(
+            "sftp://{}@{}:{}{}",
+            self.user,
+            self.host,
+            self.port,
+            path.display()
+        )
+        .into()
+    }
+
+    /// Upload a file or directory into the destination directory.
+    ///
+    /// The host has to be listed in `~/.ssh/known_hosts`. The user is authenticated with
+    /// `key_file` if given and with the SSH agent otherwise. Returns the remote path the upload
+    /// was placed at, or `None` if it was skipped because of the override action.
+    pub fn upload(
+        &self,
+        path: &Path,
+        key_file: Option<&Path>,
+        override_action: &OverrideAction,
+    ) -> JResult<Option<PathBuf>> {
+        imp::upload(self, path, key_file, override_action)
+    }
+}
+
+#[cfg(feature = "sftp")]
+mod imp {
+    use std::{
+        fs::File,
+        io::copy,
+        net::TcpStream,
+        path::{Path, PathBuf},
+        time::UNIX_EPOCH,
+    };
+
+    use anyhow::bail;
+    use resolve_path::PathResolveExt;
+    use ssh2::{CheckResult, KnownHostFileKind, Session, Sftp};
+
+    use super::SftpDestination;
+    use crate::{bucket::OverrideAction, errors::JResult};
+
+    pub fn upload(
+        destination: &SftpDestination,
+        path: &Path,
+        key_file: Option<&Path>,
+        override_action: &OverrideAction,
+    ) -> JResult<Option<PathBuf>> {
+        let session = connect(destination, key_file)?;
+        let sftp = session.sftp()?;
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        let mut target = destination.path.join(name);
+
+        if sftp.stat(&target).is_ok() {
+            let local = path.metadata()?;
+            let remote = sftp.stat(&target)?;
+            let modified = local.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+            let replace = match override_action {
+                OverrideAction::Skip => false,
+                // Uploading a directory adds to an existing one and replaces existing files.
+                OverrideAction::Overwrite | OverrideAction::Merge => true,
+                OverrideAction::KeepNewer => remote.mtime.is_none_or(|mtime| modified > mtime),
+                OverrideAction::KeepLarger => remote.size.is_none_or(|size| local.len() > size),
+                // A hashed name which is taken already holds the same file.
+                OverrideAction::HashSuffix if local.is_file() => {
+                    target = crate::bucket::hash_suffixed(path, &target)?;
+                    sftp.stat(&target).is_err()
+                }
+                OverrideAction::Rename | OverrideAction::HashSuffix => {
+                    let mut count = 1;
+                    while sftp.stat(&target).is_ok() {
+                        let mut renamed = destination.path.join(name).into_os_string();
+                        renamed.push(format!(".{count}"));
+                        target = renamed.into();
+                        count += 1;
+                    }
+                    true
+                }
+            };
+            if !replace {
+                log::info!(
+                    "skipping '{}' because '{}' already exists",
+                    path.display(),
+                    destination.url(&target).display()
+                );
+                return Ok(None);
+            }
+        }
+
+        put(&sftp, path, &target)?;
+        Ok(Some(target))
+    }
+
+    fn connect(destination: &SftpDestination, key_file: Option<&Path>) -> JResult<Session> {
+        let tcp = TcpStream::connect((destination.host.as_str(), destination.port))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+
+        let mut known_hosts = session.known_hosts()?;
+        known_hosts.read_file(
+            &PathBuf::from("~/.ssh/known_hosts".resolve()),
+            KnownHostFileKind::OpenSSH,
+        )?;
+        let Some((key, _)) = session.host_key() else {
+            bail!("'{}' did not send a host key", destination.host);
+        };
+        if !matches!(
+            known_hosts.check_port(&destination.host, destination.port, key),
+            CheckResult::Match
+        ) {
+            bail!(
+                "the host key of '{}' does not match ~/.ssh/known_hosts",
+                destination.host
+            );
+        }
+
+        match key_file {
+            Some(key_file) => {
+                session.userauth_pubkey_file(&destination.user, None, key_file, None)?
+            }
+            None => session.userauth_agent(&destination.user)?,
+        }
+        Ok(session)
+    }
+
+    /// Upload a file, or a directory with all of its contents, to `target`.
+    fn put(sftp: &Sftp, path: &Path, target: &Path) -> JResult {
+        if path.is_dir() {
+            if sftp.stat(target).is_err() {
+                sftp.mkdir(target, 0o755)?;
+            }
+            for dentry in path.read_dir()? {
+                let dentry = dentry?;
+                put(sftp, &dentry.path(), &target.join(dentry.file_name()))?;
+            }
+        } else {
+            let mut remote = sftp.create(target)?;
+            copy(&mut File::open(path)?, &mut remote)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "sftp"))]
+mod imp {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::bail;
+
+    use super::SftpDestination;
+    use crate::{bucket::OverrideAction, errors::JResult};
+
+    pub fn upload(
+        destination: &SftpDestination,
+        _path: &Path,
+        _key_file: Option<&Path>,
+        _override_action: &OverrideAction,
+    ) -> JResult<Option<PathBuf>> {
+        bail!(
+            "can't upload to '{}', because janitors was built without the `sftp` feature",
+            destination.host
+        )
+    }
+}
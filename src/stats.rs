@@ -0,0 +1,163 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::bucket::Action;
+
+/// What has been done by a single bucket since janitors was started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BucketStats {
+    pub moved: u64,
+    pub copied: u64,
+    pub deleted: u64,
+    pub renamed: u64,
+    /// Hard and symbolic links created.
+    pub linked: u64,
+    /// Archives unpacked.
+    pub extracted: u64,
+    /// Total size of all handled paths.
+    pub bytes: u64,
+    /// Total time spent applying actions.
+    pub busy: Duration,
+    /// How many actions took less than each of the `TIMING_BOUNDS_MS`, with one more entry for
+    /// actions which took longer than all of them.
+    pub timings: [u64; TIMING_BOUNDS_MS.len() + 1],
+}
+
+/// Upper bounds, in milliseconds, of the buckets of the timing histogram.
+pub const TIMING_BOUNDS_MS: [u128; 5] = [10, 100, 1000, 10_000, 60_000];
+
+/// Statistics of all buckets by bucket name.
+///
+/// They are kept across configuration reloads, so the counters of a bucket keep growing as long
+/// as its name stays the same.
+static STATS: Mutex<BTreeMap<String, BucketStats>> = Mutex::new(BTreeMap::new());
+
+/// How many paths could not be handled since janitors was started.
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// How many paths were left alone although they fit into a bucket since janitors was started.
+static SKIPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Count a path of `bytes` bytes that `action` was applied to by `bucket`, which took
+/// `elapsed`.
+pub fn record(bucket: &str, action: &Action, bytes: u64, elapsed: Duration) {
+    let mut stats = STATS.lock().expect("stats lock poisoned");
+    let entry = stats.entry(bucket.to_string()).or_default();
+    match action {
+        Action::Move => entry.moved += 1,
+        Action::Copy => entry.copied += 1,
+        Action::Delete => entry.deleted += 1,
+        Action::Rename => entry.renamed += 1,
+        Action::Hardlink | Action::Symlink => entry.linked += 1,
+        Action::Extract => entry.extracted += 1,
+    }
+    entry.bytes += bytes;
+    entry.busy += elapsed;
+    let slot = TIMING_BOUNDS_MS
+        .iter()
+        .position(|bound| elapsed.as_millis() < *bound)
+        .unwrap_or(TIMING_BOUNDS_MS.len());
+    entry.timings[slot] += 1;
+}
+
+/// The statistics of a bucket, which are all zero if it hasn't handled anything yet.
+pub fn get(bucket: &str) -> BucketStats {
+    STATS
+        .lock()
+        .expect("stats lock poisoned")
+        .get(bucket)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// The statistics of every bucket which has handled something since janitors was started,
+/// including buckets which were removed from the configuration since.
+pub fn all() -> BTreeMap<String, BucketStats> {
+    STATS.lock().expect("stats lock poisoned").clone()
+}
+
+/// Count a path which could not be handled.
+pub fn record_failure() {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many paths could not be handled since janitors was started.
+pub fn failures() -> u64 {
+    FAILURES.load(Ordering::Relaxed)
+}
+
+/// Count a path which fit into a bucket, but was left alone, e.g. because its destination was
+/// taken.
+pub fn record_skip() {
+    SKIPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many paths were left alone although they fit into a bucket since janitors was started.
+pub fn skipped() -> u64 {
+    SKIPPED.load(Ordering::Relaxed)
+}
+
+/// The statistics of all buckets of `stats` added up.
+pub fn total(stats: &BTreeMap<String, BucketStats>) -> BucketStats {
+    stats.values().fold(BucketStats::default(), |mut total, s| {
+        total.moved += s.moved;
+        total.copied += s.copied;
+        total.deleted += s.deleted;
+        total.renamed += s.renamed;
+        total.linked += s.linked;
+        total.extracted += s.extracted;
+        total.bytes += s.bytes;
+        total.busy += s.busy;
+        for (slot, count) in total.timings.iter_mut().zip(s.timings) {
+            *slot += count;
+        }
+        total
+    })
+}
+
+/// Log one line with the statistics of every bucket.
+pub fn log_summary(stats: &BTreeMap<String, BucketStats>) {
+    for (name, s) in stats.iter() {
+        log::info!(
+            bucket = name.as_str(),
+            moved = s.moved,
+            copied = s.copied,
+            deleted = s.deleted,
+            renamed = s.renamed,
+            linked = s.linked,
+            extracted = s.extracted,
+            bytes = s.bytes;
+            "bin '{name}': {} moved, {} copied, {} deleted, {} renamed, {} linked, {} extracted, {} bytes",
+            s.moved,
+            s.copied,
+            s.deleted,
+            s.renamed,
+            s.linked,
+            s.extracted,
+            s.bytes
+        );
+        let mut histogram: Vec<String> = TIMING_BOUNDS_MS
+            .iter()
+            .zip(s.timings.iter())
+            .map(|(bound, count)| format!("<{bound}ms: {count}"))
+            .collect();
+        histogram.push(format!(
+            ">={}ms: {}",
+            TIMING_BOUNDS_MS[TIMING_BOUNDS_MS.len() - 1],
+            s.timings[TIMING_BOUNDS_MS.len()]
+        ));
+        log::debug!(
+            bucket = name.as_str(),
+            busy_ms = s.busy.as_millis() as u64;
+            "bin '{name}' spent {}ms applying actions, {}",
+            s.busy.as_millis(),
+            histogram.join(", ")
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the service manager expects `watchdog` to be called, as found out by `ready`.
+static WATCHDOG: AtomicBool = AtomicBool::new(false);
+
+/// Tell systemd that janitors is watching, for services with `Type=notify`.
+///
+/// Does nothing unless janitors was built with the `systemd` feature and runs under systemd.
+pub fn ready() {
+    imp::ready(&WATCHDOG);
+}
+
+/// Tell the systemd watchdog that the event loop is still running, if the service has a
+/// `WatchdogSec`.
+pub fn watchdog() {
+    if WATCHDOG.load(Ordering::Relaxed) {
+        imp::watchdog();
+    }
+}
+
+#[cfg(feature = "systemd")]
+mod imp {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use sd_notify::NotifyState;
+
+    pub fn ready(watchdog: &AtomicBool) {
+        if let Err(e) = sd_notify::notify(&[NotifyState::Ready]) {
+            log::warn!("notifying systemd: {e}");
+        }
+        if let Some(interval) = sd_notify::watchdog_enabled() {
+            log::debug!(
+                "pinging the systemd watchdog, which expects a ping every {}ms",
+                interval.as_millis()
+            );
+            watchdog.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn watchdog() {
+        if let Err(e) = sd_notify::notify(&[NotifyState::Watchdog]) {
+            log::warn!("pinging the systemd watchdog: {e}");
+        }
+    }
+}
+
+#[cfg(not(feature = "systemd"))]
+mod imp {
+    use std::sync::atomic::AtomicBool;
+
+    pub fn ready(_watchdog: &AtomicBool) {}
+
+    pub fn watchdog() {}
+}
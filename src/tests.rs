@@ -1,4 +1,9 @@
-use crate::{bucket::Bucket, watch_path::WatchPath, *};
+use crate::{
+    bucket::{Action, Bucket, OverrideAction},
+    watch_path::WatchPath,
+    *,
+};
+use std::path::{Path, PathBuf};
 
 #[test]
 fn parse_config() {
@@ -41,6 +46,7 @@ fn parse_config() {
             path: "/some/path".into(),
             recursive_mode: watch_path::RecMode::NonRecursive,
             bucket_names: vec!["bucket1".into(), "bucket2".into(), "bucket3".into()],
+            ..Default::default()
         }]),
         bucket: Vec::from([
             Bucket {
@@ -51,7 +57,7 @@ fn parse_config() {
                 priority: 0,
                 action: bucket::Action::Copy,
                 override_action: Default::default(),
-                _regexes: Vec::new(),
+                ..Default::default()
             },
             Bucket {
                 name: "bucket2".into(),
@@ -61,7 +67,7 @@ fn parse_config() {
                 priority: 0,
                 action: bucket::Action::Move,
                 override_action: bucket::OverrideAction::Rename,
-                _regexes: Vec::new(),
+                ..Default::default()
             },
             Bucket {
                 name: "bucket3".into(),
@@ -71,12 +77,4489 @@ fn parse_config() {
                 priority: 255,
                 action: bucket::Action::Delete,
                 override_action: bucket::OverrideAction::Overwrite,
-                _regexes: Vec::new(),
+                ..Default::default()
             },
         ]),
+        ..Default::default()
     };
 
     let res = toml::from_str(input);
 
     assert_eq!(res, Ok(exp));
 }
+
+#[test]
+fn expand_env_vars() {
+    let path = std::env::var("PATH").unwrap();
+
+    let res = config::expand_env("~/$PATH/a/${PATH}b".as_ref()).unwrap();
+    assert_eq!(res, PathBuf::from(format!("~/{path}/a/{path}b")));
+
+    let res = config::expand_env("/no/variables/$".as_ref()).unwrap();
+    assert_eq!(res, PathBuf::from("/no/variables/$"));
+
+    assert!(config::expand_env("$JANITORS_SURELY_UNDEFINED/x".as_ref()).is_err());
+
+    if std::env::var_os("DOWNLOADS").is_none() {
+        let res = config::expand_env("${DOWNLOADS}/x".as_ref());
+        match dirs::download_dir() {
+            Some(dir) => assert_eq!(res.unwrap(), dir.join("x")),
+            None => assert!(matches!(
+                res.unwrap_err().downcast_ref::<errors::JError>(),
+                Some(errors::JError::UnknownUserDir(name)) if name == "DOWNLOADS"
+            )),
+        }
+    }
+}
+
+/// Create an empty, uniquely named directory for a test, which is removed when it's dropped.
+fn temp_dir(name: &str) -> TestDir {
+    let dir = tempfile::Builder::new()
+        .prefix(&format!("janitors-{name}-"))
+        .tempdir()
+        .unwrap();
+    TestDir {
+        path: dir.path().to_owned(),
+        _dir: dir,
+    }
+}
+
+/// A directory of `temp_dir`, which is used like its path.
+struct TestDir {
+    path: PathBuf,
+    _dir: tempfile::TempDir,
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl std::ops::Deref for TestDir {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+/// A temporary directory with a watch path `inbox` inside of it, for tests which run whole
+/// configurations end to end. Everything is removed when the sandbox is dropped.
+struct Sandbox {
+    dir: tempfile::TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let sandbox = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        std::fs::create_dir(sandbox.path("inbox")).unwrap();
+        sandbox
+    }
+
+    /// The absolute path of `relative` inside of the sandbox.
+    fn path(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    /// Create a file inside of the sandbox, along with its parent directories.
+    fn write(&self, relative: &str, contents: &str) -> PathBuf {
+        let path = self.path(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// An initialized configuration which watches `inbox` with all of `buckets`, whose
+    /// destinations are taken relative to the sandbox.
+    fn config(&self, buckets: Vec<Bucket>) -> Config {
+        let bucket: Vec<Bucket> = buckets
+            .into_iter()
+            .map(|b| Bucket {
+                destination: self.path(&b.destination.to_string_lossy()),
+                create_destination: true,
+                ..b
+            })
+            .collect();
+        let mut config = Config {
+            watch: vec![WatchPath {
+                path: self.path("inbox"),
+                bucket_names: bucket.iter().map(|b| b.name.clone()).collect(),
+                ..Default::default()
+            }],
+            bucket,
+            ..Default::default()
+        };
+        config.init().unwrap();
+        config
+    }
+
+    /// The paths of all files inside of the sandbox, relative to it and sorted.
+    fn files(&self) -> Vec<String> {
+        let mut files = Vec::new();
+        let mut stack = vec![self.dir.path().to_owned()];
+        while let Some(dir) = stack.pop() {
+            for dentry in std::fs::read_dir(dir).unwrap() {
+                let path = dentry.unwrap().path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else {
+                    let relative = path.strip_prefix(self.dir.path()).unwrap();
+                    files.push(relative.to_string_lossy().into_owned());
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+}
+
+#[test]
+fn sort_a_sandbox_end_to_end() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/report.pdf", "report");
+    sandbox.write("inbox/photo.jpg", "photo");
+    sandbox.write("inbox/notes.txt", "notes");
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "documents".into(),
+            destination: "documents".into(),
+            extension_filters: vec!["pdf".into()],
+            ..Default::default()
+        },
+        Bucket {
+            name: "images".into(),
+            destination: "backup".into(),
+            extension_filters: vec!["image".into()],
+            action: Action::Copy,
+            ..Default::default()
+        },
+    ]);
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "backup/photo.jpg",
+            "documents/report.pdf",
+            "inbox/notes.txt",
+            "inbox/photo.jpg"
+        ]
+    );
+
+    let late = sandbox.write("inbox/late.pdf", "late");
+    config.watch[0].handle_paths([late], true, &config).unwrap();
+    assert!(sandbox.path("documents/late.pdf").exists());
+}
+
+#[test]
+fn route_by_destination_placeholders() {
+    use std::time::{Duration, SystemTime};
+
+    let sandbox = Sandbox::new();
+    let year_2023 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_688_000_000);
+    for name in ["inbox/report.pdf", "inbox/INV-acme.txt", "inbox/notes"] {
+        std::fs::File::options()
+            .write(true)
+            .open(sandbox.write(name, "contents"))
+            .unwrap()
+            .set_modified(year_2023)
+            .unwrap();
+    }
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "archive".into(),
+            destination: "archive/{date:%Y}/{ext}".into(),
+            extension_filters: vec!["pdf".into()],
+            name_filters: vec!["^notes$".into()],
+            ..Default::default()
+        },
+        Bucket {
+            name: "invoices".into(),
+            destination: "invoices/${client}/{stem}".into(),
+            name_filters: vec!["^INV-(?P<client>[^.]+)".into()],
+            ..Default::default()
+        },
+    ]);
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "archive/2023/notes",
+            "archive/2023/pdf/report.pdf",
+            "invoices/acme/INV-acme/INV-acme.txt"
+        ]
+    );
+
+    let mut bucket = Bucket {
+        name: "typo".into(),
+        destination: "archive/{extension}".into(),
+        ..Default::default()
+    };
+    let err = bucket.init().unwrap_err().to_string();
+    assert!(err.contains("unknown placeholder in its destination: {extension}"));
+    bucket.destination = "archive/{date:%Q}".into();
+    assert!(bucket.init().is_err());
+}
+
+#[test]
+fn keep_innermost_components() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/2024/trip/beach.jpg", "beach");
+    sandbox.write("inbox/2024/forest.jpg", "forest");
+    sandbox.write("inbox/lake.jpg", "lake");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "photos".into(),
+        destination: "photos".into(),
+        extension_filters: vec!["jpg".into()],
+        keep_components: 1,
+        ..Default::default()
+    }]);
+    config.watch[0].recursive_mode = RecMode::Recursive;
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "photos/2024/forest.jpg",
+            "photos/lake.jpg",
+            "photos/trip/beach.jpg"
+        ]
+    );
+}
+
+#[test]
+fn route_by_name_captures() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/INV-acme-2024.pdf", "invoice");
+    sandbox.write("inbox/REC-globex-2024.pdf", "receipt");
+    sandbox.write("inbox/other.pdf", "other");
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "invoices".into(),
+            destination: "archive/$1".into(),
+            name_filters: vec!["^INV-([^-]+)-".into()],
+            priority: 1,
+            ..Default::default()
+        },
+        Bucket {
+            name: "receipts".into(),
+            destination: "receipts/${client}".into(),
+            name_filters: vec!["^REC-(?P<client>[^-]+)-".into()],
+            priority: 1,
+            ..Default::default()
+        },
+    ]);
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "archive/acme/INV-acme-2024.pdf",
+            "inbox/other.pdf",
+            "receipts/globex/REC-globex-2024.pdf"
+        ]
+    );
+    assert_eq!(
+        config::expand_env("/a/$1/${2}/b".as_ref()).unwrap(),
+        PathBuf::from("/a/$1/${2}/b")
+    );
+}
+
+#[test]
+fn fill_placeholders_before_captures() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/B-{date:%Y}.txt", "braces");
+    sandbox.write("inbox/D-$1.txt", "dollar");
+    let config = sandbox.config(vec![Bucket {
+        name: "captured".into(),
+        destination: "archive/$1/{stem}".into(),
+        name_filters: vec!["^[BD]-(.+)\\.txt$".into()],
+        ..Default::default()
+    }]);
+
+    // Neither captured names are taken for placeholders, nor filled in names for captures.
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "archive/$1/D-$1/D-$1.txt",
+            "archive/{date:%Y}/B-{date:%Y}/B-{date:%Y}.txt"
+        ]
+    );
+}
+
+#[test]
+fn prune_emptied_directories() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/a/b/report.pdf", "report");
+    sandbox.write("inbox/a/summary.pdf", "summary");
+    sandbox.write("inbox/c/report.pdf", "report");
+    sandbox.write("inbox/c/notes.txt", "notes");
+    std::fs::create_dir(sandbox.path("inbox/untouched")).unwrap();
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        override_action: OverrideAction::Rename,
+        ..Default::default()
+    }]);
+    config.watch[0].recursive_mode = RecMode::Recursive;
+    config.watch[0].prune_empty_dirs = true;
+
+    config.one_shot().unwrap();
+    assert!(!sandbox.path("inbox/a").exists());
+    assert!(sandbox.path("inbox/c/notes.txt").exists());
+    assert!(sandbox.path("inbox/untouched").exists());
+    assert!(sandbox.path("inbox").exists());
+
+    let last = sandbox.write("inbox/report.pdf", "report");
+    config.watch[0].handle_paths([last], true, &config).unwrap();
+    assert!(sandbox.path("inbox").exists());
+}
+
+#[test]
+fn count_skipped_paths() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/report.pdf", "new");
+    sandbox.write("documents/report.pdf", "old");
+    sandbox.write("inbox/summary.pdf", "summary");
+    let config = sandbox.config(vec![Bucket {
+        name: "count-skipped-documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        ..Default::default()
+    }]);
+    let skipped = stats::skipped();
+
+    config.one_shot().unwrap();
+    // Other tests may skip paths at the same time, so the counter only has a lower bound.
+    assert!(stats::skipped() > skipped);
+    let total = stats::total(&std::collections::BTreeMap::from([
+        (
+            "count-skipped-documents".to_string(),
+            stats::get("count-skipped-documents"),
+        ),
+        ("other".to_string(), stats::get("count-skipped-documents")),
+    ]));
+    assert_eq!((total.moved, total.bytes), (2, 14));
+    assert_eq!(
+        std::fs::read_to_string(sandbox.path("documents/report.pdf")).unwrap(),
+        "old"
+    );
+}
+
+#[test]
+fn preview_and_apply_a_plan() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/report.pdf", "report");
+    sandbox.write("inbox/photo.jpg", "photo");
+    let mut config = sandbox.config(vec![
+        Bucket {
+            name: "documents".into(),
+            destination: "documents".into(),
+            extension_filters: vec!["pdf".into()],
+            ..Default::default()
+        },
+        Bucket {
+            name: "photos".into(),
+            action: Action::Delete,
+            extension_filters: vec!["jpg".into()],
+            ..Default::default()
+        },
+    ]);
+    config.workers = 2;
+    let plan = preview::Plan::default();
+    {
+        let _preview = preview::enter(Some(plan.clone()));
+        config.one_shot().unwrap();
+    }
+    assert_eq!(sandbox.files(), ["inbox/photo.jpg", "inbox/report.pdf"]);
+    let entries = plan.entries();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|e| (e.bucket.as_str(), e.action.clone(), e.destination.clone()))
+            .collect::<Vec<_>>(),
+        [
+            ("photos", Action::Delete, PathBuf::new()),
+            (
+                "documents",
+                Action::Move,
+                sandbox.path("documents/report.pdf")
+            )
+        ]
+    );
+
+    let plan_file = sandbox.path("state/plan.jsonl");
+    preview::write_plan(&plan_file, &entries).unwrap();
+    assert_eq!(preview::read_plan(&plan_file).unwrap(), entries);
+    let late = sandbox.write("inbox/late.pdf", "late");
+    assert_eq!(preview::apply(&config, &entries).unwrap(), (2, 0));
+    assert_eq!(
+        sandbox.files(),
+        ["documents/report.pdf", "inbox/late.pdf", "state/plan.jsonl"]
+    );
+    assert_eq!(preview::apply(&config, &entries).unwrap(), (0, 2));
+    assert!(late.exists());
+
+    let reviewed = sandbox.path("state/plan.reviewed");
+    assert_eq!(preview::unreviewed(&config, &reviewed).len(), 1);
+    preview::mark_reviewed(&config, &reviewed).unwrap();
+    assert!(preview::unreviewed(&config, &reviewed).is_empty());
+}
+
+#[test]
+fn delete_empty_directories_only() {
+    let sandbox = Sandbox::new();
+    std::fs::create_dir(sandbox.path("inbox/empty")).unwrap();
+    sandbox.write("inbox/full/notes.txt", "notes");
+    let bucket = Bucket {
+        name: "cleanup".into(),
+        action: Action::Delete,
+        delete_empty_only: true,
+        ..Default::default()
+    };
+
+    bucket
+        .apply_action(&sandbox.path("inbox/empty"), false)
+        .unwrap();
+    bucket
+        .apply_action(&sandbox.path("inbox/full"), false)
+        .unwrap();
+    assert!(!sandbox.path("inbox/empty").exists());
+    assert_eq!(sandbox.files(), ["inbox/full/notes.txt"]);
+}
+
+#[test]
+fn dry_run_a_single_bucket() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/old.log", "");
+    sandbox.write("inbox/notes.txt", "");
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "logs".into(),
+            action: Action::Delete,
+            extension_filters: vec!["log".into()],
+            dry_run: true,
+            ..Default::default()
+        },
+        Bucket {
+            name: "texts".into(),
+            destination: "texts".into(),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        },
+    ]);
+
+    config.one_shot().unwrap();
+    assert_eq!(sandbox.files(), ["inbox/old.log", "texts/notes.txt"]);
+
+    // A preview plans the actions of dry-run buckets too.
+    let plan = preview::Plan::default();
+    {
+        let _scope = preview::enter(Some(plan.clone()));
+        config.one_shot().unwrap();
+    }
+    assert_eq!(plan.entries()[0].bucket, "logs");
+}
+
+#[test]
+fn skip_files_seen_in_the_manifest() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/report.pdf", "report");
+    sandbox.write("inbox/large.pdf", "large report");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        override_action: OverrideAction::Rename,
+        ..Default::default()
+    }]);
+    let manifest = sandbox.path("manifest.jsonl");
+    config.manifest = Some(manifest.clone());
+    config.manifest_max_size = 10;
+
+    config.one_shot().unwrap();
+    // The same name and contents are skipped, other contents and large files are not.
+    sandbox.write("inbox/report.pdf", "report");
+    sandbox.write("inbox/large.pdf", "large report");
+    sandbox.write("inbox/notes.pdf", "report");
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "documents/large.pdf",
+            "documents/large.pdf.1",
+            "documents/notes.pdf",
+            "documents/report.pdf",
+            "inbox/report.pdf",
+            "manifest.jsonl"
+        ]
+    );
+
+    manifest::reset(&manifest).unwrap();
+    config.one_shot().unwrap();
+    assert!(sandbox.path("documents/report.pdf.1").exists());
+}
+
+#[test]
+fn handle_empty_files() {
+    let sandbox = Sandbox::new();
+    let empty = sandbox.write("inbox/empty.pdf", "");
+    let full = sandbox.write("inbox/full.pdf", "full");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        ..Default::default()
+    }]);
+
+    config.watch[0].empty_file_action = Some(watch_path::EmptyFileAction::Skip);
+    config.one_shot().unwrap();
+    assert_eq!(sandbox.files(), ["documents/full.pdf", "inbox/empty.pdf"]);
+
+    config.watch[0].empty_file_action = Some(watch_path::EmptyFileAction::Delete);
+    config.watch[0]
+        .handle_paths([empty.clone()], true, &config)
+        .unwrap();
+    assert_eq!(sandbox.files(), ["documents/full.pdf"]);
+
+    sandbox.write("inbox/empty.pdf", "");
+    let trashed = trash::trash_into(&sandbox.path("trash"), &empty, true).unwrap();
+    assert_eq!(trashed, sandbox.path("trash/files/empty.pdf"));
+    let info = std::fs::read_to_string(sandbox.path("trash/info/empty.pdf.trashinfo")).unwrap();
+    assert!(info.contains(&format!("Path={}", empty.display())));
+
+    sandbox.write("inbox/empty.pdf", "");
+    let trashed = trash::trash_into(&sandbox.path("trash"), &empty, true).unwrap();
+    assert_eq!(trashed, sandbox.path("trash/files/empty.pdf.1"));
+    assert!(!full.exists());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn run_the_async_loop() {
+    let sandbox = Sandbox::new();
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        ..Default::default()
+    }]);
+    config.debounce_ms = 10;
+    let moved = sandbox.path("documents/report.pdf");
+
+    let shutdown = async {
+        // Give the watchers a moment to start.
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        sandbox.write("inbox/report.pdf", "report");
+        for _ in 0..100 {
+            if moved.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    };
+    async_loop::run_until(config, shutdown).await.unwrap();
+    assert!(moved.exists());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn watch_missing_paths_in_the_async_loop() {
+    let sandbox = Sandbox::new();
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        ..Default::default()
+    }]);
+    let mut missing = config.watch[0].clone();
+    missing.path = sandbox.path("later");
+    config.watch.push(missing);
+    config.reconnect_interval_secs = 1;
+    let moved = sandbox.path("documents/report.pdf");
+
+    // The missing watch path doesn't stop the others, and is watched once it exists.
+    let shutdown = async {
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        sandbox.write("later/report.pdf", "report");
+        for _ in 0..100 {
+            if moved.exists() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    };
+    async_loop::run_until(config, shutdown).await.unwrap();
+    assert!(moved.exists());
+}
+
+#[test]
+fn rename_with_name_captures() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/IMG_20240101_123456.jpg", "photo");
+    sandbox.write("inbox/SCAN-contract.pdf", "scan");
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "photos".into(),
+            name_filters: vec![r"^IMG_(\d{4})(\d{2})(\d{2})_".into(), r"^IMG_(\d+)".into()],
+            action: Action::Rename,
+            rename_template: Some("${1}-${2}-${3}.{ext}".into()),
+            ..Default::default()
+        },
+        Bucket {
+            name: "scans".into(),
+            name_filters: vec![r"^SCAN-(?P<title>\w+)".into()],
+            action: Action::Rename,
+            rename_template: Some("scan-$title.{ext}".into()),
+            ..Default::default()
+        },
+    ]);
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        ["inbox/2024-01-01.jpg", "inbox/scan-contract.pdf"]
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn skip_files_open_for_writing() {
+    let sandbox = Sandbox::new();
+    let writing = std::fs::File::create(sandbox.path("inbox/download.pdf")).unwrap();
+    sandbox.write("inbox/done.pdf", "done");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        ..Default::default()
+    }]);
+    config.skip_open_files = true;
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        ["documents/done.pdf", "inbox/download.pdf"]
+    );
+    drop(writing);
+    config.one_shot().unwrap();
+    assert!(sandbox.path("documents/download.pdf").exists());
+}
+
+#[test]
+fn override_bucket_priorities_per_watch_path() {
+    let sandbox = Sandbox::new();
+    let bucket = |name: &str, priority| Bucket {
+        name: name.into(),
+        destination: name.into(),
+        extension_filters: vec!["pdf".into()],
+        priority,
+        ..Default::default()
+    };
+    let mut config = sandbox.config(vec![bucket("documents", 1), bucket("invoices", 0)]);
+
+    let first = sandbox.write("inbox/first.pdf", "first");
+    config.watch[0]
+        .handle_paths([first], true, &config)
+        .unwrap();
+    config.watch[0].bucket_priority_overrides = [("invoices".to_string(), 2)].into();
+    let second = sandbox.write("inbox/second.pdf", "second");
+    config.watch[0]
+        .handle_paths([second], true, &config)
+        .unwrap();
+    assert_eq!(
+        sandbox.files(),
+        ["documents/first.pdf", "invoices/second.pdf"]
+    );
+
+    let explained = explain::explain(&config, &sandbox.path("inbox/third.pdf"));
+    assert!(
+        explained
+            .iter()
+            .any(|line| line.starts_with("would be put into bin 'invoices'")),
+        "{explained:?}"
+    );
+}
+
+#[test]
+fn skip_destinations_equal_to_the_source() {
+    let sandbox = Sandbox::new();
+    let report = sandbox.write("inbox/report.pdf", "report");
+    std::fs::create_dir(sandbox.path("inbox/sub")).unwrap();
+    for action in [Action::Move, Action::Copy, Action::Symlink] {
+        let bucket = Bucket {
+            name: "inbox".into(),
+            destination: sandbox.path("inbox/sub/.."),
+            action,
+            override_action: OverrideAction::Rename,
+            ..Default::default()
+        };
+        bucket.apply_action(&report, true).unwrap();
+    }
+    assert_eq!(sandbox.files(), ["inbox/report.pdf"]);
+    assert_eq!(std::fs::read_to_string(&report).unwrap(), "report");
+}
+
+#[test]
+fn include_config_files() {
+    let dir = temp_dir("include");
+    std::fs::create_dir(dir.join("conf.d")).unwrap();
+    std::fs::write(
+        dir.join("config.toml"),
+        "include = [\"conf.d/buckets.toml\"]
+        [[watch]]
+        path = \"/some/path\"
+        recursive_mode = \"non-recursive\"
+        bucket_names = [\"bucket1\"]",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("conf.d/buckets.toml"),
+        "[[bucket]]
+        name = \"bucket1\"
+        destination = \"/other/path\"
+        extension_filters = [\"zip\"]
+        name_filters = []
+        action = \"copy\"
+        priority = 0
+        override_action = \"skip\"",
+    )
+    .unwrap();
+
+    let (_, config, _) = Config::load(
+        dir.join("config.toml").to_str().unwrap(),
+        &Default::default(),
+    )
+    .unwrap();
+    assert_eq!(config.watch.len(), 1);
+    assert_eq!(config.bucket.len(), 1);
+    assert_eq!(config.bucket[0].name, "bucket1");
+
+    // Defining the same bucket in the including file is an error.
+    std::fs::write(
+        dir.join("config.toml"),
+        "include = [\"conf.d/buckets.toml\"]
+        [[bucket]]
+        name = \"bucket1\"
+        destination = \"/another/path\"
+        extension_filters = []
+        name_filters = []
+        action = \"move\"
+        priority = 0
+        override_action = \"skip\"",
+    )
+    .unwrap();
+    assert!(
+        Config::load(
+            dir.join("config.toml").to_str().unwrap(),
+            &Default::default()
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn load_without_watching_the_config() {
+    let sandbox = Sandbox::new();
+    let path = sandbox.write(
+        "config.toml",
+        "[[bucket]]
+        name = \"texts\"
+        destination = \"/texts\"
+        priority = 0",
+    );
+    let options = config::Options {
+        no_reload: true,
+        ..Default::default()
+    };
+    let (rx, config, watcher) = Config::load(path.to_str().unwrap(), &options).unwrap();
+    assert_eq!(config.bucket[0].name, "texts");
+    assert!(watcher.is_none());
+
+    std::fs::write(&path, "").unwrap();
+    assert!(
+        rx.recv_timeout(std::time::Duration::from_millis(100))
+            .is_err()
+    );
+}
+
+#[test]
+fn reject_duplicate_buckets() {
+    let dir = temp_dir("duplicate");
+    let bucket = "[[bucket]]
+        name = \"images\"
+        destination = \"/other/path\"
+        extension_filters = [\"png\"]
+        name_filters = []
+        action = \"move\"
+        priority = 0
+        override_action = \"skip\"
+        ";
+    std::fs::write(dir.join("config.toml"), bucket.repeat(2)).unwrap();
+
+    let err = Config::load(
+        dir.join("config.toml").to_str().unwrap(),
+        &Default::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::DuplicateBucket(name)) if name == "images"
+    ));
+}
+
+#[test]
+fn reject_missing_values() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir.path();
+    let path = dir.join("config.toml");
+    for (config, missing) in [
+        (
+            "[[bucket]]
+            name = \"\"
+            destination = \"/other/path\"
+            action = \"move\"
+            priority = 0
+            override_action = \"skip\"",
+            "name of a bin",
+        ),
+        (
+            "[[bucket]]
+            name = \"images\"
+            extension_filters = [\"png\"]
+            action = \"move\"
+            priority = 0
+            override_action = \"skip\"",
+            "destination of bin 'images'",
+        ),
+    ] {
+        std::fs::write(&path, config).unwrap();
+        let err = Config::load(path.to_str().unwrap(), &Default::default()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<errors::JError>(),
+            Some(errors::JError::MissingValue(value)) if value == missing
+        ));
+    }
+
+    // Buckets which only delete paths don't need a destination.
+    std::fs::write(
+        &path,
+        "[[bucket]]
+        name = \"logs\"
+        action = \"delete\"
+        priority = 0
+        override_action = \"skip\"",
+    )
+    .unwrap();
+    assert!(Config::load(path.to_str().unwrap(), &Default::default()).is_ok());
+}
+
+#[test]
+fn overlapping_buckets_in_strict_mode() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir.path();
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    let config = |second: &str| {
+        format!(
+            "[[watch]]
+            path = '{0}/inbox'
+            recursive_mode = \"non-recursive\"
+            bucket_names = [\"media\", \"{second}\"]
+
+            [[bucket]]
+            name = \"media\"
+            destination = '{0}/media'
+            extension_filters = [\"video\"]
+            target = \"files\"
+            priority = 0
+            action = \"move\"
+            override_action = \"skip\"
+
+            [[bucket]]
+            name = \"{second}\"
+            destination = '{0}/{second}'
+            extension_filters = [\"mkv\"]
+            target = \"{1}\"
+            priority = 5
+            action = \"move\"
+            override_action = \"skip\"",
+            dir.display(),
+            if second == "movies" { "files" } else { "dirs" },
+        )
+    };
+    let path = dir.join("config.toml");
+    let options = config::Options {
+        strict: true,
+        ..Default::default()
+    };
+
+    std::fs::write(&path, config("movies")).unwrap();
+    assert!(Config::load(path.to_str().unwrap(), &Default::default()).is_ok());
+    let err = Config::load(path.to_str().unwrap(), &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Bins 'movies' and 'media' both have the extension filter 'mkv' but different destinations, 'movies' wins for the paths which fit into both"
+    );
+
+    // Buckets which take different kinds of paths never fit the same path.
+    std::fs::write(&path, config("folders")).unwrap();
+    assert!(Config::load(path.to_str().unwrap(), &options).is_ok());
+}
+
+#[test]
+fn unknown_bucket_in_strict_mode() {
+    let dir = temp_dir("unknown-bucket");
+    std::fs::write(
+        dir.join("config.toml"),
+        "[[watch]]
+        path = \"/some/path\"
+        recursive_mode = \"non-recursive\"
+        bucket_names = [\"missing\"]",
+    )
+    .unwrap();
+    let path = dir.join("config.toml");
+
+    assert!(Config::load(path.to_str().unwrap(), &Default::default()).is_ok());
+    let options = config::Options {
+        strict: true,
+        ..Default::default()
+    };
+    let err = Config::load(path.to_str().unwrap(), &options).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::UnknownBucket(_, name)) if name == "missing"
+    ));
+}
+
+#[test]
+fn fill_in_default_actions() {
+    let mut config: Config = toml::from_str(
+        "default_action = \"copy\"
+        default_override_action = \"rename\"
+
+        [[watch]]
+        path = \"/watched\"
+        recursive_mode = \"non-recursive\"
+
+        [[watch.buckets]]
+        destination = \"/inline\"
+        priority = 0
+
+        [[bucket]]
+        name = \"defaults\"
+        destination = \"/defaults\"
+        priority = 0
+
+        [[bucket]]
+        name = \"own\"
+        destination = \"/own\"
+        priority = 0
+        action = \"move\"
+        override_action = \"skip\"",
+    )
+    .unwrap();
+    config.fill_bucket_defaults();
+    let defaults = &mut config.bucket[0];
+    defaults.init().unwrap();
+    assert_eq!(defaults.action, Action::Copy);
+    assert_eq!(defaults.override_action, OverrideAction::Rename);
+    let own = &mut config.bucket[1];
+    own.init().unwrap();
+    assert_eq!(own.action, Action::Move);
+    assert_eq!(own.override_action, OverrideAction::Skip);
+    let inline = &mut config.watch[0].buckets[0];
+    inline.init().unwrap();
+    assert_eq!(inline.action, Action::Copy);
+
+    // Deserialized buckets have their actions right away.
+    let bucket: Bucket = toml::from_str("destination = \"/plain\"\npriority = 0").unwrap();
+    assert_eq!(bucket.action, Action::Move);
+    assert_eq!(bucket.override_action, OverrideAction::Skip);
+    let bucket: Bucket =
+        toml::from_str("destination = \"/plain\"\npriority = 0\naction = \"copy\"").unwrap();
+    assert_eq!(bucket.action, Action::Copy);
+}
+
+#[test]
+fn select_profiles() {
+    let config: Config = toml::from_str(
+        "[[bucket]]
+        name = \"shared\"
+        destination = \"/shared\"
+        priority = 0
+        action = \"move\"
+        override_action = \"skip\"
+
+        [[profiles.default.watch]]
+        path = \"/home/downloads\"
+        recursive_mode = \"non-recursive\"
+        bucket_names = [\"shared\"]
+
+        [[profiles.work.watch]]
+        path = \"/work/inbox\"
+        recursive_mode = \"non-recursive\"
+        bucket_names = [\"shared\", \"reports\"]
+
+        [[profiles.work.bucket]]
+        name = \"reports\"
+        destination = \"/work/reports\"
+        priority = 0
+        action = \"move\"
+        override_action = \"skip\"",
+    )
+    .unwrap();
+
+    let mut default = config.clone();
+    default.select_profile(None).unwrap();
+    assert_eq!(default.watch[0].path, PathBuf::from("/home/downloads"));
+    assert_eq!(default.bucket.len(), 1);
+    assert!(default.profiles.is_empty());
+
+    let mut work = config.clone();
+    work.select_profile(Some("work")).unwrap();
+    assert_eq!(work.watch[0].path, PathBuf::from("/work/inbox"));
+    assert_eq!(work.bucket.len(), 2);
+
+    let err = config.clone().select_profile(Some("home")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unknown profile: home, the configuration has default, work"
+    );
+
+    // Without profiles, only the top-level watch paths and buckets are loaded.
+    let mut flat = Config {
+        profiles: Default::default(),
+        ..config
+    };
+    flat.select_profile(None).unwrap();
+    assert!(flat.watch.is_empty());
+}
+
+#[test]
+fn debounce_paths() {
+    let watch_path = WatchPath::default();
+
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::from_secs(60));
+    debouncer.push(&watch_path, "/a".into(), true);
+    debouncer.touch("/a".as_ref());
+    assert!(debouncer.ready().is_empty());
+    assert!(debouncer.next_deadline().is_some());
+
+    debouncer.set_window(std::time::Duration::ZERO);
+    let ready = debouncer.ready();
+    assert_eq!(ready.len(), 1);
+    assert_eq!(ready[0].1, PathBuf::from("/a"));
+    assert!(debouncer.next_deadline().is_none());
+}
+
+#[test]
+fn group_ready_paths() {
+    let downloads = WatchPath {
+        path: "/downloads".into(),
+        ..Default::default()
+    };
+    let desktop = WatchPath {
+        path: "/desktop".into(),
+        ..Default::default()
+    };
+    let ready = [
+        (downloads.clone(), PathBuf::from("/downloads/a"), true),
+        (desktop.clone(), PathBuf::from("/desktop/b"), true),
+        (downloads.clone(), PathBuf::from("/downloads/c"), false),
+        (downloads.clone(), PathBuf::from("/downloads/d"), true),
+    ];
+
+    let groups: Vec<_> = debounce::group(ready)
+        .into_iter()
+        .map(|(w, paths, is_file)| (w.path, paths, is_file))
+        .collect();
+    assert_eq!(
+        groups,
+        [
+            (
+                "/downloads".into(),
+                vec!["/downloads/a".into(), "/downloads/d".into()],
+                true
+            ),
+            ("/desktop".into(), vec!["/desktop/b".into()], true),
+            ("/downloads".into(), vec!["/downloads/c".into()], false),
+        ]
+    );
+}
+
+#[test]
+fn wait_until_stable() {
+    use std::time::Duration;
+    use watch_path::Stability;
+
+    let sandbox = Sandbox::new();
+    let file = sandbox.path("file.txt");
+    let window = Duration::from_millis(20);
+    let max_wait = Duration::from_secs(5);
+
+    assert_eq!(
+        watch_path::wait_until_stable(&file, window, max_wait).unwrap(),
+        Stability::Gone
+    );
+    std::fs::write(&file, "contents").unwrap();
+    assert_eq!(
+        watch_path::wait_until_stable(&file, window, max_wait).unwrap(),
+        Stability::Stable
+    );
+
+    // A file which keeps growing is given up on once the maximum wait has passed.
+    let growing = file.clone();
+    let writer = std::thread::spawn(move || {
+        for _ in 0..60 {
+            let mut contents = std::fs::read(&growing).unwrap();
+            contents.push(b'.');
+            std::fs::write(&growing, contents).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    });
+    let window = Duration::from_millis(100);
+    assert_eq!(
+        watch_path::wait_until_stable(&file, window, Duration::from_millis(150)).unwrap(),
+        Stability::Changing
+    );
+    writer.join().unwrap();
+}
+
+#[test]
+fn ignore_temporary_files() {
+    let mut config: Config = toml::from_str(
+        "ignore_suffixes = [\".bak\"]
+        ignore_patterns = [\"^~lock\\\\.\"]",
+    )
+    .unwrap();
+    config.init().unwrap();
+
+    assert!(config.is_ignored("/downloads/report.pdf.crdownload".as_ref()));
+    assert!(config.is_ignored("/downloads/notes.txt.bak".as_ref()));
+    assert!(config.is_ignored("/downloads/~lock.report.odt#".as_ref()));
+    assert!(!config.is_ignored("/downloads/report.pdf".as_ref()));
+
+    config.default_ignores = false;
+    assert!(!config.is_ignored("/downloads/report.pdf.crdownload".as_ref()));
+}
+
+#[test]
+fn handle_rename_events() {
+    use notify::event::{Event, EventKind, ModifyKind, RenameMode};
+
+    let dir = temp_dir("rename");
+    let watch_path = WatchPath {
+        path: dir.clone(),
+        ..Default::default()
+    };
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::ZERO);
+
+    // A file moved into the watched directory is handled like a newly created one.
+    std::fs::write(dir.join("moved.txt"), "contents").unwrap();
+    let ev = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::To)))
+        .add_path(dir.join("moved.txt"));
+    watch_path.handle_event(ev, &mut debouncer, &Config::default());
+    std::fs::create_dir(dir.join("moved_dir")).unwrap();
+    let ev = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+        .add_path("/elsewhere/moved_dir".into())
+        .add_path(dir.join("moved_dir"));
+    watch_path.handle_event(ev, &mut debouncer, &Config::default());
+
+    let mut ready = debouncer.ready();
+    ready.sort_by(|a, b| a.1.cmp(&b.1));
+    assert_eq!(ready.len(), 2);
+    assert_eq!((&ready[0].1, ready[0].2), (&dir.join("moved.txt"), true));
+    assert_eq!((&ready[1].1, ready[1].2), (&dir.join("moved_dir"), false));
+
+    // Content modifications are not new files.
+    let ev = Event::new(EventKind::Modify(ModifyKind::Any)).add_path(dir.join("moved.txt"));
+    watch_path.handle_event(ev, &mut debouncer, &Config::default());
+    assert!(debouncer.ready().is_empty());
+}
+
+#[test]
+fn exclude_dirs_anywhere() {
+    use notify::event::{CreateKind, Event, EventKind};
+
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/app/target/debug/app.log", "build");
+    sandbox.write("inbox/web/node_modules/pkg/index.log", "dependency");
+    sandbox.write("inbox/web/node_modules.log", "not a directory");
+    sandbox.write("inbox/app/run.log", "run");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "logs".into(),
+        destination: "logs".into(),
+        extension_filters: vec!["log".into()],
+        ..Default::default()
+    }]);
+    config.watch[0].recursive_mode = RecMode::Recursive;
+    config.watch[0].exclude_dirs = vec!["target".into(), "node_*".into()];
+    config.watch[0].init().unwrap();
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "inbox/app/target/debug/app.log",
+            "inbox/web/node_modules/pkg/index.log",
+            "logs/node_modules.log",
+            "logs/run.log"
+        ]
+    );
+
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::ZERO);
+    let ev = Event::new(EventKind::Create(CreateKind::File))
+        .add_path(sandbox.path("inbox/app/target/debug/app.log"));
+    config.watch[0].handle_event(ev, &mut debouncer, &config);
+    assert!(debouncer.ready().is_empty());
+
+    config.watch[0].exclude_dirs = vec!["app/target".into()];
+    assert!(config.watch[0].init().is_err());
+}
+
+#[test]
+fn mirror_removed_paths() {
+    use notify::event::{Event, EventKind, RemoveKind};
+
+    let sandbox = Sandbox::new();
+    sandbox.write("thumbs/2024/beach.jpg", "thumbnail");
+    sandbox.write("thumbs/forest.jpg", "thumbnail");
+    let watch_path = WatchPath {
+        path: sandbox.path("inbox"),
+        mirror_removals: Some(format!(
+            "{}/{{dir}}/{{stem}}.jpg",
+            sandbox.path("thumbs").display()
+        )),
+        ..Default::default()
+    };
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::ZERO);
+
+    let ev = Event::new(EventKind::Remove(RemoveKind::File))
+        .add_path(sandbox.path("inbox/2024/beach.png"))
+        .add_path(sandbox.path("inbox/lake.png"));
+    watch_path.handle_event(ev, &mut debouncer, &Config::default());
+    assert_eq!(sandbox.files(), ["thumbs/forest.jpg"]);
+
+    // The watch path itself has no mirror, and protected paths are not deleted.
+    sandbox.write("thumbs/texts/a.txt", "");
+    sandbox.write("thumbs/old/b.jpg", "");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "texts".into(),
+        destination: "thumbs/texts".into(),
+        extension_filters: vec!["txt".into()],
+        ..Default::default()
+    }]);
+    config.watch[0].mirror_removals =
+        Some(format!("{}/{{path}}", sandbox.path("thumbs").display()));
+    let ev = Event::new(EventKind::Remove(RemoveKind::Folder))
+        .add_path(sandbox.path("inbox"))
+        .add_path(sandbox.path("inbox/texts"))
+        .add_path(sandbox.path("inbox/old"));
+    config.watch[0].handle_event(ev, &mut debouncer, &config);
+    assert_eq!(sandbox.files(), ["thumbs/forest.jpg", "thumbs/texts/a.txt"]);
+    // Neither is the fixed part of the template.
+    let watch_path = WatchPath {
+        path: sandbox.path("inbox"),
+        mirror_removals: Some(format!("{}/{{dir}}", sandbox.path("thumbs").display())),
+        ..Default::default()
+    };
+    let ev = Event::new(EventKind::Remove(RemoveKind::File)).add_path(sandbox.path("inbox/a.png"));
+    watch_path.handle_event(ev, &mut debouncer, &Config::default());
+    assert_eq!(sandbox.files(), ["thumbs/forest.jpg", "thumbs/texts/a.txt"]);
+
+    // The mirror of a removed path is never relative to the working directory.
+    config.watch[0].mirror_removals = Some("thumbs/{path}".into());
+    assert!(config.watch[0].init().is_err());
+}
+
+#[test]
+fn ignore_file_excludes_paths() {
+    let dir = temp_dir("ignore-file");
+    std::fs::write(dir.join(".janitorignore"), "*.keep\nprojects/\n").unwrap();
+    let mut watch_path = WatchPath {
+        path: dir.clone(),
+        ..Default::default()
+    };
+    watch_path.init().unwrap();
+
+    assert!(watch_path.is_ignored(&dir.join(".janitorignore"), false));
+    assert!(watch_path.is_ignored(&dir.join("notes.keep"), false));
+    assert!(watch_path.is_ignored(&dir.join("projects"), true));
+    assert!(watch_path.is_ignored(&dir.join("projects/a/b.txt"), false));
+    assert!(!watch_path.is_ignored(&dir.join("notes.txt"), false));
+    assert!(!watch_path.is_ignored("/elsewhere/notes.keep".as_ref(), false));
+}
+
+#[test]
+fn skip_hidden_paths() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/.draft.txt", "");
+    sandbox.write("inbox/.cache/notes.txt", "");
+    sandbox.write("inbox/notes.txt", "");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "all".into(),
+        destination: "all".into(),
+        extension_filters: vec!["*".into()],
+        ..Default::default()
+    }]);
+    config.watch[0].recursive_mode = RecMode::Recursive;
+
+    config.one_shot().unwrap();
+    config.watch[0]
+        .handle_paths([sandbox.path("inbox/.draft.txt")], true, &config)
+        .unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "all/notes.txt",
+            "inbox/.cache/notes.txt",
+            "inbox/.draft.txt",
+        ]
+    );
+
+    config.watch[0].include_hidden = true;
+    config.watch[0]
+        .handle_paths([sandbox.path("inbox/.draft.txt")], true, &config)
+        .unwrap();
+    assert!(sandbox.path("all/.draft.txt").exists());
+}
+
+#[test]
+fn watch_path_depth() {
+    let watch_path = WatchPath {
+        path: "/watched".into(),
+        max_depth: Some(1),
+        ..Default::default()
+    };
+
+    assert_eq!(watch_path.depth("/watched/a.txt".as_ref()), Some(0));
+    assert_eq!(watch_path.depth("/watched/dir/a.txt".as_ref()), Some(1));
+    assert_eq!(watch_path.depth("/watched/dir/sub/a.txt".as_ref()), Some(2));
+    assert_eq!(watch_path.depth("/elsewhere/a.txt".as_ref()), None);
+
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::ZERO);
+    for path in ["/watched/dir/a.txt", "/watched/dir/sub/a.txt"] {
+        let ev = notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File))
+            .add_path(path.into());
+        watch_path.handle_event(ev, &mut debouncer, &Config::default());
+    }
+    let ready = debouncer.ready();
+    assert_eq!(ready.len(), 1);
+    assert_eq!(ready[0].1, PathBuf::from("/watched/dir/a.txt"));
+}
+
+#[test]
+fn limit_the_recursive_mode_by_depth() {
+    let watch_path: WatchPath =
+        toml::from_str("path = \"/watched\"\nrecursive_mode = \"depth:2\"").unwrap();
+    assert_eq!(watch_path.recursive_mode, RecMode::Depth(2));
+    assert_eq!(watch_path.depth_limit(), Some(1));
+    assert!(!watch_path.exceeds_max_depth("/watched/project/a.txt".as_ref()));
+    assert!(watch_path.exceeds_max_depth("/watched/project/src/a.txt".as_ref()));
+    for mode in ["depth:0", "depth:x", "deep"] {
+        assert!(mode.parse::<RecMode>().is_err());
+    }
+
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/top.pdf", "top");
+    sandbox.write("inbox/project/report.pdf", "report");
+    sandbox.write("inbox/project/docs/manual.pdf", "manual");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        ..Default::default()
+    }]);
+    config.watch[0].recursive_mode = RecMode::Depth(2);
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "documents/report.pdf",
+            "documents/top.pdf",
+            "inbox/project/docs/manual.pdf"
+        ]
+    );
+}
+
+#[test]
+fn skip_symlinks() {
+    let dir = temp_dir("symlinks");
+    std::fs::write(dir.join("target.txt"), "contents").unwrap();
+    std::os::unix::fs::symlink(dir.join("target.txt"), dir.join("link.txt")).unwrap();
+
+    let mut watch_path = WatchPath {
+        path: dir.clone(),
+        ..Default::default()
+    };
+    assert!(watch_path.is_skipped_symlink(&dir.join("link.txt")));
+    assert!(!watch_path.is_skipped_symlink(&dir.join("target.txt")));
+
+    watch_path.follow_symlinks = true;
+    assert!(!watch_path.is_skipped_symlink(&dir.join("link.txt")));
+}
+
+#[test]
+fn move_directory_into_bucket() {
+    let dir = temp_dir("move-dir");
+    std::fs::create_dir_all(dir.join("watched/project/src")).unwrap();
+    std::fs::write(dir.join("watched/project/src/main.rs"), "fn main() {}").unwrap();
+    std::fs::create_dir(dir.join("bucket")).unwrap();
+    let bucket = Bucket {
+        name: "projects".into(),
+        destination: dir.join("bucket"),
+        ..Default::default()
+    };
+
+    bucket
+        .apply_action(&dir.join("watched/project"), false)
+        .unwrap();
+    assert!(!dir.join("watched/project").exists());
+    assert!(dir.join("bucket/project/src/main.rs").is_file());
+}
+
+#[test]
+fn create_missing_destination() {
+    let dir = temp_dir("create-destination");
+    std::fs::write(dir.join("report.pdf"), "contents").unwrap();
+    let mut bucket = Bucket {
+        name: "documents".into(),
+        destination: dir.join("documents/2024"),
+        create_destination: false,
+        ..Default::default()
+    };
+
+    assert!(bucket.apply_action(&dir.join("report.pdf"), true).is_err());
+    bucket.create_destination = true;
+    bucket.apply_action(&dir.join("report.pdf"), true).unwrap();
+    assert!(dir.join("documents/2024/report.pdf").is_file());
+}
+
+#[test]
+fn parallel_workers_place_all_files() {
+    let dir = temp_dir("workers");
+    let mut paths = Vec::new();
+    for i in 0..50 {
+        // Every one of these collides with the others in the destination.
+        let source = dir.join(format!("watched/{i}"));
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("same.txt"), i.to_string()).unwrap();
+        paths.push(source.join("same.txt"));
+
+        std::fs::write(dir.join(format!("watched/unique-{i}.txt")), "").unwrap();
+        paths.push(dir.join(format!("watched/unique-{i}.txt")));
+    }
+
+    let config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("watched"),
+            bucket_names: vec!["texts".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            override_action: bucket::OverrideAction::Rename,
+            ..Default::default()
+        }],
+        workers: 8,
+        ..Default::default()
+    };
+    config.watch[0].handle_paths(paths, true, &config).unwrap();
+
+    let placed = std::fs::read_dir(dir.join("texts")).unwrap().count();
+    assert_eq!(placed, 100);
+    let mut contents: Vec<String> = std::fs::read_dir(dir.join("texts"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.file_name().unwrap().to_str().unwrap().starts_with("same"))
+        .map(|p| std::fs::read_to_string(p).unwrap())
+        .collect();
+    contents.sort_by_key(|c| c.parse::<u32>().unwrap());
+    assert_eq!(contents, (0..50).map(|i| i.to_string()).collect::<Vec<_>>());
+}
+
+#[test]
+fn parallel_scan_of_a_large_tree() {
+    let sandbox = Sandbox::new();
+    for a in 0..10 {
+        for b in 0..5 {
+            for c in 0..4 {
+                // Every file collides with the others in the destination.
+                sandbox.write(
+                    &format!("inbox/{a}/{b}/{c}/same.txt"),
+                    &format!("{a}-{b}-{c}"),
+                );
+            }
+        }
+        sandbox.write(&format!("inbox/{a}/notes.md"), "notes");
+    }
+    let mut config = sandbox.config(vec![Bucket {
+        name: "texts".into(),
+        destination: "texts".into(),
+        extension_filters: vec!["txt".into()],
+        override_action: bucket::OverrideAction::Rename,
+        ..Default::default()
+    }]);
+    config.watch[0].recursive_mode = RecMode::Recursive;
+    config.workers = 4;
+
+    config.one_shot().unwrap();
+    let files = sandbox.files();
+    assert_eq!(
+        files.iter().filter(|f| f.starts_with("texts/")).count(),
+        200
+    );
+    assert_eq!(files.iter().filter(|f| f.starts_with("inbox/")).count(), 10);
+    let mut contents: Vec<String> = std::fs::read_dir(sandbox.path("texts"))
+        .unwrap()
+        .map(|e| std::fs::read_to_string(e.unwrap().path()).unwrap())
+        .collect();
+    contents.sort();
+    contents.dedup();
+    assert_eq!(contents.len(), 200);
+}
+
+#[test]
+fn count_bucket_stats() {
+    let dir = temp_dir("stats");
+    std::fs::write(dir.join("a.txt"), "abc").unwrap();
+    std::fs::write(dir.join("b.txt"), "defg").unwrap();
+    let config = Config {
+        bucket: vec![Bucket {
+            // Statistics are global, so the name must not be used by other tests.
+            name: "stats-texts".into(),
+            destination: dir.join("texts"),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    for name in ["a.txt", "b.txt"] {
+        config.bucket[0]
+            .apply_action(&dir.join(name), true)
+            .unwrap();
+    }
+    let stats = config.stats()["stats-texts"];
+    assert_eq!(stats.moved, 2);
+    assert_eq!(stats.copied, 0);
+    assert_eq!(stats.bytes, 7);
+    assert_eq!(stats.timings.iter().sum::<u64>(), 2);
+}
+
+#[test]
+fn explain_bucket_choice() {
+    let dir = temp_dir("explain");
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.clone(),
+            bucket_names: vec!["all".into(), "documents".into(), "images".into()],
+            ..Default::default()
+        }],
+        bucket: vec![
+            Bucket {
+                name: "all".into(),
+                name_filters: vec![".*".into()],
+                ..Default::default()
+            },
+            Bucket {
+                name: "documents".into(),
+                extension_filters: vec!["pdf".into()],
+                priority: 10,
+                ..Default::default()
+            },
+            Bucket {
+                name: "images".into(),
+                extension_filters: vec!["png".into()],
+                priority: 10,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    let lines = explain::explain(&config, &dir.join("report.pdf"));
+    assert_eq!(
+        lines[1],
+        "would be put into bin 'documents' with action 'move', because its extension filters contain 'pdf'"
+    );
+    assert!(lines[2].starts_with("bin 'all' also matches, because its name filter '.*' matches"));
+    assert!(lines[3].starts_with("bin 'images' does not match"));
+}
+
+#[test]
+fn list_buckets_with_effective_filters() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir.path();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.to_owned(),
+            buckets: vec![Bucket {
+                name: "reports".into(),
+                name_filters: vec!["report.*".into()],
+                anchor_names: true,
+                action: Action::Copy,
+                destination: dir.join("reports"),
+                priority: 5,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "videos".into(),
+            extension_filters: vec!["video".into(), "iso".into()],
+            destination: dir.join("videos"),
+            enabled: false,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    let lines = explain::list_buckets(&config);
+    assert_eq!(lines[0], "reports (priority 5, copy)");
+    assert_eq!(
+        lines[1],
+        format!("  destination: {}", dir.join("reports").display())
+    );
+    assert_eq!(lines[2], "  name filters: ^(?:report.*)$");
+    assert_eq!(lines[3], "videos (priority 0, move, disabled)");
+    assert_eq!(
+        lines[5],
+        "  extensions: mp4, mkv, webm, mov, avi, m4v, wmv, flv, iso"
+    );
+    assert_eq!(lines.len(), 6);
+}
+
+#[test]
+fn keep_newer_and_larger_files() {
+    use std::{
+        fs::{File, read_to_string, write},
+        time::{Duration, SystemTime},
+    };
+
+    let dir = temp_dir("keep-newer");
+    let set_age = |name: &str, hours: u64| {
+        File::options()
+            .write(true)
+            .open(dir.join(name))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(hours * 3600))
+            .unwrap();
+    };
+    std::fs::create_dir_all(dir.join("reports")).unwrap();
+    write(dir.join("reports/report.txt"), "existing").unwrap();
+    set_age("reports/report.txt", 2);
+    let mut bucket = Bucket {
+        name: "reports".into(),
+        destination: dir.join("reports"),
+        override_action: OverrideAction::KeepNewer,
+        ..Default::default()
+    };
+
+    write(dir.join("report.txt"), "older").unwrap();
+    set_age("report.txt", 3);
+    bucket.apply_action(&dir.join("report.txt"), true).unwrap();
+    assert_eq!(
+        read_to_string(dir.join("reports/report.txt")).unwrap(),
+        "existing"
+    );
+
+    write(dir.join("report.txt"), "newer").unwrap();
+    bucket.apply_action(&dir.join("report.txt"), true).unwrap();
+    assert_eq!(
+        read_to_string(dir.join("reports/report.txt")).unwrap(),
+        "newer"
+    );
+
+    bucket.override_action = OverrideAction::KeepLarger;
+    write(dir.join("report.txt"), "tiny").unwrap();
+    bucket.apply_action(&dir.join("report.txt"), true).unwrap();
+    assert_eq!(
+        read_to_string(dir.join("reports/report.txt")).unwrap(),
+        "newer"
+    );
+    write(dir.join("report.txt"), "much larger").unwrap();
+    bucket.apply_action(&dir.join("report.txt"), true).unwrap();
+    assert_eq!(
+        read_to_string(dir.join("reports/report.txt")).unwrap(),
+        "much larger"
+    );
+}
+
+#[test]
+fn replace_existing_directories() {
+    let sandbox = Sandbox::new();
+    sandbox.write("albums/trip/old.jpg", "old");
+    sandbox.write("trip/new.jpg", "new");
+    let mut bucket = Bucket {
+        name: "albums".into(),
+        destination: sandbox.path("albums"),
+        override_action: OverrideAction::Overwrite,
+        ..Default::default()
+    };
+    bucket.apply_action(&sandbox.path("trip"), false).unwrap();
+    assert_eq!(sandbox.files(), ["albums/trip/new.jpg"]);
+
+    // Copies replace the directory as well, instead of mixing the old contents in.
+    sandbox.write("trip/copy.jpg", "copy");
+    bucket.action = Action::Copy;
+    bucket.apply_action(&sandbox.path("trip"), false).unwrap();
+    assert_eq!(sandbox.files(), ["albums/trip/copy.jpg", "trip/copy.jpg"]);
+
+    // A file replaces a directory of the same name.
+    std::fs::remove_dir_all(sandbox.path("trip")).unwrap();
+    sandbox.write("trip", "file");
+    bucket.action = Action::Move;
+    bucket.apply_action(&sandbox.path("trip"), true).unwrap();
+    assert_eq!(sandbox.files(), ["albums/trip"]);
+
+    bucket.override_action = OverrideAction::KeepLarger;
+    std::fs::remove_file(sandbox.path("albums/trip")).unwrap();
+    sandbox.write("albums/trip/small.jpg", "s");
+    sandbox.write("trip/large.jpg", "much larger");
+    bucket.apply_action(&sandbox.path("trip"), false).unwrap();
+    assert_eq!(sandbox.files(), ["albums/trip/large.jpg"]);
+}
+
+#[test]
+fn hash_suffix_colliding_files() {
+    let sandbox = Sandbox::new();
+    sandbox.write("reports/report.txt", "existing");
+    let bucket = Bucket {
+        name: "reports".into(),
+        destination: sandbox.path("reports"),
+        override_action: OverrideAction::HashSuffix,
+        ..Default::default()
+    };
+
+    let report = sandbox.write("inbox/report.txt", "other");
+    bucket.apply_action(&report, true).unwrap();
+    let duplicate = sandbox.write("inbox/report.txt", "other");
+    bucket.apply_action(&duplicate, true).unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "inbox/report.txt",
+            "reports/report-d9298a10.txt",
+            "reports/report.txt"
+        ]
+    );
+}
+
+#[test]
+fn publish_actions_to_the_feed() {
+    use crate::feed::{self, Outcome};
+
+    let sandbox = Sandbox::new();
+    let entries = feed::subscribe();
+    let bucket = Bucket {
+        name: "feed-documents".into(),
+        destination: sandbox.path("documents"),
+        create_destination: true,
+        ..Default::default()
+    };
+    let report = sandbox.write("inbox/report.pdf", "report");
+    bucket.apply_action(&report, true).unwrap();
+    let again = sandbox.write("inbox/report.pdf", "report");
+    bucket.apply_action(&again, true).unwrap();
+
+    // Other tests apply actions at the same time.
+    let ours: Vec<_> = entries
+        .try_iter()
+        .filter(|entry| entry.bucket == "feed-documents")
+        .collect();
+    assert_eq!(ours.len(), 2);
+    assert_eq!(ours[0].action, "move");
+    assert_eq!(ours[0].source, report);
+    assert_eq!(ours[0].destination, sandbox.path("documents/report.pdf"));
+    assert_eq!(ours[0].outcome, Outcome::Done);
+    assert_eq!(ours[1].outcome, Outcome::Skipped);
+    assert!(
+        serde_json::to_string(&ours[0])
+            .unwrap()
+            .contains("\"outcome\":\"done\"")
+    );
+}
+
+#[test]
+fn case_insensitive_name_filters() {
+    let mut bucket = Bucket {
+        name: "invoices".into(),
+        name_filters: vec!["invoice".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    assert!(!bucket.is_fitting(&"Invoice-2024.pdf").unwrap());
+
+    bucket.case_insensitive_names = true;
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&"Invoice-2024.pdf").unwrap());
+}
+
+#[test]
+fn retry_temporary_errors() {
+    use std::{cell::Cell, io};
+
+    let config = Config {
+        max_retries: 3,
+        retry_delay_ms: 1,
+        ..Default::default()
+    };
+    let attempts = Cell::new(0);
+    let res = config.with_retries("test", || {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() < 3 {
+            Err(io::Error::from(io::ErrorKind::TimedOut).into())
+        } else {
+            Ok(())
+        }
+    });
+    assert!(res.is_ok());
+    assert_eq!(attempts.get(), 3);
+
+    // Permanent errors are returned right away.
+    attempts.set(0);
+    let res: JResult = config.with_retries("test", || {
+        attempts.set(attempts.get() + 1);
+        Err(io::Error::from(io::ErrorKind::NotFound).into())
+    });
+    assert!(res.is_err());
+    assert_eq!(attempts.get(), 1);
+
+    // Temporary errors are only retried `max_retries` times.
+    attempts.set(0);
+    let res: JResult = config.with_retries("test", || {
+        attempts.set(attempts.get() + 1);
+        Err(io::Error::from(io::ErrorKind::ResourceBusy).into())
+    });
+    assert!(res.is_err());
+    assert_eq!(attempts.get(), 4);
+}
+
+#[test]
+fn move_unmatched_into_default_destination() {
+    let dir = temp_dir("default-destination");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/notes.txt"), "").unwrap();
+    std::fs::write(dir.join("inbox/photo.png"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["texts".into()],
+            default_destination: Some(dir.join("inbox/unsorted")),
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config.one_shot().unwrap();
+    assert!(dir.join("texts/notes.txt").is_file());
+    assert!(dir.join("inbox/unsorted/photo.png").is_file());
+    // The default destination is never moved into itself.
+    config.one_shot().unwrap();
+    assert!(dir.join("inbox/unsorted/photo.png").is_file());
+}
+
+#[test]
+fn keep_free_space_in_destination() {
+    let dir = temp_dir("free-space");
+    std::fs::write(dir.join("large.iso"), "contents").unwrap();
+    let mut bucket = Bucket {
+        name: "images".into(),
+        destination: dir.join("images"),
+        action: Action::Copy,
+        min_free_bytes: u64::MAX,
+        ..Default::default()
+    };
+
+    let err = bucket
+        .apply_action(&dir.join("large.iso"), true)
+        .unwrap_err();
+    assert!(err.to_string().starts_with("Not enough free space"));
+    assert!(!dir.join("images/large.iso").exists());
+
+    bucket.min_free_bytes = 1;
+    bucket.apply_action(&dir.join("large.iso"), true).unwrap();
+    assert!(dir.join("images/large.iso").is_file());
+}
+
+#[test]
+fn split_watch_path_with_multiple_paths() {
+    let input = "
+        [[watch]]
+        paths = [\"/downloads/one\", \"/downloads/two\"]
+        recursive_mode = \"recursive\"
+        bucket_names = [\"bucket\"]
+
+        [[watch]]
+        recursive_mode = \"recursive\"
+        bucket_names = [\"bucket\"]
+        ";
+
+    let mut config: Config = toml::from_str(input).unwrap();
+    let err = config.init().unwrap_err();
+    assert_eq!(err.to_string(), "Missing value: path");
+
+    config.watch.pop();
+    config.init().unwrap();
+    let paths: Vec<_> = config.watch.iter().map(|w| w.path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("/downloads/one"),
+            PathBuf::from("/downloads/two")
+        ]
+    );
+    assert!(config.watch.iter().all(|w| w.paths.is_empty()
+        && w.recursive_mode == watch_path::RecMode::Recursive
+        && w.bucket_names == ["bucket"]));
+}
+
+#[test]
+fn keep_unchanged_watchers_on_reload() {
+    let dir = temp_dir("reconcile-watchers");
+    for name in ["a", "b", "c"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+    }
+    let watch_path = |name: &str, bucket: &str| WatchPath {
+        path: dir.join(name),
+        bucket_names: vec![bucket.into()],
+        ..Default::default()
+    };
+    let mut watchers = Vec::new();
+    let mut remove_indecies = std::collections::HashSet::new();
+
+    let config = Config {
+        watch: vec![watch_path("a", "old"), watch_path("b", "old")],
+        ..Default::default()
+    };
+    config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    let rx_a = watchers[0].0.clone();
+    let rx_b = watchers[1].0.clone();
+
+    let config = Config {
+        watch: vec![watch_path("c", "new"), watch_path("a", "new")],
+        ..Default::default()
+    };
+    let added = config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    // Only the watcher of `c` is new, so only it ignores events during `reload_grace_ms`.
+    assert_eq!(added, [0]);
+    assert_eq!(watchers.len(), 2);
+    assert!(!watchers[0].0.same_channel(&rx_a));
+    assert!(watchers[1].0.same_channel(&rx_a));
+    assert_eq!(watchers[1].1.bucket_names, ["new"]);
+    assert!(watchers.iter().all(|(rx, _, _)| !rx.same_channel(&rx_b)));
+}
+
+#[test]
+fn poll_watch_paths() {
+    let dir = temp_dir("poll");
+    let mut watchers = Vec::new();
+    let mut remove_indecies = std::collections::HashSet::new();
+    let config = Config {
+        watch: vec![WatchPath {
+            path: dir.clone(),
+            poll_interval_ms: Some(10),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    assert!(matches!(watchers[0].2, config::FileWatcher::Poll(_)));
+    assert_eq!(watchers[0].2.kind(), notify::WatcherKind::PollWatcher);
+    assert!(!config::natively_recursive(watchers[0].2.kind()));
+
+    std::fs::write(dir.join("new.txt"), "").unwrap();
+    let event = watchers[0]
+        .0
+        .recv_timeout(std::time::Duration::from_secs(5))
+        .unwrap()
+        .unwrap();
+    assert!(event.paths.contains(&dir.join("new.txt")));
+}
+
+#[test]
+fn run_action_hooks() {
+    let dir = temp_dir("hooks");
+    std::fs::write(dir.join("it's a file.txt"), "").unwrap();
+    let mut bucket = Bucket {
+        name: "texts".into(),
+        destination: dir.join("texts"),
+        pre_hook: Some("exit 3".into()),
+        ..Default::default()
+    };
+
+    let err = bucket
+        .apply_action(&dir.join("it's a file.txt"), true)
+        .unwrap_err();
+    assert!(err.to_string().starts_with("Hook 'exit 3' failed"));
+    assert!(dir.join("it's a file.txt").is_file());
+
+    bucket.pre_hook = Some("test -f {path}".into());
+    bucket.post_hook = Some("cp {destination} {destination}.copy".into());
+    bucket
+        .apply_action(&dir.join("it's a file.txt"), true)
+        .unwrap();
+    assert!(dir.join("texts/it's a file.txt").is_file());
+    assert!(dir.join("texts/it's a file.txt.copy").is_file());
+
+    // A failing post hook doesn't fail the action.
+    std::fs::write(dir.join("other.txt"), "").unwrap();
+    bucket.post_hook = Some("false".into());
+    bucket.apply_action(&dir.join("other.txt"), true).unwrap();
+    assert!(dir.join("texts/other.txt").is_file());
+}
+
+#[test]
+fn parse_sftp_destination() {
+    use sftp::SftpDestination;
+
+    let destination =
+        SftpDestination::parse("sftp://me@nas.local:2222/srv/files".as_ref()).unwrap();
+    assert_eq!(
+        destination,
+        SftpDestination {
+            user: "me".into(),
+            host: "nas.local".into(),
+            port: 2222,
+            path: "/srv/files".into(),
+        }
+    );
+    let destination = SftpDestination::parse("sftp://nas.local".as_ref()).unwrap();
+    assert_eq!((destination.port, destination.path), (22, "/".into()));
+
+    assert_eq!(SftpDestination::parse("/srv/files".as_ref()), None);
+    assert_eq!(SftpDestination::parse("sftp:///srv/files".as_ref()), None);
+}
+
+#[test]
+fn active_hours_window() {
+    use chrono::NaiveTime;
+    use schedule::ActiveHours;
+
+    let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    let night: ActiveHours = "22:00-06:00".parse().unwrap();
+    assert!(night.contains(time(23, 30)));
+    assert!(night.contains(time(2, 0)));
+    assert!(!night.contains(time(6, 0)));
+    assert!(!night.contains(time(12, 0)));
+    let day: ActiveHours = "09:00 - 17:30".parse().unwrap();
+    assert!(day.contains(time(9, 0)));
+    assert!(!day.contains(time(17, 30)));
+    assert!("22:00".parse::<ActiveHours>().is_err());
+    assert!("25:00-06:00".parse::<ActiveHours>().is_err());
+
+    let noon = chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+        .unwrap()
+        .and_time(time(12, 0));
+    assert_eq!(night.until_start(noon).as_secs(), 10 * 3600);
+    assert_eq!(day.until_start(noon).as_secs(), 21 * 3600);
+
+    // Dormant buckets are skipped.
+    let dir = temp_dir("active-hours");
+    std::fs::write(dir.join("old.log"), "").unwrap();
+    let now = schedule::now().time();
+    let hours = |from: i64, to: i64| {
+        let format = |offset| (now + chrono::Duration::hours(offset)).format("%H:%M");
+        format!("{}-{}", format(from), format(to))
+    };
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.clone(),
+            bucket_names: vec!["logs".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "logs".into(),
+            extension_filters: vec!["log".into()],
+            action: Action::Delete,
+            active_hours: Some(hours(1, 2)),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+    config.one_shot().unwrap();
+    assert!(dir.join("old.log").exists());
+
+    config.bucket[0].active_hours = Some(hours(-1, 1));
+    config.init().unwrap();
+    config.one_shot().unwrap();
+    assert!(!dir.join("old.log").exists());
+}
+
+#[test]
+fn bucket_targets() {
+    let dir = temp_dir("targets");
+    std::fs::create_dir_all(dir.join("watched/project")).unwrap();
+    std::fs::write(dir.join("watched/project.txt"), "").unwrap();
+    let bucket = |name: &str, target| Bucket {
+        name: name.into(),
+        destination: dir.join(name),
+        name_filters: vec!["^project".into()],
+        target,
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("watched"),
+            bucket_names: vec!["files".into(), "dirs".into()],
+            ..Default::default()
+        }],
+        bucket: vec![
+            bucket("files", bucket::Target::Files),
+            bucket("dirs", bucket::Target::Dirs),
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config.one_shot().unwrap();
+    assert!(dir.join("files/project.txt").is_file());
+    assert!(dir.join("dirs/project").is_dir());
+    assert!(!dir.join("dirs/project.txt").exists());
+    assert!(!dir.join("files/project").exists());
+}
+
+#[test]
+fn refuse_to_delete_protected_paths() {
+    let dir = temp_dir("protected");
+    std::fs::create_dir_all(dir.join("watched/projects/archive")).unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("watched"),
+            bucket_names: vec!["archive".into(), "trash".into()],
+            ..Default::default()
+        }],
+        bucket: vec![
+            Bucket {
+                name: "archive".into(),
+                destination: dir.join("watched/projects/archive"),
+                ..Default::default()
+            },
+            Bucket {
+                name: "trash".into(),
+                name_filters: vec!["projects".into()],
+                action: Action::Delete,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    assert!(config.ensure_deletable(&dir).is_err());
+    assert!(config.ensure_deletable(&dir.join("watched")).is_err());
+    assert!(config.ensure_deletable(&dir.join("watched/other")).is_ok());
+
+    let err = config.one_shot().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::FailedPaths(failed))
+            if failed.len() == 1 && failed[0].1.starts_with("Refusing to delete")
+    ));
+    assert!(dir.join("watched/projects/archive").is_dir());
+}
+
+#[test]
+fn undo_journaled_actions() {
+    let dir = temp_dir("journal");
+    std::fs::create_dir_all(dir.join("watched")).unwrap();
+    for name in ["a.txt", "b.txt", "c.pdf"] {
+        std::fs::write(dir.join("watched").join(name), name).unwrap();
+    }
+    let bucket = |name: &str, extension: &str, action| Bucket {
+        name: name.into(),
+        destination: dir.join(name),
+        extension_filters: vec![extension.into()],
+        action,
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("watched"),
+            bucket_names: vec!["texts".into(), "documents".into()],
+            ..Default::default()
+        }],
+        bucket: vec![
+            bucket("texts", "txt", Action::Move),
+            bucket("documents", "pdf", Action::Copy),
+        ],
+        journal: Some(dir.join("journal.jsonl")),
+        ..Default::default()
+    };
+    config.init().unwrap();
+    config.one_shot().unwrap();
+    assert!(dir.join("texts/a.txt").is_file());
+    assert!(dir.join("documents/c.pdf").is_file());
+
+    // Modified files are left alone.
+    std::fs::write(dir.join("texts/b.txt"), "modified").unwrap();
+    let (undone, skipped) = journal::undo(&dir.join("journal.jsonl")).unwrap();
+    assert_eq!((undone, skipped), (2, 1));
+    assert!(dir.join("watched/a.txt").is_file());
+    assert!(!dir.join("texts/a.txt").exists());
+    assert!(dir.join("texts/b.txt").is_file());
+    assert!(!dir.join("documents/c.pdf").exists());
+    assert!(dir.join("watched/c.pdf").is_file());
+
+    assert_eq!(journal::undo(&dir.join("journal.jsonl")).unwrap(), (0, 0));
+}
+
+#[test]
+fn flatten_directories() {
+    let dir = temp_dir("flatten");
+    std::fs::create_dir_all(dir.join("shots/2024/january")).unwrap();
+    std::fs::write(dir.join("shots/one.png"), "").unwrap();
+    std::fs::write(dir.join("shots/2024/january/two.png"), "").unwrap();
+    std::fs::create_dir_all(dir.join("screenshots")).unwrap();
+    std::fs::write(dir.join("screenshots/one.png"), "").unwrap();
+    let mut bucket = Bucket {
+        name: "screenshots".into(),
+        destination: dir.join("screenshots"),
+        override_action: OverrideAction::Rename,
+        flatten: true,
+        ..Default::default()
+    };
+
+    bucket.apply_action(&dir.join("shots"), false).unwrap();
+    assert!(dir.join("screenshots/one.png.1").is_file());
+    assert!(dir.join("screenshots/two.png").is_file());
+    assert!(dir.join("shots/2024/january").is_dir());
+
+    std::fs::write(dir.join("shots/2024/three.png"), "").unwrap();
+    bucket.remove_flattened_dirs = true;
+    bucket.apply_action(&dir.join("shots"), false).unwrap();
+    assert!(dir.join("screenshots/three.png").is_file());
+    assert!(!dir.join("shots").exists());
+}
+
+#[test]
+fn rename_in_place() {
+    let dir = temp_dir("rename-action");
+    for name in ["IMG_1234.jpg", "IMG_1235.jpg", "photo-1.jpg"] {
+        std::fs::write(dir.join(name), name).unwrap();
+    }
+    let mut bucket = Bucket {
+        name: "photos".into(),
+        action: Action::Rename,
+        ..Default::default()
+    };
+    assert!(bucket.init().is_err());
+
+    bucket.rename_template = Some("photo-{counter}.{ext}".into());
+    bucket.init().unwrap();
+    bucket
+        .apply_action(&dir.join("IMG_1234.jpg"), true)
+        .unwrap();
+    bucket
+        .apply_action(&dir.join("IMG_1235.jpg"), true)
+        .unwrap();
+    // Files which have the right name already are left alone.
+    bucket.apply_action(&dir.join("photo-1.jpg"), true).unwrap();
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("photo-1.jpg"), "photo-1.jpg");
+    assert_eq!(read("photo-2.jpg"), "IMG_1234.jpg");
+    assert_eq!(read("photo-3.jpg"), "IMG_1235.jpg");
+
+    let date = chrono::Local::now().format("%Y-%m-%d");
+    bucket.rename_template = Some("{date} {stem}.{ext}".into());
+    bucket.apply_action(&dir.join("photo-1.jpg"), true).unwrap();
+    assert!(dir.join(format!("{date} photo-1.jpg")).is_file());
+}
+
+#[test]
+fn match_name_filters_against_full_path() {
+    let mut bucket = Bucket {
+        name: "invoices".into(),
+        name_filters: vec!["/invoices/".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    assert!(!bucket.is_fitting(&"/home/me/invoices/march.pdf").unwrap());
+
+    bucket.match_full_path = true;
+    assert!(bucket.is_fitting(&"/home/me/invoices/march.pdf").unwrap());
+    assert!(!bucket.is_fitting(&"/home/me/reports/march.pdf").unwrap());
+}
+
+#[test]
+fn parse_config_template() {
+    let mut config: Config = toml::from_str(config::TEMPLATE).unwrap();
+    config.init().unwrap();
+    assert_eq!(config.watch.len(), 1);
+    assert_eq!(config.bucket.len(), 3);
+
+    // Every commented out setting is valid as well.
+    let uncommented: String = config::TEMPLATE
+        .lines()
+        .map(|line| match line.strip_prefix("# ") {
+            Some(setting) if setting.contains(" = ") => setting,
+            _ => line,
+        })
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let mut config: Config = toml::from_str(&uncommented).unwrap();
+    config.init().unwrap();
+    assert_eq!(config.watch.len(), 2);
+    assert_eq!(
+        config.bucket[0].rename_template.as_deref(),
+        Some("{date}-{stem}.{ext}")
+    );
+}
+
+#[test]
+fn override_bucket_action() {
+    let dir = temp_dir("action-override");
+    for name in ["moved", "copied"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        std::fs::write(dir.join(name).join(format!("{name}.txt")), "").unwrap();
+    }
+    let watch_path = |name: &str, action_override| WatchPath {
+        path: dir.join(name),
+        bucket_names: vec!["texts".into()],
+        action_override,
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![
+            watch_path("moved", None),
+            watch_path("copied", Some(Action::Copy)),
+        ],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config.one_shot().unwrap();
+    assert!(dir.join("texts/moved.txt").is_file());
+    assert!(!dir.join("moved/moved.txt").exists());
+    assert!(dir.join("texts/copied.txt").is_file());
+    assert!(dir.join("copied/copied.txt").is_file());
+}
+
+#[test]
+fn report_match_reason() {
+    use crate::bucket::MatchReason;
+
+    let mut bucket = Bucket {
+        name: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        name_filters: vec!["^notes".into(), "report".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+
+    assert_eq!(
+        bucket.match_reason(&"report.pdf").unwrap(),
+        Some(MatchReason::Extension("pdf".into()))
+    );
+    assert_eq!(
+        bucket.match_reason(&"annual-report.txt").unwrap(),
+        Some(MatchReason::NameFilter("report".into()))
+    );
+    assert_eq!(bucket.match_reason(&"photo.jpg").unwrap(), None);
+}
+
+#[test]
+fn parse_sizes() {
+    use crate::units::parse_size;
+
+    assert_eq!(parse_size("1KB").unwrap(), 1000);
+    assert_eq!(parse_size("1KiB").unwrap(), 1024);
+    assert_eq!(parse_size("4096").unwrap(), 4096);
+    assert_eq!(parse_size(" 512k ").unwrap(), 512_000);
+    assert_eq!(parse_size("1.5 GiB").unwrap(), 3 << 29);
+    assert_eq!(parse_size("10MB").unwrap(), 10_000_000);
+    assert!(parse_size("10XB").is_err());
+    assert!(parse_size("MB").is_err());
+    assert!(parse_size("").is_err());
+
+    let bucket: Bucket = toml::from_str(
+        "name = \"b\"
+        destination = \"/b\"
+        extension_filters = []
+        name_filters = []
+        priority = 0
+        action = \"copy\"
+        override_action = \"skip\"
+        min_free_bytes = \"2KiB\"",
+    )
+    .unwrap();
+    assert_eq!(bucket.min_free_bytes, 2048);
+}
+
+#[test]
+fn parse_durations() {
+    use crate::units::parse_duration;
+    use std::time::Duration;
+
+    assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+    assert_eq!(parse_duration(" 15 M ").unwrap(), Duration::from_secs(900));
+    assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+    assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+    assert!(parse_duration("1.5h").is_err());
+    assert!(parse_duration("h").is_err());
+    assert!(parse_duration("10w").is_err());
+}
+
+#[test]
+fn parse_verbosities() {
+    use crate::units::parse_verbosity;
+
+    assert_eq!(parse_verbosity("3"), Ok(3));
+    assert_eq!(parse_verbosity("error"), Ok(0));
+    assert_eq!(parse_verbosity("Warning"), Ok(1));
+    assert_eq!(parse_verbosity("info"), Ok(2));
+    assert_eq!(parse_verbosity("TRACE"), Ok(4));
+    assert!(parse_verbosity("loud").is_err());
+    assert!(parse_verbosity("-1").is_err());
+}
+
+#[test]
+fn reload_after_a_quiet_period() {
+    use crate::reload::Reloader;
+    use std::time::Duration;
+
+    let delay = Duration::from_millis(200);
+    let sandbox = Sandbox::new();
+    let file = sandbox.dir.path().join("janitors.toml");
+    std::fs::write(&file, "").unwrap();
+    let files = vec![file.clone()];
+    let mut reloader = Reloader::new(delay, &files);
+    assert_eq!(reloader.remaining(), None);
+    assert_eq!(reloader.due(), None);
+
+    // Every modification restarts the quiet period, and the file is reloaded only once.
+    reloader.modified(file.clone());
+    std::thread::sleep(delay / 2);
+    reloader.modified(file.clone());
+    std::thread::sleep(delay / 2);
+    assert_eq!(reloader.due(), None);
+    assert!(
+        reloader
+            .remaining()
+            .is_some_and(|remaining| remaining > Duration::ZERO)
+    );
+    std::thread::sleep(delay);
+    assert_eq!(reloader.remaining(), Some(Duration::ZERO));
+    assert_eq!(reloader.due(), Some(file.clone()));
+    assert_eq!(reloader.due(), None);
+    assert_eq!(reloader.remaining(), None);
+
+    // A modification without an event is noticed once through the modification times.
+    assert!(!reloader.check_times(&files, &file));
+    let modified = std::time::SystemTime::now() - Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert!(reloader.check_times(&files, &file));
+    assert!(!reloader.check_times(&files, &file));
+    std::thread::sleep(delay);
+    assert_eq!(reloader.due(), Some(file.clone()));
+
+    // Loading the files again takes their modification times.
+    std::fs::remove_file(&file).unwrap();
+    reloader.loaded(&files);
+    assert!(!reloader.check_times(&files, &file));
+}
+
+#[test]
+fn rescan_single_watch_path() {
+    let dir = temp_dir("rescan");
+    for name in ["a", "b"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        std::fs::write(dir.join(name).join("notes.txt"), "").unwrap();
+    }
+    let watch_path = |name: &str| WatchPath {
+        path: dir.join(name),
+        bucket_names: vec!["texts".into()],
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![watch_path("a"), watch_path("b")],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            override_action: OverrideAction::Rename,
+            ..Default::default()
+        }],
+        rescan_interval_minutes: 5,
+        ..Default::default()
+    };
+    config.init().unwrap();
+    assert_eq!(
+        config.rescan_interval(),
+        Some(std::time::Duration::from_secs(300))
+    );
+
+    std::fs::create_dir_all(dir.join("texts")).unwrap();
+    config.scan(&config.watch[1]).unwrap();
+    assert!(dir.join("a/notes.txt").is_file());
+    assert!(!dir.join("b/notes.txt").exists());
+    assert!(dir.join("texts/notes.txt").is_file());
+}
+
+#[test]
+fn apply_all_matching_buckets() {
+    let dir = temp_dir("apply-all");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/report.pdf"), "").unwrap();
+    let bucket = |name: &str, action: Action, priority| Bucket {
+        name: name.into(),
+        destination: dir.join(name),
+        extension_filters: vec!["pdf".into()],
+        priority,
+        action,
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["documents".into(), "backup".into()],
+            apply_all_matching: true,
+            ..Default::default()
+        }],
+        bucket: vec![
+            bucket("documents", Action::Move, 10),
+            bucket("backup", Action::Copy, 0),
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    // The copy is made first, even though its bucket has the lower priority.
+    config.one_shot().unwrap();
+    assert!(dir.join("documents/report.pdf").is_file());
+    assert!(dir.join("backup/report.pdf").is_file());
+    assert!(!dir.join("inbox/report.pdf").exists());
+
+    // Only one bucket may consume the file.
+    std::fs::write(dir.join("inbox/other.pdf"), "").unwrap();
+    config.bucket[1].action = Action::Move;
+    config.watch[0].bucket_names = vec!["documents".into(), "archive".into()];
+    config.bucket[1].name = "archive".into();
+    config.bucket[1].destination = dir.join("archive");
+    let err = config.one_shot().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::FailedPaths(failed))
+            if failed.len() == 1 && failed[0].1.contains("fits into several bins")
+    ));
+    assert!(dir.join("inbox/other.pdf").is_file());
+}
+
+#[test]
+fn filter_by_owner() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = temp_dir("owner");
+    std::fs::write(dir.join("mine.txt"), "").unwrap();
+    let meta = std::fs::metadata(dir.join("mine.txt")).unwrap();
+    let mut bucket = Bucket {
+        name: "texts".into(),
+        extension_filters: vec!["txt".into()],
+        owner_uid: Some(meta.uid()),
+        owner_gid: Some(meta.gid()),
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&dir.join("mine.txt")).unwrap());
+
+    bucket.owner_uid = Some(meta.uid().wrapping_add(1));
+    assert!(!bucket.is_fitting(&dir.join("mine.txt")).unwrap());
+}
+
+#[test]
+fn preserve_metadata_of_copies() {
+    use std::{
+        fs::{FileTimes, Permissions},
+        os::unix::fs::PermissionsExt,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    let dir = temp_dir("preserve-metadata");
+    std::fs::create_dir_all(dir.join("project/src")).unwrap();
+    std::fs::write(dir.join("project/src/main.rs"), "").unwrap();
+    let modified = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let file = std::fs::File::open(dir.join("project/src/main.rs")).unwrap();
+    file.set_times(FileTimes::new().set_modified(modified))
+        .unwrap();
+    std::fs::set_permissions(
+        dir.join("project/src/main.rs"),
+        Permissions::from_mode(0o600),
+    )
+    .unwrap();
+
+    let bucket = Bucket {
+        name: "backup".into(),
+        destination: dir.join("backup"),
+        action: Action::Copy,
+        preserve_metadata: true,
+        ..Default::default()
+    };
+    bucket.apply_action(&dir.join("project"), false).unwrap();
+
+    let copy = dir.join("backup/project/src/main.rs");
+    let meta = std::fs::metadata(&copy).unwrap();
+    assert_eq!(meta.modified().unwrap(), modified);
+    assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    assert!(dir.join("project/src/main.rs").is_file());
+}
+
+#[test]
+fn set_mode_and_group() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let dir = temp_dir("set-mode");
+    std::fs::create_dir_all(dir.join("shared")).unwrap();
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    let gid = std::fs::metadata(dir.join("notes.txt")).unwrap().gid();
+    let config = |mode: &str| {
+        format!(
+            "name = \"shared\"
+            destination = \"{}\"
+            extension_filters = [\"txt\"]
+            name_filters = []
+            priority = 0
+            action = \"move\"
+            override_action = \"skip\"
+            set_mode = {mode}
+            set_group = \"{gid}\"",
+            dir.join("shared").display()
+        )
+    };
+    let mut bucket: Bucket = toml::from_str(&config("\"640\"")).unwrap();
+    bucket.init().unwrap();
+    assert_eq!(bucket.set_mode, Some(0o640));
+
+    bucket.apply_action(&dir.join("notes.txt"), true).unwrap();
+    let meta = std::fs::metadata(dir.join("shared/notes.txt")).unwrap();
+    assert_eq!(meta.permissions().mode() & 0o7777, 0o640);
+    assert_eq!(meta.gid(), gid);
+
+    assert_eq!(
+        toml::from_str::<Bucket>(&config("0o755")).unwrap().set_mode,
+        Some(0o755)
+    );
+    assert!(toml::from_str::<Bucket>(&config("\"9\"")).is_err());
+    bucket.set_group = Some("janitors-surely-unknown-group".into());
+    assert!(bucket.init().is_err());
+}
+
+#[test]
+fn sweep_directory_with_all_buckets() {
+    let dir = temp_dir("sweep");
+    std::fs::create_dir_all(dir.join("mess/nested")).unwrap();
+    std::fs::write(dir.join("mess/notes.txt"), "").unwrap();
+    std::fs::write(dir.join("mess/nested/more.txt"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("watched"),
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config
+        .sweep(&dir.join("mess"), watch_path::RecMode::NonRecursive)
+        .unwrap();
+    assert!(dir.join("texts/notes.txt").is_file());
+    assert!(dir.join("mess/nested/more.txt").is_file());
+
+    config
+        .sweep(&dir.join("mess"), watch_path::RecMode::Recursive)
+        .unwrap();
+    assert!(dir.join("texts/more.txt").is_file());
+    assert!(
+        config
+            .sweep(&dir.join("missing"), Default::default())
+            .is_err()
+    );
+}
+
+#[test]
+fn skip_disabled_buckets_and_watch_paths() {
+    let dir = temp_dir("disabled");
+    for name in ["a", "b"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        std::fs::write(dir.join(name).join("notes.txt"), "").unwrap();
+    }
+    let bucket = |name: &str, priority| Bucket {
+        name: name.into(),
+        destination: dir.join(name),
+        extension_filters: vec!["txt".into()],
+        priority,
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![
+            WatchPath {
+                path: dir.join("a"),
+                bucket_names: vec!["texts".into(), "fallback".into()],
+                ..Default::default()
+            },
+            WatchPath {
+                path: dir.join("b"),
+                bucket_names: vec!["texts".into()],
+                enabled: false,
+                ..Default::default()
+            },
+        ],
+        bucket: vec![
+            Bucket {
+                enabled: false,
+                ..bucket("texts", 10)
+            },
+            bucket("fallback", 0),
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config.one_shot().unwrap();
+    assert!(dir.join("fallback/notes.txt").is_file());
+    assert!(!dir.join("texts").exists());
+    assert!(dir.join("b/notes.txt").is_file());
+}
+
+#[test]
+fn queue_throttled_paths() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/a.txt", "a");
+    sandbox.write("inbox/b.txt", "b");
+    let config = sandbox.config(vec![Bucket {
+        // Throttles are global, so the name must not be used by other tests.
+        name: "queued-texts".into(),
+        destination: "texts".into(),
+        extension_filters: vec!["txt".into()],
+        max_actions_per_minute: Some(1),
+        ..Default::default()
+    }]);
+    throttle::set_queueing(true);
+
+    let paths = [sandbox.path("inbox/a.txt"), sandbox.path("inbox/b.txt")];
+    config.watch[0].handle_paths(paths, true, &config).unwrap();
+    assert_eq!(sandbox.files(), ["inbox/b.txt", "texts/a.txt"]);
+    assert!(
+        schedule::deferred()
+            .iter()
+            .any(|(_, path, _)| *path == sandbox.path("inbox/b.txt"))
+    );
+}
+
+#[test]
+fn queue_paths_of_any_throttled_bucket() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/a.txt", "a");
+    sandbox.write("inbox/b.txt", "b");
+    let mut config = sandbox.config(vec![
+        Bucket {
+            name: "queued-backups".into(),
+            destination: "backups".into(),
+            extension_filters: vec!["txt".into()],
+            action: Action::Copy,
+            priority: 1,
+            ..Default::default()
+        },
+        Bucket {
+            // Throttles are global, so the name must not be used by other tests.
+            name: "queued-archive".into(),
+            destination: "archive".into(),
+            extension_filters: vec!["txt".into()],
+            max_actions_per_minute: Some(1),
+            ..Default::default()
+        },
+    ]);
+    config.watch[0].apply_all_matching = true;
+    throttle::set_queueing(true);
+
+    // The best bucket isn't throttled, but the other one which is applied as well is.
+    let paths = [sandbox.path("inbox/a.txt"), sandbox.path("inbox/b.txt")];
+    config.watch[0].handle_paths(paths, true, &config).unwrap();
+    assert_eq!(
+        sandbox.files(),
+        ["archive/a.txt", "backups/a.txt", "inbox/b.txt"]
+    );
+}
+
+#[test]
+fn throttle_actions() {
+    use std::time::{Duration, Instant};
+
+    let window = Duration::from_millis(100);
+    let start = Instant::now();
+    throttle::acquire("throttled", 2, window);
+    throttle::acquire("throttled", 2, window);
+    assert!(start.elapsed() < window);
+    throttle::acquire("throttled", 2, window);
+    assert!(start.elapsed() >= window);
+
+    // Other buckets are not affected.
+    let start = Instant::now();
+    throttle::acquire("unthrottled", 2, window);
+    assert!(start.elapsed() < window);
+    assert!(throttle::wait_time("unthrottled", 2, window).is_none());
+    throttle::acquire("unthrottled", 2, window);
+    assert!(throttle::wait_time("unthrottled", 2, window).is_some_and(|wait| wait <= window));
+
+    let mut bucket = Bucket {
+        max_actions_per_minute: Some(0),
+        ..Default::default()
+    };
+    assert!(bucket.init().is_err());
+}
+
+#[test]
+fn maintain_old_files() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = temp_dir("maintain");
+    std::fs::create_dir_all(dir.join("downloads")).unwrap();
+    std::fs::write(dir.join("downloads/old.txt"), "").unwrap();
+    std::fs::write(dir.join("downloads/new.txt"), "").unwrap();
+    let month_ago = SystemTime::now() - Duration::from_secs(31 * 24 * 60 * 60);
+    std::fs::File::open(dir.join("downloads/old.txt"))
+        .unwrap()
+        .set_modified(month_ago)
+        .unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("downloads"),
+            bucket_names: vec!["archive".into(), "texts".into()],
+            default_destination: Some(dir.join("unsorted")),
+            ..Default::default()
+        }],
+        bucket: vec![
+            Bucket {
+                name: "archive".into(),
+                destination: dir.join("archive"),
+                name_filters: vec![".*".into()],
+                min_age_days: Some(30),
+                ..Default::default()
+            },
+            Bucket {
+                name: "texts".into(),
+                destination: dir.join("texts"),
+                extension_filters: vec!["txt".into()],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    // Only the buckets with an age filter are considered.
+    config.maintain().unwrap();
+    assert!(dir.join("archive/old.txt").is_file());
+    assert!(dir.join("downloads/new.txt").is_file());
+    assert!(!dir.join("unsorted").exists());
+}
+
+#[test]
+fn keep_watchers_if_reload_fails() {
+    let dir = temp_dir("transactional-watchers");
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    let watch_path = |name: &str| WatchPath {
+        path: dir.join(name),
+        ..Default::default()
+    };
+    let mut watchers = Vec::new();
+    let mut remove_indecies = std::collections::HashSet::new();
+
+    let config = Config {
+        watch: vec![watch_path("a")],
+        ..Default::default()
+    };
+    config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    let rx_a = watchers[0].0.clone();
+
+    // The second watch path can't be created below a file, so its watcher can't be created.
+    std::fs::write(dir.join("file"), "").unwrap();
+    let config = Config {
+        watch: vec![watch_path("a"), watch_path("file/missing")],
+        create_watch_dirs: true,
+        ..Default::default()
+    };
+    assert!(
+        config
+            .setup_watchers(&mut watchers, &mut remove_indecies)
+            .is_err()
+    );
+    assert_eq!(watchers.len(), 1);
+    assert!(watchers[0].0.same_channel(&rx_a));
+}
+
+#[test]
+fn merge_directories() {
+    let dir = temp_dir("merge");
+    let write = |path: &str, contents: &str| {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    };
+    write("inbox/project/readme.md", "new");
+    write("inbox/project/src/main.rs", "new");
+    write("inbox/project/src/lib.rs", "new");
+    write("archive/project/readme.md", "old");
+    write("archive/project/src/main.rs", "old");
+    write("archive/project/notes.txt", "old");
+
+    let mut bucket = Bucket {
+        name: "archive".into(),
+        destination: dir.join("archive"),
+        override_action: OverrideAction::Merge,
+        merge_override_action: OverrideAction::Rename,
+        ..Default::default()
+    };
+    bucket
+        .apply_action(&dir.join("inbox/project"), false)
+        .unwrap();
+
+    let read = |path: &str| std::fs::read_to_string(dir.join(path)).unwrap();
+    assert_eq!(read("archive/project/readme.md"), "old");
+    assert_eq!(read("archive/project/readme.md.1"), "new");
+    assert_eq!(read("archive/project/src/main.rs"), "old");
+    assert_eq!(read("archive/project/src/main.rs.1"), "new");
+    assert_eq!(read("archive/project/src/lib.rs"), "new");
+    assert_eq!(read("archive/project/notes.txt"), "old");
+    assert!(!dir.join("inbox/project").exists());
+
+    // Skipped files stay where they are, and so does their directory.
+    write("inbox/project/src/main.rs", "newer");
+    bucket.merge_override_action = OverrideAction::Skip;
+    bucket
+        .apply_action(&dir.join("inbox/project"), false)
+        .unwrap();
+    assert_eq!(read("archive/project/src/main.rs"), "old");
+    assert_eq!(read("inbox/project/src/main.rs"), "newer");
+
+    bucket.merge_override_action = OverrideAction::Overwrite;
+    bucket
+        .apply_action(&dir.join("inbox/project"), false)
+        .unwrap();
+    assert_eq!(read("archive/project/src/main.rs"), "newer");
+    assert!(!dir.join("inbox/project").exists());
+}
+
+#[test]
+fn dedup_files_within_run() {
+    let dir = temp_dir("dedup");
+    std::fs::create_dir_all(dir.join("downloads")).unwrap();
+    for (name, contents) in [
+        ("a.txt", "same"),
+        ("b.txt", "same"),
+        ("c.txt", "diff"),
+        ("d.txt", "same"),
+    ] {
+        std::fs::write(dir.join("downloads").join(name), contents).unwrap();
+    }
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("downloads"),
+            bucket_names: vec!["texts".into()],
+            dedup_within_run: true,
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config.one_shot().unwrap();
+    assert!(dir.join("texts/a.txt").is_file());
+    assert!(dir.join("texts/c.txt").is_file());
+    assert!(dir.join("downloads/b.txt").is_file());
+    assert!(dir.join("downloads/d.txt").is_file());
+
+    config.watch[0].delete_duplicates = true;
+    std::fs::write(dir.join("downloads/e.txt"), "same").unwrap();
+    config.one_shot().unwrap();
+    assert!(dir.join("texts/b.txt").is_file());
+    assert!(!dir.join("downloads/d.txt").exists());
+    assert!(!dir.join("downloads/e.txt").exists());
+}
+
+#[test]
+fn break_priority_ties() {
+    let bucket = |name: &str, priority| Bucket {
+        name: name.into(),
+        destination: PathBuf::from("/tmp").join(name),
+        extension_filters: vec!["pdf".into()],
+        priority,
+        ..Default::default()
+    };
+    let mut config = Config {
+        bucket: vec![
+            bucket("b", 1),
+            bucket("c", 1),
+            bucket("a", 1),
+            bucket("z", 0),
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    let path = PathBuf::from("/tmp/report.pdf");
+    let ranked = |tie_break| -> Vec<String> {
+        bucket::rank_buckets(config.bucket.iter(), &path, true, tie_break)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.name.clone())
+            .collect()
+    };
+    assert_eq!(ranked(TieBreak::HighestPriority), ["c", "b", "a", "z"]);
+    assert_eq!(ranked(TieBreak::Name), ["a", "b", "c", "z"]);
+    assert_eq!(ranked(TieBreak::FirstDefined), ["b", "c", "a", "z"]);
+
+    let parsed: Config = toml::from_str("tie_break = \"first-defined\"").unwrap();
+    assert_eq!(parsed.tie_break, TieBreak::FirstDefined);
+}
+
+#[test]
+fn rank_negative_priorities() {
+    let bucket = |name: &str, priority| Bucket {
+        name: name.into(),
+        destination: PathBuf::from("/tmp").join(name),
+        extension_filters: vec!["pdf".into()],
+        priority,
+        ..Default::default()
+    };
+    let mut config = Config {
+        bucket: vec![
+            bucket("low", -5),
+            bucket("none", 0),
+            bucket("b", -1),
+            bucket("a", -1),
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    let mut sorted = config.bucket.clone();
+    sorted.sort();
+    let names: Vec<&str> = sorted.iter().map(|b| b.name.as_str()).collect();
+    assert_eq!(names, ["low", "a", "b", "none"]);
+
+    let path = PathBuf::from("/tmp/report.pdf");
+    let ranked = |tie_break| -> Vec<String> {
+        bucket::rank_buckets(config.bucket.iter(), &path, true, tie_break)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.name.clone())
+            .collect()
+    };
+    assert_eq!(ranked(TieBreak::HighestPriority), ["none", "b", "a", "low"]);
+    assert_eq!(ranked(TieBreak::Name), ["none", "a", "b", "low"]);
+    assert_eq!(ranked(TieBreak::FirstDefined), ["none", "b", "a", "low"]);
+
+    let parsed: Config = toml::from_str(
+        "[[bucket]]
+        name = \"n\"
+        destination = \"/tmp\"
+        action = \"move\"
+        override_action = \"skip\"
+        priority = -3",
+    )
+    .unwrap();
+    assert_eq!(parsed.bucket[0].priority, -3);
+}
+
+#[test]
+fn link_files_into_destinations() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = temp_dir("link-files");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/report.pdf"), "report").unwrap();
+    let bucket = |name: &str, action| Bucket {
+        name: name.into(),
+        destination: dir.join(name),
+        create_destination: true,
+        action,
+        override_action: OverrideAction::Overwrite,
+        ..Default::default()
+    };
+    let source = dir.join("inbox/report.pdf");
+
+    let hard = bucket("hard", Action::Hardlink);
+    hard.apply_action(&source, true).unwrap();
+    hard.apply_action(&source, true).unwrap();
+    let linked = std::fs::metadata(dir.join("hard/report.pdf")).unwrap();
+    assert_eq!(linked.ino(), std::fs::metadata(&source).unwrap().ino());
+
+    std::fs::create_dir_all(dir.join("soft")).unwrap();
+    std::fs::write(dir.join("soft/report.pdf"), "old").unwrap();
+    bucket("soft", Action::Symlink)
+        .apply_action(&source, true)
+        .unwrap();
+    assert_eq!(
+        std::fs::read_link(dir.join("soft/report.pdf")).unwrap(),
+        source
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("soft/report.pdf")).unwrap(),
+        "report"
+    );
+    assert!(source.exists());
+
+    assert!(
+        bucket("hard", Action::Hardlink)
+            .apply_action(&dir.join("inbox"), false)
+            .is_err()
+    );
+}
+
+#[test]
+fn label_watch_path_logs() {
+    let mut watch_path = WatchPath {
+        path: PathBuf::from("/tmp/downloads"),
+        ..Default::default()
+    };
+    assert_eq!(watch_path.label(), "/tmp/downloads");
+    watch_path.label = Some("downloads".into());
+    assert_eq!(watch_path.label(), "downloads");
+
+    assert_eq!(label::current(), None);
+    {
+        let _outer = label::enter(watch_path.label());
+        {
+            let _inner = label::enter("other");
+            assert_eq!(label::current().as_deref(), Some("other"));
+        }
+        assert_eq!(label::current().as_deref(), Some("downloads"));
+        let other_thread = std::thread::spawn(label::current).join().unwrap();
+        assert_eq!(other_thread, None);
+    }
+    assert_eq!(label::current(), None);
+}
+
+#[test]
+fn act_on_symlinks_as_links() {
+    let dir = temp_dir("symlinks-as-links");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::create_dir_all(dir.join("target")).unwrap();
+    std::fs::write(dir.join("target/kept.txt"), "kept").unwrap();
+    std::os::unix::fs::symlink(dir.join("target"), dir.join("inbox/dir-link")).unwrap();
+    std::os::unix::fs::symlink(dir.join("target/kept.txt"), dir.join("inbox/file-link")).unwrap();
+    let bucket = |name: &str, action, treat_symlinks_as_links| Bucket {
+        name: name.into(),
+        destination: dir.join(name),
+        create_destination: true,
+        action,
+        treat_symlinks_as_links,
+        ..Default::default()
+    };
+
+    bucket("copies", Action::Copy, true)
+        .apply_action(&dir.join("inbox/file-link"), true)
+        .unwrap();
+    assert_eq!(
+        std::fs::read_link(dir.join("copies/file-link")).unwrap(),
+        dir.join("target/kept.txt")
+    );
+    bucket("contents", Action::Copy, false)
+        .apply_action(&dir.join("inbox/file-link"), true)
+        .unwrap();
+    assert!(!dir.join("contents/file-link").is_symlink());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("contents/file-link")).unwrap(),
+        "kept"
+    );
+
+    bucket("moved", Action::Move, true)
+        .apply_action(&dir.join("inbox/file-link"), true)
+        .unwrap();
+    assert!(!dir.join("inbox/file-link").is_symlink());
+    assert!(dir.join("moved/file-link").is_symlink());
+
+    bucket("deleted", Action::Delete, true)
+        .apply_action(&dir.join("inbox/dir-link"), false)
+        .unwrap();
+    assert!(!dir.join("inbox/dir-link").is_symlink());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("target/kept.txt")).unwrap(),
+        "kept"
+    );
+}
+
+#[test]
+fn continue_after_failed_paths() {
+    let dir = temp_dir("continue-after-failure");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(dir.join("inbox").join(name), name).unwrap();
+    }
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["texts".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            // Only the first file is allowed to run the hook.
+            pre_hook: Some(format!(
+                "test {{path}} = '{}'",
+                dir.join("inbox/a.txt").display()
+            )),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    let err = config.one_shot().unwrap_err();
+    let Some(errors::JError::FailedPaths(failed)) = err.downcast_ref::<errors::JError>() else {
+        panic!("unexpected error: {err}");
+    };
+    let mut failed: Vec<_> = failed.iter().map(|(path, _)| path.clone()).collect();
+    failed.sort();
+    assert_eq!(failed, [dir.join("inbox/b.txt"), dir.join("inbox/c.txt")]);
+    assert!(dir.join("texts/a.txt").is_file());
+    // Other tests may fail paths at the same time, so the counter only has a lower bound.
+    assert!(stats::failures() >= 2);
+}
+
+#[test]
+fn combine_failed_paths() {
+    use errors::JError;
+
+    assert!(JError::combine([(PathBuf::from("/a"), Ok(()))]).is_ok());
+
+    let nested = JError::combine([
+        (PathBuf::from("/watch/a"), Err(anyhow::anyhow!("first"))),
+        (PathBuf::from("/watch/b"), Ok(())),
+    ]);
+    let err = JError::combine([
+        (PathBuf::from("/watch"), nested),
+        (
+            PathBuf::from("/other"),
+            Err(JError::InvalidPath("/x".into()).into()),
+        ),
+    ])
+    .unwrap_err();
+    let Some(JError::FailedPaths(failed)) = err.downcast_ref::<JError>() else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(
+        failed,
+        &[
+            (PathBuf::from("/watch/a"), "first".to_string()),
+            (PathBuf::from("/other"), "Invalid path: /x".to_string()),
+        ]
+    );
+    assert_eq!(
+        err.to_string(),
+        "Could not handle 2 paths: '/watch/a', '/other'"
+    );
+}
+
+#[test]
+fn reconnect_watchers() {
+    let dir = temp_dir("reconnect-watchers");
+    std::fs::create_dir_all(dir.join("drive")).unwrap();
+    let config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("drive"),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let mut watchers = Vec::new();
+    let mut remove_indecies = std::collections::HashSet::new();
+    config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    let rx = watchers[0].0.clone();
+
+    // Nothing changes while the watch path exists.
+    let reconnected = config.reconnect_watchers(&mut watchers, &mut remove_indecies);
+    assert!(reconnected.is_empty() && remove_indecies.is_empty());
+
+    std::fs::remove_dir(dir.join("drive")).unwrap();
+    let reconnected = config.reconnect_watchers(&mut watchers, &mut remove_indecies);
+    assert!(reconnected.is_empty());
+    assert!(remove_indecies.contains(&0));
+
+    std::fs::create_dir(dir.join("drive")).unwrap();
+    let reconnected = config.reconnect_watchers(&mut watchers, &mut remove_indecies);
+    assert_eq!(reconnected, [0]);
+    assert!(remove_indecies.is_empty());
+    assert!(!watchers[0].0.same_channel(&rx));
+}
+
+#[test]
+fn skip_missing_watch_paths() {
+    let dir = temp_dir("missing-watch-paths");
+    std::fs::create_dir_all(dir.join("present")).unwrap();
+    let watch_path = |name: &str| WatchPath {
+        path: dir.join(name),
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![watch_path("typo"), watch_path("present")],
+        ..Default::default()
+    };
+    let mut watchers = Vec::new();
+    let mut remove_indecies = std::collections::HashSet::new();
+    config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    assert_eq!(watchers.len(), 2);
+    assert_eq!(remove_indecies, [0].into());
+
+    config.create_watch_dirs = true;
+    config
+        .setup_watchers(&mut watchers, &mut remove_indecies)
+        .unwrap();
+    assert!(dir.join("typo").is_dir());
+    assert!(remove_indecies.is_empty());
+
+    std::fs::write(
+        dir.join("config.toml"),
+        format!(
+            "[[watch]]
+            path = '{}'
+            recursive_mode = \"non-recursive\"
+            bucket_names = []",
+            dir.join("other-typo").display()
+        ),
+    )
+    .unwrap();
+    let path = dir.join("config.toml");
+    assert!(Config::load(path.to_str().unwrap(), &Default::default()).is_ok());
+    let options = config::Options {
+        strict: true,
+        ..Default::default()
+    };
+    let err = Config::load(path.to_str().unwrap(), &options).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::MissingWatchPath(_))
+    ));
+}
+
+#[test]
+fn share_filter_sets() {
+    let mut config: Config = toml::from_str(
+        "
+        [[filter_set]]
+        name = \"office\"
+        extension_filters = [\"docx\", \"xlsx\"]
+        name_filters = [\"^report\"]
+
+        [[bucket]]
+        name = \"documents\"
+        destination = \"/documents\"
+        use_filter_sets = [\"office\"]
+        extension_filters = [\"pdf\", \"docx\"]
+        priority = 0
+        action = \"move\"
+        override_action = \"skip\"
+        ",
+    )
+    .unwrap();
+    config.init().unwrap();
+    config.init().unwrap();
+    let bucket = &config.bucket[0];
+    assert_eq!(bucket.extension_filters, ["pdf", "docx", "xlsx"]);
+    assert_eq!(bucket.name_filters, ["^report"]);
+    assert!(bucket.is_fitting(&"/downloads/sheet.xlsx").unwrap());
+    assert!(bucket.is_fitting(&"/downloads/report-2024.txt").unwrap());
+
+    config.bucket[0].use_filter_sets.push("missing".into());
+    let err = config.init().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::UnknownFilterSet(bucket, set)) if bucket == "documents" && set == "missing"
+    ));
+}
+
+#[test]
+fn match_any_or_all_filters() {
+    let mut bucket = Bucket {
+        name: "invoices".into(),
+        extension_filters: vec!["pdf".into()],
+        name_filters: vec!["invoice".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    for path in ["/a/invoice-1.pdf", "/a/manual.pdf", "/a/invoice.txt"] {
+        assert!(bucket.is_fitting(&path).unwrap(), "{path}");
+    }
+    assert!(!bucket.is_fitting(&"/a/manual.txt").unwrap());
+
+    bucket.match_mode = bucket::MatchMode::All;
+    assert_eq!(
+        bucket.match_reason(&"/a/invoice-1.pdf").unwrap(),
+        Some(bucket::MatchReason::Both("pdf".into(), "invoice".into()))
+    );
+    for path in ["/a/manual.pdf", "/a/invoice.txt", "/a/manual.txt"] {
+        assert!(!bucket.is_fitting(&path).unwrap(), "{path}");
+    }
+
+    // Without name filters only the extension has to match.
+    bucket.name_filters.clear();
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&"/a/manual.pdf").unwrap());
+}
+
+#[test]
+fn resume_pending_paths() {
+    let dir = temp_dir("pending");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/a.txt"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["texts".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        pending_file: Some(dir.join("pending.json")),
+        ..Default::default()
+    };
+    config.init().unwrap();
+    let file = config.pending_file.clone().unwrap();
+    assert!(pending::load(&file).unwrap().is_empty());
+
+    let entry = |watch_path: &str, name: &str| pending::Entry {
+        watch_path: dir.join(watch_path),
+        path: dir.join(watch_path).join(name),
+        is_file: true,
+    };
+    let entries = [
+        entry("inbox", "a.txt"),
+        entry("inbox", "gone.txt"),
+        entry("removed-watch-path", "b.txt"),
+    ];
+    pending::save(&file, &entries).unwrap();
+    assert_eq!(pending::load(&file).unwrap(), entries);
+
+    pending::resume(&config, &file).unwrap();
+    assert!(dir.join("texts/a.txt").is_file());
+}
+
+#[test]
+fn time_actions() {
+    use std::time::Duration;
+
+    // Statistics are global, so the name must not be used by other tests.
+    let name = "time-actions";
+    for ms in [1, 5, 50, 2000, 120_000] {
+        stats::record(name, &Action::Copy, 1, Duration::from_millis(ms));
+    }
+    let s = stats::get(name);
+    assert_eq!(s.copied, 5);
+    assert_eq!(s.busy, Duration::from_millis(122_056));
+    assert_eq!(s.timings, [2, 1, 0, 1, 0, 1]);
+}
+
+#[test]
+fn replace_in_destination_names() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/COPY_tax return.pdf", "new");
+    sandbox.write("documents/tax-return.pdf", "old");
+    let config = sandbox.config(vec![Bucket {
+        name: "documents".into(),
+        destination: "documents".into(),
+        extension_filters: vec!["pdf".into()],
+        filename_replace: vec![
+            (" ".into(), "_".into()),
+            ("^COPY_".into(), "".into()),
+            (r"^(\w+)_(\w+)\.pdf$".into(), "$1-$2.pdf".into()),
+        ],
+        override_action: OverrideAction::Rename,
+        ..Default::default()
+    }]);
+
+    config.one_shot().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        ["documents/tax-return.pdf", "documents/tax-return.pdf.1"]
+    );
+
+    let mut bucket = config.bucket[0].clone();
+    bucket.filename_replace = vec![("(".into(), "".into())];
+    assert!(bucket.init().is_err());
+}
+
+#[test]
+fn plan_actions_without_applying_them() {
+    let sandbox = Sandbox::new();
+    let invoice = sandbox.write("inbox/invoice-acme.pdf", "");
+    let archive = sandbox.write("inbox/invoice-acme.zip", "");
+    let config = sandbox.config(vec![Bucket {
+        name: "invoices".into(),
+        destination: "clients/$1".into(),
+        name_filters: vec![r"invoice-(\w+)\..*".into()],
+        extension_actions: [("zip".to_string(), Action::Copy)].into(),
+        ..Default::default()
+    }]);
+    let bucket = &config.bucket[0];
+
+    let plan = bucket.plan_action(&invoice, true).unwrap();
+    assert_eq!(plan.source, invoice);
+    assert_eq!(
+        plan.destination,
+        sandbox.path("clients/acme/invoice-acme.pdf")
+    );
+    assert_eq!(plan.action, Action::Move);
+    assert_eq!(
+        bucket.plan_action(&archive, true).unwrap().action,
+        Action::Copy
+    );
+    // Nothing was created or moved.
+    assert_eq!(
+        sandbox.files(),
+        ["inbox/invoice-acme.pdf", "inbox/invoice-acme.zip"]
+    );
+
+    bucket.apply_action(&invoice, true).unwrap();
+    assert!(sandbox.path("clients/acme/invoice-acme.pdf").exists());
+}
+
+#[test]
+fn extract_archives() {
+    use std::io::Write;
+
+    let sandbox = Sandbox::new();
+    let mut zip =
+        zip::ZipWriter::new(std::fs::File::create(sandbox.path("inbox/photos.zip")).unwrap());
+    zip.start_file("beach.jpg", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(b"sand").unwrap();
+    zip.finish().unwrap();
+    let gz = flate2::write::GzEncoder::new(
+        std::fs::File::create(sandbox.path("inbox/notes.tar.gz")).unwrap(),
+        flate2::Compression::default(),
+    );
+    let mut tar = tar::Builder::new(gz);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, "todo/monday.txt", &b"work"[..])
+        .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+    sandbox.write("inbox/broken.zip", "not an archive");
+
+    let config = sandbox.config(vec![Bucket {
+        name: "archives".into(),
+        destination: "extracted".into(),
+        extension_filters: vec!["zip".into(), "gz".into()],
+        action: Action::Extract,
+        ..Default::default()
+    }]);
+    let mut bucket = config.bucket[0].clone();
+    bucket.delete_extracted = true;
+    let err = bucket
+        .apply_action(&sandbox.path("inbox/broken.zip"), true)
+        .unwrap_err();
+    assert!(err.to_string().contains("not a zip, tar or gzip archive"));
+    bucket
+        .apply_action(&sandbox.path("inbox/notes.tar.gz"), true)
+        .unwrap();
+    config.bucket[0]
+        .apply_action(&sandbox.path("inbox/photos.zip"), true)
+        .unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "extracted/notes/todo/monday.txt",
+            "extracted/photos/beach.jpg",
+            "inbox/broken.zip",
+            "inbox/photos.zip",
+        ]
+    );
+    assert_eq!(
+        std::fs::read_to_string(sandbox.path("extracted/photos/beach.jpg")).unwrap(),
+        "sand"
+    );
+
+    // With the default override action, an existing extraction is kept.
+    std::fs::remove_file(sandbox.path("extracted/photos/beach.jpg")).unwrap();
+    config.bucket[0]
+        .apply_action(&sandbox.path("inbox/photos.zip"), true)
+        .unwrap();
+    assert!(!sandbox.path("extracted/photos/beach.jpg").exists());
+}
+
+#[test]
+fn normalize_destination_names() {
+    for (name, normalized) in [
+        ("My File (1).PDF", "my-file-1.pdf"),
+        ("  spaced   out  .txt", "spaced-out.txt"),
+        ("archive.tar.gz", "archive.tar.gz"),
+        (".bashrc", ".bashrc"),
+        ("Ünïcode Ñame.md", "ünïcode-ñame.md"),
+        ("snake_case.rs", "snake_case.rs"),
+        ("(((", "((("),
+    ] {
+        assert_eq!(
+            bucket::normalize_name(name, true, "-"),
+            normalized,
+            "{name}"
+        );
+    }
+    assert_eq!(
+        bucket::normalize_name("My File.PDF", false, "_"),
+        "My_File.PDF"
+    );
+
+    let dir = temp_dir("normalize-names");
+    std::fs::write(dir.join("My Report (1).PDF"), "new").unwrap();
+    std::fs::create_dir_all(dir.join("reports")).unwrap();
+    std::fs::write(dir.join("reports/my-report-1.pdf"), "old").unwrap();
+    let mut bucket = Bucket {
+        name: "reports".into(),
+        destination: dir.join("reports"),
+        normalize_name: true,
+        override_action: OverrideAction::Rename,
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    bucket
+        .apply_action(&dir.join("My Report (1).PDF"), true)
+        .unwrap();
+    // The normalized name collides with the existing file.
+    assert_eq!(
+        std::fs::read_to_string(dir.join("reports/my-report-1.pdf.1")).unwrap(),
+        "new"
+    );
+
+    bucket.normalize_separator = "/".into();
+    assert!(bucket.init().is_err());
+}
+
+#[test]
+fn skip_destinations_inside_watch_paths() {
+    let dir = temp_dir("destination-inside");
+    std::fs::create_dir_all(dir.join("inbox/sorted/nested")).unwrap();
+    std::fs::write(dir.join("inbox/sorted/nested/a.txt"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            recursive_mode: RecMode::Recursive,
+            bucket_names: vec!["sorted".into(), "trash".into()],
+            ..Default::default()
+        }],
+        bucket: vec![
+            Bucket {
+                name: "sorted".into(),
+                destination: dir.join("inbox/sorted"),
+                extension_filters: vec!["txt".into()],
+                ..Default::default()
+            },
+            Bucket {
+                name: "trash".into(),
+                action: Action::Delete,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    config.init().unwrap();
+    assert_eq!(
+        config.destinations_inside(&config.watch[0]),
+        [dir.join("inbox/sorted")]
+    );
+
+    // Files which were put into the destination are not handled again.
+    let path = dir.join("inbox/sorted/nested/a.txt");
+    assert!(config.is_inside_destination(&config.watch[0], &path));
+    config.watch[0]
+        .handle_paths([path.clone()], true, &config)
+        .unwrap();
+    assert!(path.is_file());
+
+    std::fs::write(
+        dir.join("config.toml"),
+        format!(
+            "[[watch]]
+            path = '{0}/inbox'
+            recursive_mode = \"recursive\"
+            bucket_names = [\"sorted\"]
+
+            [[bucket]]
+            name = \"sorted\"
+            destination = '{0}/inbox/sorted'
+            priority = 0
+            action = \"move\"
+            override_action = \"skip\"",
+            dir.display()
+        ),
+    )
+    .unwrap();
+    let path = dir.join("config.toml");
+    assert!(Config::load(path.to_str().unwrap(), &Default::default()).is_ok());
+    let options = config::Options {
+        strict: true,
+        ..Default::default()
+    };
+    let err = Config::load(path.to_str().unwrap(), &options).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::DestinationInsideWatchPath(..))
+    ));
+}
+
+#[test]
+fn ignore_recently_handled_paths() {
+    let dir = temp_dir("once-per");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/a.txt"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["backup".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "backup".into(),
+            destination: dir.join("backup"),
+            extension_filters: vec!["txt".into()],
+            action: Action::Copy,
+            ..Default::default()
+        }],
+        once_per_ms: 60_000,
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    let path = dir.join("inbox/a.txt");
+    let handle = || {
+        config.watch[0]
+            .handle_paths([path.clone()], true, &config)
+            .unwrap()
+    };
+    handle();
+    std::fs::remove_file(dir.join("backup/a.txt")).unwrap();
+    // The second event for the same path is a duplicate.
+    handle();
+    assert!(!dir.join("backup/a.txt").exists());
+}
+
+#[test]
+fn handle_deferred_paths_despite_once_per() {
+    use std::{io::Write, time::Duration};
+
+    let sandbox = Sandbox::new();
+    let path = sandbox.write("inbox/growing.txt", "");
+    let mut config = sandbox.config(vec![Bucket {
+        name: "texts".into(),
+        destination: "texts".into(),
+        extension_filters: vec!["txt".into()],
+        ..Default::default()
+    }]);
+    config.once_per_ms = 60_000;
+    config.stable_wait_ms = 40;
+    config.stable_max_wait_ms = 40;
+
+    let writer = {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let mut file = std::fs::File::options().append(true).open(path).unwrap();
+            for _ in 0..20 {
+                file.write_all(b"data").unwrap();
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        })
+    };
+    config.watch[0]
+        .handle_paths([path.clone()], true, &config)
+        .unwrap();
+    assert_eq!(sandbox.files(), ["inbox/growing.txt"]);
+    writer.join().unwrap();
+
+    // The deferred path comes back long before `once_per_ms` passed, and isn't a duplicate.
+    config.watch[0].handle_paths([path], true, &config).unwrap();
+    assert_eq!(sandbox.files(), ["texts/growing.txt"]);
+}
+
+#[test]
+fn inline_watch_path_buckets() {
+    let dir = temp_dir("inline-buckets");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/a.pdf"), "").unwrap();
+    let config = |inline_name: &str| {
+        format!(
+            "[[bucket]]
+            name = \"images\"
+            destination = \"{0}/images\"
+            extension_filters = [\"png\"]
+            priority = 0
+            action = \"move\"
+            override_action = \"skip\"
+
+            [[watch]]
+            path = \"{0}/inbox\"
+            bucket_names = [\"images\"]
+            recursive_mode = \"non-recursive\"
+
+            [[watch.buckets]]
+            {inline_name}
+            destination = \"{0}/documents\"
+            extension_filters = [\"pdf\"]
+            priority = 0
+            action = \"move\"
+            override_action = \"skip\"
+            ",
+            dir.display()
+        )
+    };
+    std::fs::write(dir.join("config.toml"), config("")).unwrap();
+    let (_, config_a, _watcher) = Config::load(
+        dir.join("config.toml").to_str().unwrap(),
+        &Default::default(),
+    )
+    .unwrap();
+    let watch_path = &config_a.watch[0];
+    let names: Vec<&str> = watch_path
+        .buckets(&config_a)
+        .map(|b| b.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["images", &format!("{}#1", watch_path.path.display())]
+    );
+    watch_path
+        .handle_paths([dir.join("inbox/a.pdf")], true, &config_a)
+        .unwrap();
+    assert!(dir.join("documents/a.pdf").exists());
+
+    // Inline buckets share their names with the buckets the watch path references.
+    std::fs::write(dir.join("config.toml"), config("name = \"images\"")).unwrap();
+    let err = Config::load(
+        dir.join("config.toml").to_str().unwrap(),
+        &Default::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::DuplicateBucket(name)) if name == "images"
+    ));
+}
+
+#[test]
+fn name_bins_in_errors() {
+    let mut bucket = Bucket {
+        name: "broken".into(),
+        name_filters: vec!["(unclosed".into()],
+        ..Default::default()
+    };
+    let err = bucket.init().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::RegexCompile(bucket, pattern, _))
+            if bucket == "broken" && pattern == "(unclosed"
+    ));
+
+    let dir = temp_dir("action-failed");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/a.txt"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["texts".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            pre_hook: Some("false".into()),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+    let err = config.one_shot().unwrap_err();
+    let Some(errors::JError::FailedPaths(failed)) = err.downcast_ref::<errors::JError>() else {
+        panic!("unexpected error: {err}");
+    };
+    assert!(
+        failed[0].1.starts_with(&format!(
+            "Bin 'texts' failed on '{}': Hook",
+            dir.join("inbox/a.txt").display()
+        )),
+        "{}",
+        failed[0].1
+    );
+}
+
+#[test]
+fn report_all_invalid_name_filters() {
+    let mut config = Config {
+        bucket: vec![
+            Bucket {
+                name: "first".into(),
+                name_filters: vec!["^ok".into(), "[unclosed".into()],
+                ..Default::default()
+            },
+            Bucket {
+                name: "second".into(),
+                name_filters: vec!["(unclosed".into()],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    };
+    let err = config.init().unwrap_err();
+    let Some(errors::JError::InvalidNameFilters(invalid)) = err.downcast_ref::<errors::JError>()
+    else {
+        panic!("unexpected error: {err}");
+    };
+    let invalid: Vec<(&str, &str)> = invalid
+        .iter()
+        .map(|(bucket, filter, _)| (bucket.as_str(), filter.as_str()))
+        .collect();
+    assert_eq!(invalid, [("first", "[unclosed"), ("second", "(unclosed")]);
+    assert!(err.to_string().starts_with("2 filters are invalid:"));
+}
+
+#[test]
+fn anchor_name_filters() {
+    let mut bucket = Bucket {
+        name: "invoices".into(),
+        name_filters: vec!["invoice|receipt".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&"/tmp/invoice").unwrap());
+    assert!(bucket.is_fitting(&"/tmp/old-invoice.bak").unwrap());
+    assert!(bucket.is_fitting(&"/tmp/receipt-2024").unwrap());
+
+    bucket.anchor_names = true;
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&"/tmp/invoice").unwrap());
+    assert!(bucket.is_fitting(&"/tmp/receipt").unwrap());
+    assert!(!bucket.is_fitting(&"/tmp/old-invoice.bak").unwrap());
+    assert!(!bucket.is_fitting(&"/tmp/receipt-2024").unwrap());
+}
+
+#[test]
+fn match_extension_regexes() {
+    let mut bucket = Bucket {
+        name: "media".into(),
+        extension_filters: vec!["ogg".into()],
+        extension_regex: vec!["mp[0-9]".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&"/tmp/song.mp3").unwrap());
+    assert!(bucket.is_fitting(&"/tmp/clip.mp4").unwrap());
+    assert!(bucket.is_fitting(&"/tmp/song.ogg").unwrap());
+    assert!(!bucket.is_fitting(&"/tmp/page.mhtml").unwrap());
+    assert!(!bucket.is_fitting(&"/tmp/m3").unwrap());
+
+    bucket.extension_filters.clear();
+    bucket.name_filters = vec!["^song".into()];
+    bucket.match_mode = MatchMode::All;
+    bucket.init().unwrap();
+    assert!(bucket.is_fitting(&"/tmp/song.mp3").unwrap());
+    assert!(!bucket.is_fitting(&"/tmp/clip.mp4").unwrap());
+    assert!(!bucket.is_fitting(&"/tmp/song.ogg").unwrap());
+
+    bucket.extension_regex = vec!["m[".into()];
+    let err = bucket.init().unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::RegexCompile(bucket, pattern, _))
+            if bucket == "media" && pattern == "m["
+    ));
+}
+
+#[test]
+fn batch_paths_until_min_batch() {
+    let dir = temp_dir("min-batch");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    for name in ["a.log", "b.log", "c.log", "d.log"] {
+        std::fs::write(dir.join("inbox").join(name), "").unwrap();
+    }
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["log-archive".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "log-archive".into(),
+            destination: dir.join("archive"),
+            extension_filters: vec!["log".into()],
+            min_batch: 3,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+    let handle = |name: &str| {
+        config.watch[0]
+            .handle_paths([dir.join("inbox").join(name)], true, &config)
+            .unwrap()
+    };
+
+    handle("a.log");
+    handle("b.log");
+    // Duplicate events don't count twice.
+    handle("b.log");
+    assert!(dir.join("inbox/a.log").is_file());
+    assert_eq!(batch::batched().len(), 2);
+
+    handle("c.log");
+    for name in ["a.log", "b.log", "c.log"] {
+        assert!(dir.join("archive").join(name).is_file());
+    }
+
+    // Without `batch_flush_minutes`, only flushing everything handles a partial batch.
+    handle("d.log");
+    config.flush_batches(false).unwrap();
+    assert!(dir.join("inbox/d.log").is_file());
+    config.flush_batches(true).unwrap();
+    assert!(dir.join("archive/d.log").is_file());
+}
+
+#[test]
+fn wait_for_bursts() {
+    let sandbox = Sandbox::new();
+    for name in ["1.jpg", "2.jpg", "3.jpg", "a.txt", "b.txt", "scan.pdf"] {
+        sandbox.write(&format!("inbox/{name}"), "");
+    }
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "photo-burst".into(),
+            destination: "shoots".into(),
+            extension_filters: vec!["jpg".into()],
+            burst_threshold: 3,
+            ..Default::default()
+        },
+        Bucket {
+            name: "no-burst".into(),
+            destination: "notes".into(),
+            extension_filters: vec!["txt".into()],
+            burst_threshold: 2,
+            burst_window_secs: 0,
+            ..Default::default()
+        },
+        Bucket {
+            name: "scan-burst".into(),
+            destination: "scans".into(),
+            extension_filters: vec!["pdf".into()],
+            burst_threshold: 3,
+            ..Default::default()
+        },
+    ]);
+    let handle = |config: &Config, name: &str| {
+        config.watch[0]
+            .handle_paths([sandbox.path("inbox").join(name)], true, config)
+            .unwrap()
+    };
+
+    handle(&config, "1.jpg");
+    handle(&config, "2.jpg");
+    // Duplicate events don't count twice.
+    handle(&config, "2.jpg");
+    // The first text file is forgotten before the second arrives.
+    handle(&config, "a.txt");
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    handle(&config, "b.txt");
+    assert_eq!(sandbox.files().len(), 6);
+
+    handle(&config, "3.jpg");
+    handle(&config, "scan.pdf");
+    assert_eq!(
+        sandbox.files(),
+        [
+            "inbox/a.txt",
+            "inbox/b.txt",
+            "inbox/scan.pdf",
+            "shoots/1.jpg",
+            "shoots/2.jpg",
+            "shoots/3.jpg",
+        ]
+    );
+
+    // Shutting down handles the paths which are still within their window.
+    config.flush_bursts().unwrap();
+    assert_eq!(
+        sandbox.files(),
+        [
+            "inbox/a.txt",
+            "inbox/b.txt",
+            "scans/scan.pdf",
+            "shoots/1.jpg",
+            "shoots/2.jpg",
+            "shoots/3.jpg",
+        ]
+    );
+}
+
+#[test]
+fn match_content_types() {
+    let dir = temp_dir("mime-filters");
+    std::fs::write(dir.join("download"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+    std::fs::write(dir.join("report.txt"), b"%PDF-1.7\n").unwrap();
+    std::fs::write(dir.join("notes"), "just text").unwrap();
+    let mut bucket = Bucket {
+        name: "typed".into(),
+        mime_filters: vec!["image/*".into(), "application/pdf".into()],
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+
+    assert_eq!(
+        bucket.match_reason(&dir.join("download")).unwrap(),
+        Some(MatchReason::Mime("image/*".into(), "image/png".into()))
+    );
+    assert!(bucket.is_fitting(&dir.join("report.txt")).unwrap());
+    assert!(!bucket.is_fitting(&dir.join("notes")).unwrap());
+    assert!(!bucket.is_fitting(&dir).unwrap());
+
+    // With "all", the content type has to match as well as the extension.
+    bucket.match_mode = MatchMode::All;
+    bucket.extension_filters = vec!["txt".into()];
+    assert_eq!(
+        bucket.match_reason(&dir.join("report.txt")).unwrap(),
+        Some(MatchReason::Extension("txt".into()))
+    );
+    std::fs::write(dir.join("other.txt"), "just text").unwrap();
+    assert!(!bucket.is_fitting(&dir.join("other.txt")).unwrap());
+}
+
+#[test]
+fn match_content_predicates() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.dir.path();
+    std::fs::write(dir.join("invoice.pdf"), "INVOICE 42").unwrap();
+    std::fs::write(dir.join("letter.pdf"), "Dear reader").unwrap();
+    std::fs::write(dir.join("invoice.txt"), "INVOICE 43").unwrap();
+    let mut bucket = Bucket {
+        name: "invoices".into(),
+        extension_filters: vec!["pdf".into()],
+        content_predicate: Some("grep -q INVOICE".into()),
+        ..Default::default()
+    };
+    bucket.init().unwrap();
+
+    assert_eq!(
+        bucket.match_reason(&dir.join("invoice.pdf")).unwrap(),
+        Some(MatchReason::Extension("pdf".into()))
+    );
+    assert!(!bucket.is_fitting(&dir.join("letter.pdf")).unwrap());
+    assert!(!bucket.is_fitting(&dir.join("invoice.txt")).unwrap());
+
+    // Without other filters, the predicate alone decides.
+    bucket.extension_filters.clear();
+    bucket.content_predicate = Some("grep -q INVOICE {path}".into());
+    assert_eq!(
+        bucket.match_reason(&dir.join("invoice.txt")).unwrap(),
+        Some(MatchReason::Predicate("grep -q INVOICE {path}".into()))
+    );
+    assert!(!bucket.is_fitting(&dir).unwrap());
+
+    bucket.content_predicate = Some("sleep 5".into());
+    bucket.content_predicate_timeout_secs = 0;
+    let err = bucket.is_fitting(&dir.join("invoice.txt")).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<errors::JError>(),
+        Some(errors::JError::PredicateTimedOut(_, 0))
+    ));
+}
+
+#[test]
+fn fail_paths_whose_predicate_times_out() {
+    let sandbox = Sandbox::new();
+    sandbox.write("inbox/a.txt", "a");
+    let config = sandbox.config(vec![
+        Bucket {
+            name: "slow".into(),
+            destination: "slow".into(),
+            extension_filters: vec!["txt".into()],
+            content_predicate: Some("sleep 5".into()),
+            content_predicate_timeout_secs: 0,
+            priority: 1,
+            ..Default::default()
+        },
+        Bucket {
+            name: "texts".into(),
+            destination: "texts".into(),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        },
+    ]);
+
+    // The path stays where it is instead of falling through to the worse fitting bucket.
+    assert!(
+        config.watch[0]
+            .handle_paths([sandbox.path("inbox/a.txt")], true, &config)
+            .is_err()
+    );
+    assert_eq!(sandbox.files(), ["inbox/a.txt"]);
+}
+
+#[test]
+fn expand_extension_groups() {
+    let mut config: Config = toml::from_str(
+        "
+        [extension_groups]
+        raw = [\"cr2\", \"nef\"]
+        image = [\"png\"]
+
+        [[bucket]]
+        name = \"photos\"
+        destination = \"/photos\"
+        extension_filters = [\"raw\", \"image\", \"video\"]
+        priority = 0
+        action = \"move\"
+        override_action = \"skip\"
+
+        [[bucket]]
+        name = \"anything\"
+        destination = \"/anything\"
+        extension_filters = [\"*\"]
+        priority = 0
+        action = \"move\"
+        override_action = \"skip\"
+        ",
+    )
+    .unwrap();
+    config.init().unwrap();
+    config.init().unwrap();
+    let photos = &config.bucket[0];
+    assert!(photos.is_fitting(&"/downloads/IMG_1.nef").unwrap());
+    assert!(photos.is_fitting(&"/downloads/clip.mkv").unwrap());
+    // The custom group replaces the built-in one.
+    assert!(photos.is_fitting(&"/downloads/a.png").unwrap());
+    assert!(!photos.is_fitting(&"/downloads/a.jpg").unwrap());
+    assert_eq!(
+        photos.match_reason(&"/downloads/clip.mkv").unwrap(),
+        Some(MatchReason::Extension("mkv".into()))
+    );
+
+    let anything = &config.bucket[1];
+    assert!(anything.is_fitting(&"/downloads/file.whatever").unwrap());
+    assert!(!anything.is_fitting(&"/downloads/README").unwrap());
+}
+
+#[test]
+fn sweep_existing_paths_on_start() {
+    let dir = temp_dir("initial-sweep");
+    for watched in ["swept", "left"] {
+        std::fs::create_dir_all(dir.join(watched)).unwrap();
+        std::fs::write(dir.join(watched).join(format!("{watched}.txt")), "").unwrap();
+    }
+    let watch_path = |name: &str, initial_sweep| WatchPath {
+        path: dir.join(name),
+        bucket_names: vec!["texts".into()],
+        initial_sweep,
+        ..Default::default()
+    };
+    let mut config = Config {
+        watch: vec![
+            watch_path("swept", true),
+            watch_path("left", false),
+            watch_path("missing", true),
+        ],
+        bucket: vec![Bucket {
+            name: "texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+
+    config.sweep_on_start(false).unwrap();
+    assert!(dir.join("texts/swept.txt").is_file());
+    assert!(dir.join("left/left.txt").is_file());
+
+    config.sweep_on_start(true).unwrap();
+    assert!(dir.join("texts/left.txt").is_file());
+}
+
+#[test]
+fn watch_nested_directories_only() {
+    use notify::event::{CreateKind, Event, EventKind};
+
+    let watch_path = WatchPath {
+        path: "/projects".into(),
+        recursive_mode: RecMode::Directories,
+        ..Default::default()
+    };
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::ZERO);
+    let events = [
+        (CreateKind::Folder, "/projects/new-project"),
+        (CreateKind::Folder, "/projects/new-project/src"),
+        (CreateKind::File, "/projects/notes.txt"),
+        (CreateKind::File, "/projects/new-project/src/main.rs"),
+    ];
+    for (kind, path) in events {
+        let ev = Event::new(EventKind::Create(kind)).add_path(path.into());
+        watch_path.handle_event(ev, &mut debouncer, &Config::default());
+    }
+
+    let mut ready: Vec<PathBuf> = debouncer.ready().into_iter().map(|r| r.1).collect();
+    ready.sort();
+    assert_eq!(
+        ready,
+        [
+            PathBuf::from("/projects/new-project"),
+            PathBuf::from("/projects/new-project/src"),
+            PathBuf::from("/projects/notes.txt"),
+        ]
+    );
+}
+
+#[test]
+fn track_last_activity() {
+    let dir = temp_dir("activity");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/a.txt"), "").unwrap();
+    let mut config = Config {
+        watch: vec![WatchPath {
+            path: dir.join("inbox"),
+            bucket_names: vec!["activity-texts".into()],
+            ..Default::default()
+        }],
+        bucket: vec![Bucket {
+            name: "activity-texts".into(),
+            destination: dir.join("texts"),
+            extension_filters: vec!["txt".into()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    config.init().unwrap();
+    let before = std::time::SystemTime::now();
+
+    let watch_path = &config.watch[0];
+    let mut debouncer = debounce::Debouncer::new(std::time::Duration::ZERO);
+    let ev = notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File))
+        .add_path(dir.join("inbox/a.txt"));
+    watch_path.handle_event(ev, &mut debouncer, &config);
+    assert!(activity::last_events()[&watch_path.path] >= before);
+    assert!(!activity::last_actions().contains_key("activity-texts"));
+
+    for (watch_path, path, is_file) in debouncer.ready() {
+        watch_path.handle_paths([path], is_file, &config).unwrap();
+    }
+    assert!(activity::last_actions()["activity-texts"] >= before);
+}
+
+#[test]
+fn pick_actions_by_extension() {
+    let dir = temp_dir("extension-actions");
+    std::fs::create_dir_all(dir.join("inbox")).unwrap();
+    std::fs::write(dir.join("inbox/scan.pdf"), "").unwrap();
+    std::fs::write(dir.join("inbox/scan.log"), "").unwrap();
+    let mut config: Config = toml::from_str(&format!(
+        "
+        [[watch]]
+        path = \"{0}/inbox\"
+        bucket_names = [\"scans\"]
+        recursive_mode = \"non-recursive\"
+
+        [[bucket]]
+        name = \"scans\"
+        destination = \"{0}/scans\"
+        name_filters = [\"^scan\"]
+        priority = 0
+        action = \"move\"
+        extension_actions = {{ log = \"delete\" }}
+        override_action = \"skip\"
+        ",
+        dir.display()
+    ))
+    .unwrap();
+    config.init().unwrap();
+    config.one_shot().unwrap();
+    assert!(dir.join("scans/scan.pdf").is_file());
+    assert!(!dir.join("inbox/scan.log").exists());
+    assert!(!dir.join("scans/scan.log").exists());
+
+    // The action override of a watch path replaces the actions by extension as well.
+    std::fs::write(dir.join("inbox/scan.log"), "").unwrap();
+    config.watch[0].action_override = Some(Action::Copy);
+    config.one_shot().unwrap();
+    assert!(dir.join("inbox/scan.log").is_file());
+    assert!(dir.join("scans/scan.log").is_file());
+}
+
+#[test]
+fn move_by_renaming_on_the_same_file_system() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = temp_dir("atomic-move");
+    std::fs::write(dir.join("a.txt"), "contents").unwrap();
+    std::fs::create_dir_all(dir.join("dir/sub")).unwrap();
+    let file_inode = std::fs::metadata(dir.join("a.txt")).unwrap().ino();
+    let dir_inode = std::fs::metadata(dir.join("dir")).unwrap().ino();
+
+    // A renamed path keeps its inode, while a copy would get a new one.
+    bucket::move_path(&dir.join("a.txt"), &dir.join("b.txt"), true).unwrap();
+    bucket::move_path(&dir.join("dir"), &dir.join("moved"), false).unwrap();
+    assert_eq!(
+        std::fs::metadata(dir.join("b.txt")).unwrap().ino(),
+        file_inode
+    );
+    assert_eq!(
+        std::fs::metadata(dir.join("moved")).unwrap().ino(),
+        dir_inode
+    );
+    assert!(dir.join("moved/sub").is_dir());
+    assert!(!dir.join("a.txt").exists() && !dir.join("dir").exists());
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// When each bucket applied its most recent actions, oldest first, by bucket name.
+static RECENT: Mutex<BTreeMap<String, VecDeque<Instant>>> = Mutex::new(BTreeMap::new());
+
+/// Whether paths of throttled buckets are deferred instead of waited for, see `set_queueing`.
+static QUEUEING: AtomicBool = AtomicBool::new(false);
+
+/// Defer the paths of buckets which reached their limit with `schedule::defer`, instead of
+/// blocking the thread which handles them. Event loops turn this on, since they pick deferred
+/// paths up again, while one-shot runs wait for their turn.
+pub fn set_queueing(queueing: bool) {
+    QUEUEING.store(queueing, Ordering::Relaxed);
+}
+
+/// Whether throttled paths are deferred, see `set_queueing`.
+pub fn is_queueing() -> bool {
+    QUEUEING.load(Ordering::Relaxed)
+}
+
+/// How long `bucket` has to wait before it may apply another action, if it applied `max`
+/// actions within the last `window` already.
+pub fn wait_time(bucket: &str, max: u32, window: Duration) -> Option<Duration> {
+    let mut recent = RECENT.lock().expect("throttle lock poisoned");
+    let times = recent.entry(bucket.to_string()).or_default();
+    let now = Instant::now();
+    forget_expired(times, now, window);
+    // The oldest action drops out of the window first.
+    (times.len() >= max as usize).then(|| window - now.duration_since(times[0]))
+}
+
+/// Block until `bucket` has applied less than `max` actions within the last `window`, then count
+/// one more action for it.
+///
+/// Waiting callers are not dropped, they just wait their turn, so a burst of paths is handled
+/// over time instead of all at once.
+pub fn acquire(bucket: &str, max: u32, window: Duration) {
+    loop {
+        let wait = {
+            let mut recent = RECENT.lock().expect("throttle lock poisoned");
+            let times = recent.entry(bucket.to_string()).or_default();
+            let now = Instant::now();
+            forget_expired(times, now, window);
+            if times.len() < max as usize {
+                times.push_back(now);
+                return;
+            }
+            // The oldest action drops out of the window first.
+            window - now.duration_since(times[0])
+        };
+        log::debug!(
+            "bin '{bucket}' applied {max} actions within {}s, waiting {}ms",
+            window.as_secs(),
+            wait.as_millis()
+        );
+        sleep(wait);
+    }
+}
+
+/// Remove the actions of `times` which happened `window` or longer before `now`.
+fn forget_expired(times: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while times
+        .front()
+        .is_some_and(|t| now.duration_since(*t) >= window)
+    {
+        times.pop_front();
+    }
+}
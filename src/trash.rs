@@ -0,0 +1,65 @@
+use std::{
+    fs::{create_dir_all, write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use resolve_path::PathResolveExt;
+
+use crate::{bucket::move_path, errors::JResult};
+
+/// The trash directory of the user, as described by the freedesktop.org trash specification:
+/// `$XDG_DATA_HOME/Trash`, or `~/.local/share/Trash` if `XDG_DATA_HOME` is not set.
+pub fn trash_dir() -> PathBuf {
+    match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("Trash"),
+        None => Path::new("~/.local/share/Trash").resolve().into_owned(),
+    }
+}
+
+/// Move `path` into the trash directory of the user.
+pub fn trash(path: &Path, is_file: bool) -> JResult<PathBuf> {
+    trash_into(&trash_dir(), path, is_file)
+}
+
+/// Move `path` into the `files` directory of the trash directory `trash`, and record where it
+/// came from in the `info` directory, so that file managers can restore it.
+///
+/// Returns where the path ended up in the trash.
+pub fn trash_into(trash: &Path, path: &Path, is_file: bool) -> JResult<PathBuf> {
+    let files = trash.join("files");
+    let info = trash.join("info");
+    create_dir_all(&files)?;
+    create_dir_all(&info)?;
+
+    let original = std::path::absolute(path)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("'{}' has no file name", path.display()))?
+        .to_string_lossy()
+        .into_owned();
+    // Paths of the same name which were trashed before get a number, like `name.1`.
+    let mut trashed_name = name.clone();
+    let mut counter = 0;
+    while files.join(&trashed_name).symlink_metadata().is_ok()
+        || info
+            .join(format!("{trashed_name}.trashinfo"))
+            .symlink_metadata()
+            .is_ok()
+    {
+        counter += 1;
+        trashed_name = format!("{name}.{counter}");
+    }
+
+    write(
+        info.join(format!("{trashed_name}.trashinfo")),
+        format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            original.display(),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+        ),
+    )?;
+    let to = files.join(trashed_name);
+    move_path(path, &to, is_file)?;
+    Ok(to)
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Deserializer};
+
+use crate::errors::JResult;
+
+/// Parse a size like `512`, `10MB`, `1.5GiB` or `512k` into a number of bytes.
+///
+/// Units are case insensitive. `k`, `M`, `G`, `T` and `P`, optionally followed by a `B`, are
+/// powers of 1000, while `Ki`, `Mi`, `Gi`, `Ti` and `Pi`, optionally followed by a `B`, are powers
+/// of 1024. A number without a unit, or with just `B`, is a number of bytes. Whitespace between
+/// the number and the unit is allowed.
+pub fn parse_size(s: &str) -> JResult<u64> {
+    let invalid = || anyhow!("invalid size '{s}', expected e.g. \"512\", \"10MB\" or \"1.5GiB\"");
+
+    let trimmed = s.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    if number.is_empty() {
+        bail!(invalid());
+    }
+
+    let unit = unit.trim().to_lowercase();
+    let unit = unit.strip_suffix('b').unwrap_or(&unit);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "k" => 1000,
+        "m" => 1000u64.pow(2),
+        "g" => 1000u64.pow(3),
+        "t" => 1000u64.pow(4),
+        "p" => 1000u64.pow(5),
+        "ki" => 1 << 10,
+        "mi" => 1 << 20,
+        "gi" => 1 << 30,
+        "ti" => 1 << 40,
+        "pi" => 1 << 50,
+        _ => bail!(invalid()),
+    };
+
+    if let Ok(number) = number.parse::<u64>() {
+        return number.checked_mul(multiplier).ok_or_else(invalid);
+    }
+    let bytes = number.parse::<f64>().map_err(|_| invalid())? * multiplier as f64;
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        bail!(invalid());
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Parse a duration like `90`, `30s`, `15m`, `2h` or `1d` into a `Duration`.
+///
+/// A number without a unit is a number of seconds. Units are case insensitive and whitespace
+/// between the number and the unit is allowed.
+pub fn parse_duration(s: &str) -> JResult<Duration> {
+    let invalid =
+        || anyhow!("invalid duration '{s}', expected e.g. \"90\", \"30s\", \"15m\" or \"2h\"");
+
+    let trimmed = s.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!(invalid()),
+    };
+    let secs = number.checked_mul(multiplier).ok_or_else(invalid)?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Deserialize a size which is either a number of bytes or a string for `parse_size`.
+pub fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(bytes),
+        Size::Text(text) => parse_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Parse a `--verbosity`, either a number from `0`, errors only, to `4`, everything, or the name
+/// of the most verbose level which is logged, like `info`.
+pub fn parse_verbosity(s: &str) -> Result<usize, String> {
+    if let Ok(verbosity) = s.parse() {
+        return Ok(verbosity);
+    }
+    match s.to_lowercase().as_str() {
+        "error" => Ok(0),
+        "warn" | "warning" => Ok(1),
+        "info" => Ok(2),
+        "debug" => Ok(3),
+        "trace" => Ok(4),
+        _ => Err(format!(
+            "'{s}' is neither a number nor one of error, warn, info, debug and trace"
+        )),
+    }
+}
@@ -1,35 +1,250 @@
-use crate::{bucket::Bucket, config::Config, errors::JResult};
-use std::path::PathBuf;
+use crate::{
+    activity, batch,
+    bucket::{Action, Bucket, OverrideAction, rank_buckets},
+    burst,
+    config::{Config, Derived, default_true},
+    cooldown, deadline,
+    debounce::Debouncer,
+    errors::{JError, JResult},
+    feed::{self, Outcome},
+    label,
+    manifest::{self, Fingerprint},
+    preview, schedule, stats, throttle, trash,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{File, metadata, remove_dir, remove_file, symlink_metadata},
+    hash::{DefaultHasher, Hasher},
+    io::{BufRead, BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
-use notify::{Event, EventKind, RecursiveMode};
+use anyhow::bail;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{
+    Event, EventKind, RecursiveMode,
+    event::{CreateKind, ModifyKind, RenameMode},
+};
+use resolve_path::PathResolveExt;
 use serde::Deserialize;
 
+/// Name of the bucket which moves paths into the default destination of a watch path.
+pub const DEFAULT_BUCKET: &str = "default";
+
+/// Name of the ignore file which is looked for in the root of a watch path by default.
+pub const DEFAULT_IGNORE_FILE: &str = ".janitorignore";
+
 /// A `WatchPath` represents a path which is watched for new files.
 ///
 /// When a new file is created in the given path, a supplied list of buckets is queried for a
 /// fitting bucket. Once such a bucket is found, the bucket's action is applied on the file. If
-/// the file fits into multiple buckets(even after comparing bucket priorities), the bucket with
-/// the lowest lexicographical name is used. A recursive mode can also be provided, to either check
+/// the file fits into multiple buckets(even after comparing bucket priorities), the `tie_break`
+/// of the configuration decides. A recursive mode can also be provided, to either check
 /// only the given directory(non-recursive) or the entire sub tree(recursive).
+///
+/// Paths matching the patterns of an ignore file in the root of the watch path are never
+/// touched. The ignore file uses the `.gitignore` syntax, so entire subtrees can be excluded.
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct WatchPath {
     /// Path to watch.
+    #[serde(default)]
     pub path: PathBuf,
+    /// More paths which are watched with the same settings. Every one of them is turned into a
+    /// watch path of its own when the configuration is loaded.
+    #[serde(default)]
+    pub paths: Vec<PathBuf>,
     /// Path recursive mode.
     pub recursive_mode: RecMode,
+    /// Prefixed to the log lines written while handling the paths of this watch path, see
+    /// `label()`.
+    #[serde(default)]
+    pub label: Option<String>,
     /// Names of buckets to use.
+    #[serde(default)]
     pub bucket_names: Vec<String>,
+    /// Buckets which only this watch path uses, in addition to the ones of `bucket_names`.
+    ///
+    /// Their name may be left out, in which case they are named after the watch path and their
+    /// position, like `~/Downloads#1`.
+    #[serde(default)]
+    pub buckets: Vec<Bucket>,
+    /// Whether the path is watched. Disabled watch paths are neither watched nor swept in
+    /// one-shot mode.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Name of the ignore file in the root of the watch path.
+    #[serde(default = "default_ignore_file")]
+    pub ignore_file: String,
+    /// How deep into subdirectories a recursive watch path looks, where `0` means only the
+    /// entries of the watched directory itself.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Whether symbolic links are followed. If not, links are neither acted upon nor descended
+    /// into.
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    /// Where paths which don't fit into any bucket are moved to. If not set, such paths are
+    /// left alone.
+    #[serde(default)]
+    pub default_destination: Option<PathBuf>,
+    /// An action which is applied instead of the action of the bucket a path is put into.
+    #[serde(default)]
+    pub action_override: Option<Action>,
+    /// Whether paths are put into every bucket they fit into, in the order of their priority,
+    /// instead of just the best fitting one.
+    ///
+    /// Buckets which copy paths are applied first. A path may only fit into one bucket which
+    /// moves, renames or deletes it, which is always applied last.
+    #[serde(default)]
+    pub apply_all_matching: bool,
+    /// Whether files with the same contents as another file are left out when the watch path is
+    /// scanned as a whole, e.g. in one-shot mode, so that only the first of them in the order of
+    /// their paths is handled.
+    #[serde(default)]
+    pub dedup_within_run: bool,
+    /// Whether the files left out by `dedup_within_run` are deleted instead of left alone.
+    #[serde(default)]
+    pub delete_duplicates: bool,
+    /// What happens if a path of the same name already exists in the default destination.
+    #[serde(default)]
+    pub default_override_action: OverrideAction,
+    /// Scan the path in this interval, in milliseconds, instead of waiting for events of the
+    /// operating system. Defaults to the global `poll_interval_ms`.
+    #[serde(default)]
+    pub poll_interval_ms: Option<u64>,
+    /// Whether the paths which are inside of the path already are handled once when watching
+    /// starts, instead of waiting for events.
+    #[serde(default)]
+    pub initial_sweep: bool,
+    /// Priorities which replace the `priority` of buckets, by bucket name, for the paths of this
+    /// watch path only.
+    #[serde(default)]
+    pub bucket_priority_overrides: BTreeMap<String, i32>,
+    /// Whether directories inside of the path which are empty after their paths were handled
+    /// are removed. The path itself is never removed.
+    #[serde(default)]
+    pub prune_empty_dirs: bool,
+    /// What happens to empty files instead of putting them into a bucket. Defaults to the
+    /// global `empty_file_action`, and if neither is set, empty files are put into buckets like
+    /// any other file.
+    #[serde(default)]
+    pub empty_file_action: Option<EmptyFileAction>,
+    /// Whether hidden paths, whose names start with a dot, and the paths inside of hidden
+    /// directories are handled.
+    #[serde(default)]
+    pub include_hidden: bool,
+    /// A template of a path which is deleted whenever a path inside of the watch path is
+    /// removed, to keep a mirror like a thumbnail cache in sync. Without it, removals are
+    /// ignored. See `mirror_path` for its placeholders.
+    #[serde(default)]
+    pub mirror_removals: Option<String>,
+    /// Names of directories, or glob patterns like `build-*` for them, which are left alone
+    /// along with everything inside of them, wherever they are inside of the watch path.
+    #[serde(default)]
+    pub exclude_dirs: Vec<String>,
+    #[serde(skip)]
+    pub _ignore: Derived<Option<Gitignore>>,
+    /// The matcher of `exclude_dirs`.
+    #[serde(skip)]
+    pub _exclude: Derived<Option<Gitignore>>,
+    /// The bucket which moves paths into the default destination.
+    #[serde(skip)]
+    pub _default_bucket: Derived<Option<Bucket>>,
+}
+
+impl Default for WatchPath {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            paths: Vec::new(),
+            recursive_mode: RecMode::default(),
+            label: None,
+            bucket_names: Vec::new(),
+            buckets: Vec::new(),
+            enabled: true,
+            ignore_file: default_ignore_file(),
+            max_depth: None,
+            follow_symlinks: false,
+            default_destination: None,
+            action_override: None,
+            apply_all_matching: false,
+            dedup_within_run: false,
+            delete_duplicates: false,
+            default_override_action: OverrideAction::default(),
+            poll_interval_ms: None,
+            initial_sweep: false,
+            bucket_priority_overrides: BTreeMap::new(),
+            prune_empty_dirs: false,
+            empty_file_action: None,
+            include_hidden: false,
+            mirror_removals: None,
+            exclude_dirs: Vec::new(),
+            _ignore: Derived::default(),
+            _exclude: Derived::default(),
+            _default_bucket: Derived::default(),
+        }
+    }
+}
+
+fn default_ignore_file() -> String {
+    DEFAULT_IGNORE_FILE.to_string()
 }
 
 /// If the `Recursive` mode is used, the entire sub tree is watched for new files. If the
-/// `NonRecursive` mode is used, only the immediate directory is checked for new files.
-#[derive(Debug, Clone, Deserialize, Copy, Default, PartialEq, Eq)]
+/// `NonRecursive` mode is used, only the immediate directory is checked for new files. The
+/// `Directories` mode watches the entire sub tree for new directories, but only the immediate
+/// directory for new files. The `Depth` mode watches as many levels of directories as it holds,
+/// where `1` is the immediate directory only, `2` includes its immediate subdirectories and so
+/// on; it's written as `"depth:2"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum RecMode {
-    #[serde(rename(deserialize = "recursive"))]
     Recursive,
-    #[serde(rename(deserialize = "non-recursive"))]
     #[default]
     NonRecursive,
+    Directories,
+    Depth(usize),
+}
+
+impl FromStr for RecMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> JResult<Self> {
+        Ok(match s {
+            "recursive" => Self::Recursive,
+            "non-recursive" => Self::NonRecursive,
+            "directories" => Self::Directories,
+            _ => match s.strip_prefix("depth:").map(|depth| depth.trim().parse()) {
+                Some(Ok(depth)) if depth > 0 => Self::Depth(depth),
+                _ => bail!(
+                    "invalid recursive mode '{s}', expected \"recursive\", \"non-recursive\", \"directories\" or a depth of at least 1 like \"depth:2\""
+                ),
+            },
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for RecMode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// What happens to files which are empty, instead of putting them into a bucket.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyFileAction {
+    /// Leave empty files where they are.
+    Skip,
+    /// Delete empty files.
+    Delete,
+    /// Move empty files into the trash directory of the user.
+    Trash,
 }
 
 impl From<RecMode> for RecursiveMode {
@@ -37,54 +252,908 @@ impl From<RecMode> for RecursiveMode {
         match other {
             RecMode::Recursive => RecursiveMode::Recursive,
             RecMode::NonRecursive => RecursiveMode::NonRecursive,
+            RecMode::Directories => RecursiveMode::Recursive,
+            RecMode::Depth(1) => RecursiveMode::NonRecursive,
+            // Events of deeper levels are left out by `exceeds_max_depth`.
+            RecMode::Depth(_) => RecursiveMode::Recursive,
         }
     }
 }
 
 impl WatchPath {
+    /// Split a watch path with multiple `paths` into one watch path per path.
+    pub fn split(self) -> JResult<Vec<WatchPath>> {
+        if self.paths.is_empty() {
+            if self.path.as_os_str().is_empty() {
+                bail!(JError::MissingValue("path".into()));
+            }
+            return Ok(vec![self]);
+        }
+
+        let paths = (!self.path.as_os_str().is_empty())
+            .then(|| self.path.clone())
+            .into_iter()
+            .chain(self.paths.iter().cloned());
+        Ok(paths
+            .map(|path| WatchPath {
+                path,
+                paths: Vec::new(),
+                ..self.clone()
+            })
+            .collect())
+    }
+
+    /// (Re)load the ignore file from the root of the watch path, if there is one, compile the
+    /// `exclude_dirs` and set up the bucket for the default destination.
+    pub fn init(&mut self) -> JResult {
+        self._default_bucket.0 = self.default_destination.as_ref().map(|destination| Bucket {
+            name: DEFAULT_BUCKET.to_string(),
+            destination: destination.clone(),
+            override_action: self.default_override_action.clone(),
+            ..Default::default()
+        });
+
+        let root = self.path.resolve();
+        let ignore_path = root.join(&self.ignore_file);
+        self._ignore.0 = None;
+        if ignore_path.is_file() {
+            let mut builder = GitignoreBuilder::new(&root);
+            if let Some(e) = builder.add(&ignore_path) {
+                return Err(e.into());
+            }
+            self._ignore.0 = Some(builder.build()?);
+        }
+
+        if let Some(template) = &self.mirror_removals
+            && !template.starts_with('~')
+            && Path::new(template).is_relative()
+        {
+            bail!(
+                "mirror_removals of watch path '{}' has to be an absolute path: {template}",
+                self.path.display()
+            );
+        }
+
+        self._exclude.0 = None;
+        if !self.exclude_dirs.is_empty() {
+            let mut builder = GitignoreBuilder::new(&root);
+            for pattern in self.exclude_dirs.iter() {
+                if pattern.contains('/') {
+                    bail!(
+                        "exclude_dirs of watch path '{}' takes names of directories, not paths: {pattern}",
+                        self.path.display()
+                    );
+                }
+                // The trailing slash only matches directories, in any depth.
+                builder.add_line(None, &format!("{pattern}/"))?;
+            }
+            self._exclude.0 = Some(builder.build()?);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether a path is one of the `exclude_dirs` or inside of one.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        path.starts_with(self.path.resolve())
+            && self._exclude.0.as_ref().is_some_and(|exclude| {
+                exclude
+                    .matched_path_or_any_parents(path, is_dir)
+                    .is_ignore()
+            })
+    }
+
+    /// Check whether a path is excluded by the ignore file of the watch path, or because it's
+    /// hidden and hidden paths are not included.
+    ///
+    /// The ignore file itself is always excluded.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let root = self.path.resolve();
+        if !path.starts_with(&root) {
+            return false;
+        }
+        if path == root.join(&self.ignore_file)
+            || self.is_hidden(path)
+            || self.is_excluded(path, is_dir)
+        {
+            return true;
+        }
+
+        self._ignore
+            .0
+            .as_ref()
+            .is_some_and(|ignore| ignore.matched_path_or_any_parents(path, is_dir).is_ignore())
+    }
+
+    /// Whether `path` is hidden inside the watch path and hidden paths are not included. The
+    /// watch path itself may well be inside of a hidden directory.
+    pub fn is_hidden(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(self.path.resolve()) else {
+            return false;
+        };
+        !self.include_hidden
+            && relative
+                .components()
+                .any(|c| c.as_os_str().as_encoded_bytes().starts_with(b"."))
+    }
+
+    /// The depth of a path inside the watch path, where entries of the watched directory itself
+    /// have a depth of `0`.
+    pub fn depth(&self, path: &Path) -> Option<usize> {
+        let relative = path.strip_prefix(self.path.resolve()).ok()?;
+        Some(relative.components().count().saturating_sub(1))
+    }
+
+    /// The deepest depth of paths which are handled, if limited by `max_depth` or a `Depth`
+    /// recursive mode.
+    pub fn depth_limit(&self) -> Option<usize> {
+        let mode = match self.recursive_mode {
+            RecMode::Depth(levels) => Some(levels - 1),
+            _ => None,
+        };
+        match (self.max_depth, mode) {
+            (Some(max), Some(mode)) => Some(max.min(mode)),
+            (max, mode) => max.or(mode),
+        }
+    }
+
+    /// Check whether a path lies deeper than `max_depth` or the recursive mode allow.
+    pub fn exceeds_max_depth(&self, path: &Path) -> bool {
+        match (self.depth_limit(), self.depth(path)) {
+            (Some(max), Some(depth)) => depth > max,
+            _ => false,
+        }
+    }
+
+    /// Check whether a path is a file below the top level of a watch path in the `Directories`
+    /// mode, whose events are left out.
+    pub fn is_nested_file(&self, path: &Path, is_file: bool) -> bool {
+        is_file
+            && self.recursive_mode == RecMode::Directories
+            && self.depth(path).is_some_and(|depth| depth > 0)
+    }
+
+    /// Check whether a path is a symbolic link which should be left alone.
+    pub fn is_skipped_symlink(&self, path: &Path) -> bool {
+        !self.follow_symlinks
+            && symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+    }
+
+    /// The label of the watch path in log lines, which is the path itself unless `label` is set.
+    pub fn label(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    /// Check whether a path is the default destination of the watch path.
+    pub fn is_default_destination(&self, path: &Path) -> bool {
+        self.default_destination
+            .as_ref()
+            .is_some_and(|destination| destination.resolve() == path.resolve())
+    }
+
     /// Handle a provided file system event.
-    pub fn handle_event(&self, ev: Event, config: &Config) -> JResult {
+    ///
+    /// Newly created paths and paths which were moved into the watched directory are handed
+    /// over to the debouncer, which releases them once they stop changing. Paths which were
+    /// moved away are forgotten and any other event for a waiting path restarts its window.
+    pub fn handle_event(&self, mut ev: Event, debouncer: &mut Debouncer, config: &Config) {
+        let _label = label::enter(self.label());
+        activity::record_event(&self.path);
         if ev.attrs.flag().is_some() {
-            // The `Rescan` flag has been found: ignore the event and re-scan.
-            return Ok(());
+            // The `Rescan` flag has been found: the caller re-scans the whole watch path.
+            return;
         }
-        let is_file = match ev.kind {
-            EventKind::Create(create_kind) => match create_kind {
-                notify::event::CreateKind::File => true,
-                notify::event::CreateKind::Folder => false,
-                _ => return Ok(()),
-            },
-            _ => return Ok(()),
+        ev.paths
+            .retain(|path| !self.is_excluded(path, path.is_dir()));
+        match ev.kind {
+            EventKind::Create(create_kind) => {
+                let is_file = match create_kind {
+                    CreateKind::File => true,
+                    CreateKind::Folder => false,
+                    _ => return,
+                };
+                log::trace!("Create event: {ev:?}");
+                for path in ev.paths {
+                    if self.exceeds_max_depth(&path) || self.is_nested_file(&path, is_file) {
+                        continue;
+                    }
+                    debouncer.push(self, path, is_file);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(rename_mode)) => {
+                log::trace!("Rename event: {ev:?}");
+                let mut paths = ev.paths.into_iter();
+                match rename_mode {
+                    RenameMode::From => {
+                        paths.for_each(|path| debouncer.remove(&path));
+                        return;
+                    }
+                    // The first path is the old name, the second one is the new name.
+                    RenameMode::Both => {
+                        if let Some(from) = paths.next() {
+                            debouncer.remove(&from);
+                        }
+                    }
+                    _ => {}
+                }
+                // The event does not say what was renamed, so look at the resulting path.
+                for path in paths.filter(|path| !self.exceeds_max_depth(path)) {
+                    match metadata(&path) {
+                        Ok(meta) if self.is_nested_file(&path, meta.is_file()) => {}
+                        Ok(meta) => debouncer.push(self, path, meta.is_file()),
+                        Err(_) => debouncer.remove(&path),
+                    }
+                }
+            }
+            EventKind::Remove(_) => {
+                log::trace!("Remove event: {ev:?}");
+                for path in ev.paths.iter() {
+                    debouncer.remove(path);
+                    if !self.exceeds_max_depth(path) && !self.is_ignored(path, false) {
+                        self.remove_mirror(path, config);
+                    }
+                }
+            }
+            _ => {
+                for path in ev.paths.iter() {
+                    debouncer.touch(path);
+                }
+            }
+        }
+    }
+
+    /// The path which `mirror_removals` derives from the removed `path`, if it's set.
+    ///
+    /// `{path}` stands for the path relative to the watch path, `{dir}` for the directory it was
+    /// in relative to the watch path, `{stem}` for its name without the extension and `{ext}` for
+    /// the extension, so `"~/.thumbs/{dir}/{stem}.jpg"` mirrors `photos/2024/beach.png` as
+    /// `~/.thumbs/2024/beach.jpg` for the watch path `photos`.
+    pub fn mirror_path(&self, path: &Path) -> Option<PathBuf> {
+        let template = self.mirror_removals.as_ref()?;
+        let relative = path
+            .strip_prefix(self.path.resolve())
+            .ok()
+            .filter(|relative| !relative.as_os_str().is_empty())?;
+        let dir = relative.parent().unwrap_or(Path::new(""));
+        let mirror = template
+            .replace("{path}", &relative.to_string_lossy())
+            .replace("{dir}", &dir.to_string_lossy())
+            .replace(
+                "{stem}",
+                &path.file_stem().unwrap_or_default().to_string_lossy(),
+            )
+            .replace(
+                "{ext}",
+                &path.extension().unwrap_or_default().to_string_lossy(),
+            );
+        Some(PathBuf::from(mirror.trim_end_matches('.')).resolve().into())
+    }
+
+    /// Delete the path which mirrors the removed `path`, if there is one.
+    ///
+    /// The mirror is left alone if it's the part of `mirror_removals` in front of its first
+    /// placeholder, or a parent of it, or if `config` protects it.
+    fn remove_mirror(&self, path: &Path, config: &Config) {
+        let Some(mirror) = self.mirror_path(path) else {
+            return;
+        };
+        let prefix = self
+            .mirror_removals
+            .as_deref()
+            .and_then(|template| template.split('{').next())
+            .unwrap_or_default();
+        let res = if Path::new(prefix).resolve().starts_with(&mirror) {
+            Err(JError::ProtectedPath(mirror.clone()).into())
+        } else {
+            config.ensure_deletable(&mirror)
+        };
+        if let Err(e) = res {
+            log::error!("'{}' was removed: {e}", path.display());
+            return;
+        }
+        let Ok(meta) = symlink_metadata(&mirror) else {
+            log::trace!(
+                "'{}' was removed, its mirror '{}' doesn't exist",
+                path.display(),
+                mirror.display()
+            );
+            return;
+        };
+        if preview::is_active() {
+            log::info!(
+                "would delete '{}', because '{}' was removed",
+                mirror.display(),
+                path.display()
+            );
+            return;
+        }
+        let res = if meta.is_dir() {
+            std::fs::remove_dir_all(&mirror)
+        } else {
+            remove_file(&mirror)
         };
-        log::trace!("Create event: {ev:?}");
-        self.handle_paths(ev.paths.into_iter(), is_file, config)?;
+        match res {
+            Ok(()) => log::info!(
+                "deleted '{}', because '{}' was removed",
+                mirror.display(),
+                path.display()
+            ),
+            Err(e) => log::error!("deleting the mirror '{}': {e}", mirror.display()),
+        }
+    }
 
-        Ok(())
+    /// Leave out the files of `paths` with the same contents as another one, keeping only the
+    /// first of each group in the order of their paths.
+    ///
+    /// Files are compared by their size first, then by a hash of their contents and finally
+    /// byte by byte. Files which can't be read and ignored files are always kept. The duplicates
+    /// are deleted if `delete_duplicates` is set.
+    pub fn dedup(&self, mut paths: Vec<PathBuf>, config: &Config) -> Vec<PathBuf> {
+        paths.sort();
+        let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+        for path in paths.iter().filter(|path| !config.is_ignored(path)) {
+            if let Ok(meta) = metadata(path) {
+                by_size.entry(meta.len()).or_default().push(path);
+            }
+        }
+
+        let mut duplicates = HashSet::new();
+        for group in by_size.into_values().filter(|group| group.len() > 1) {
+            let mut by_hash: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+            for path in group {
+                let Ok(hash) = hash_contents(path) else {
+                    continue;
+                };
+                let kept = by_hash.entry(hash).or_default();
+                match kept
+                    .iter()
+                    .find(|original| same_contents(original, path).unwrap_or(false))
+                {
+                    Some(original) => {
+                        duplicates.insert(path.clone());
+                        self.drop_duplicate(path, original);
+                    }
+                    None => kept.push(path),
+                }
+            }
+        }
+
+        paths.retain(|path| !duplicates.contains(path));
+        paths
+    }
+
+    fn drop_duplicate(&self, path: &Path, original: &Path) {
+        if self.delete_duplicates && preview::is_active() {
+            log::info!(
+                "would delete '{}', it has the same contents as '{}', which is not part of the plan",
+                path.display(),
+                original.display()
+            );
+            return;
+        }
+        if !self.delete_duplicates {
+            log::info!(
+                "skipping '{}', it has the same contents as '{}'",
+                path.display(),
+                original.display()
+            );
+            return;
+        }
+        match remove_file(path) {
+            Ok(()) => log::info!(
+                "deleted '{}', it has the same contents as '{}'",
+                path.display(),
+                original.display()
+            ),
+            Err(e) => log::error!("deleting duplicate '{}': {e}", path.display()),
+        }
     }
 
+    /// The buckets of `config` named in `bucket_names`, followed by the inline `buckets`.
+    pub fn buckets<'a>(&'a self, config: &'a Config) -> impl Iterator<Item = &'a Bucket> {
+        config
+            .bucket
+            .iter()
+            .filter(|bucket| self.bucket_names.contains(&bucket.name))
+            .chain(self.buckets.iter())
+    }
+
+    /// The buckets of `buckets`, with their priority replaced by the
+    /// `bucket_priority_overrides`.
+    pub fn prioritized_buckets<'a>(
+        &'a self,
+        config: &'a Config,
+    ) -> impl Iterator<Item = Cow<'a, Bucket>> {
+        self.buckets(config).map(
+            |bucket| match self.bucket_priority_overrides.get(&bucket.name) {
+                Some(priority) if *priority != bucket.priority => Cow::Owned(Bucket {
+                    priority: *priority,
+                    ..bucket.clone()
+                }),
+                _ => Cow::Borrowed(bucket),
+            },
+        )
+    }
+
+    /// Place each of the given paths into the best fitting bucket.
+    ///
+    /// If the configuration allows for more than one worker, the paths are handled in parallel.
     pub fn handle_paths<I>(&self, paths: I, is_file: bool, config: &Config) -> JResult
     where
         I: IntoIterator<Item = PathBuf>,
     {
-        let possible_buckets: Vec<&Bucket> = config
-            .bucket
-            .iter()
-            .filter(|bucket| self.bucket_names.contains(&bucket.name))
+        let prioritized: Vec<Cow<Bucket>> = self
+            .prioritized_buckets(config)
+            .filter(|bucket| bucket.enabled)
+            .collect();
+        let possible_buckets: Vec<&Bucket> = prioritized.iter().map(AsRef::as_ref).collect();
+
+        let plan = preview::current();
+        // Keep working on the remaining paths if one of them fails, which is logged right away.
+        let handle = |path: PathBuf| {
+            if !deadline::claim() {
+                log::trace!(
+                    "leaving '{}', the maximum runtime has passed",
+                    path.display()
+                );
+                return (path, Ok(()));
+            }
+            // Workers run on threads of their own, so every path enters the label and the plan.
+            let _label = label::enter(self.label());
+            let _plan = preview::enter(plan.clone());
+            let res = self.handle_path(path.clone(), is_file, config, &possible_buckets);
+            if let Err(e) = &res {
+                log::error!("could not handle '{}': {e}", path.display());
+                stats::record_failure();
+            }
+            (path, res)
+        };
+        let results = config.in_parallel(paths, handle);
+        if self.prune_empty_dirs {
+            self.prune_empty_parents(results.iter().map(|(path, _)| path.as_path()));
+        }
+        JError::combine(results)
+    }
+
+    /// Remove the directories which contained any of the `handled` paths and are empty now,
+    /// along with their parents which are empty now too, up to the watch path itself.
+    ///
+    /// Returns how many directories were removed.
+    pub fn prune_empty_parents<'a>(&self, handled: impl Iterator<Item = &'a Path>) -> usize {
+        let root = self.path.resolve();
+        // The deepest directories first, so their parents may be empty by the time they come up.
+        let mut dirs: Vec<&Path> = handled
+            .filter(|path| symlink_metadata(path).is_err())
+            .filter_map(Path::parent)
             .collect();
+        dirs.sort_by_key(|dir| (std::cmp::Reverse(dir.components().count()), *dir));
+        dirs.dedup();
+
+        let mut pruned = 0;
+        for dir in dirs {
+            for dir in dir.ancestors() {
+                if dir == root || !dir.starts_with(&root) || remove_dir(dir).is_err() {
+                    break;
+                }
+                log::debug!("removed empty directory '{}'", dir.display());
+                pruned += 1;
+            }
+        }
+        if pruned > 0 {
+            log::info!(
+                "removed {pruned} empty directories in '{}'",
+                self.path.display()
+            );
+        }
+        pruned
+    }
+
+    /// The bucket with its action replaced by the `action_override` of the watch path, if any,
+    /// or else by its action for the extension of `path`. Buckets with `keep_components` get to
+    /// know the watch path, too.
+    fn override_action<'a>(&self, bucket: &'a Bucket, path: &Path) -> Cow<'a, Bucket> {
+        let bucket = self.override_bucket_action(bucket, path);
+        if bucket.keep_components == 0 || bucket._watch_root.is_some() {
+            return bucket;
+        }
+        Cow::Owned(Bucket {
+            _watch_root: Some(self.path.resolve().into_owned()),
+            ..bucket.into_owned()
+        })
+    }
+
+    fn override_bucket_action<'a>(&self, bucket: &'a Bucket, path: &Path) -> Cow<'a, Bucket> {
+        match &self.action_override {
+            Some(action) if *action != bucket.action || !bucket.extension_actions.is_empty() => {
+                log::info!(
+                    "watch path '{}' overrides the action of bin '{}' with '{}'",
+                    self.path.display(),
+                    bucket.name,
+                    format!("{action:?}").to_lowercase()
+                );
+                Cow::Owned(Bucket {
+                    action: action.clone(),
+                    extension_actions: BTreeMap::new(),
+                    ..bucket.clone()
+                })
+            }
+            Some(_) => Cow::Borrowed(bucket),
+            None => bucket.for_path(path),
+        }
+    }
+
+    /// Handle `path` again after `delay`. The path wasn't handled yet, so its `once_per` claim is
+    /// given up, otherwise it would be dropped as a duplicate when it comes back.
+    fn defer(&self, path: PathBuf, is_file: bool, delay: Duration) {
+        cooldown::release(&path);
+        schedule::defer(self, path, is_file, delay);
+    }
+
+    /// Place a single path into the best fitting bucket out of `possible_buckets`.
+    fn handle_path(
+        &self,
+        path: PathBuf,
+        is_file: bool,
+        config: &Config,
+        possible_buckets: &[&Bucket],
+    ) -> JResult {
+        if config.is_ignored(&path) || self.is_ignored(&path, !is_file) {
+            log::trace!("ignoring '{}'", path.display());
+            return Ok(());
+        }
+        if self.is_skipped_symlink(&path) {
+            log::trace!("skipping symbolic link '{}'", path.display());
+            return Ok(());
+        }
+        if self.is_default_destination(&path) {
+            return Ok(());
+        }
+        if config.is_inside_destination(self, &path) {
+            log::trace!("'{}' was put into a destination already", path.display());
+            return Ok(());
+        }
+        if let Some(ttl) = config.once_per()
+            && !cooldown::claim(&path, ttl)
+        {
+            log::trace!("'{}' was handled just now, ignoring it", path.display());
+            return Ok(());
+        }
+        // Events for links are classified by the link itself, so look at the link target.
+        let is_file = if self.follow_symlinks && path.exists() {
+            path.is_file()
+        } else {
+            is_file
+        };
+
+        if config.stable_wait_ms > 0 {
+            let window = Duration::from_millis(config.stable_wait_ms);
+            let max_wait = Duration::from_millis(config.stable_max_wait_ms);
+            match wait_until_stable(&path, window, max_wait)? {
+                Stability::Stable => {}
+                Stability::Gone => {
+                    log::trace!(
+                        "'{}' disappeared while waiting for it to become stable",
+                        path.display()
+                    );
+                    return Ok(());
+                }
+                // Waiting any longer would hold up every other path, so try again later.
+                Stability::Changing => {
+                    log::debug!(
+                        "'{}' is still changing after {}ms, trying again later",
+                        path.display(),
+                        config.stable_max_wait_ms
+                    );
+                    self.defer(path, is_file, window);
+                    return Ok(());
+                }
+            }
+        }
+        if is_file
+            && let Some(action) = self.empty_file_action
+            && metadata(&path).is_ok_and(|m| m.len() == 0)
+        {
+            return handle_empty_file(&path, action);
+        }
+        let fingerprint = config.manifest_fingerprint(&path, is_file);
+        if let (Some(manifest), Some(fingerprint)) = (&config.manifest, &fingerprint)
+            && let Some(seen) = manifest::seen(manifest, fingerprint)?
+        {
+            log::info!(
+                "skipping '{}', a file with the same name and contents was handled at {seen}",
+                path.display()
+            );
+            stats::record_skip();
+            return Ok(());
+        }
 
-        for path in paths.into_iter() {
-            let mut fitting_buckets: Vec<&&Bucket> = possible_buckets
+        let now = schedule::now();
+        let mut fitting_buckets = rank_buckets(
+            possible_buckets.iter().copied(),
+            &path,
+            is_file,
+            config.tie_break,
+        )?;
+        if !self.bucket_priority_overrides.is_empty() && log::log_enabled!(log::Level::Trace) {
+            let unchanged = rank_buckets(
+                self.buckets(config).filter(|bucket| bucket.enabled),
+                &path,
+                is_file,
+                config.tie_break,
+            )?;
+            let name = |ranked: &[&Bucket]| ranked.first().map(|bucket| bucket.name.clone());
+            if name(&unchanged) != name(&fitting_buckets) {
+                log::trace!(
+                    "the bucket_priority_overrides of '{}' put '{}' into bin '{}' instead of '{}'",
+                    self.path.display(),
+                    path.display(),
+                    name(&fitting_buckets).unwrap_or_default(),
+                    name(&unchanged).unwrap_or_default()
+                );
+            }
+        }
+        if let Some(best) = fitting_buckets.first()
+            && best.defer_outside_active_hours
+            && let Some(hours) = best._active_hours.filter(|h| !h.contains(now.time()))
+        {
+            let delay = hours.until_start(now);
+            log::info!(
+                "bin '{}' is dormant, deferring '{}' for {}s",
+                best.name,
+                path.display(),
+                delay.as_secs()
+            );
+            self.defer(path, is_file, delay);
+            return Ok(());
+        }
+        fitting_buckets.retain(|bucket| {
+            let active = bucket.is_active_at(now.time());
+            if !active {
+                log::info!("bin '{}' is dormant, skipping it", bucket.name);
+            }
+            active
+        });
+        if !self.apply_all_matching {
+            fitting_buckets.truncate(1);
+        }
+        // Every bucket which is about to be applied has to be ready, otherwise waiting for one of
+        // them would hold up every other path, so the path queues up instead.
+        if throttle::is_queueing()
+            && let Some((bucket, max, wait)) = fitting_buckets
                 .iter()
-                .filter(|bucket| bucket.is_fitting(&path).is_ok_and(|inner| inner))
-                .collect();
-            fitting_buckets.sort();
-            fitting_buckets.reverse();
+                .filter_map(|bucket| {
+                    let max = bucket.max_actions_per_minute?;
+                    let wait = throttle::wait_time(&bucket.name, max, Duration::from_secs(60))?;
+                    Some((bucket, max, wait))
+                })
+                .max_by_key(|(_, _, wait)| *wait)
+        {
+            log::debug!(
+                "bin '{}' applied {max} actions within the last minute, deferring '{}' for {}ms",
+                bucket.name,
+                path.display(),
+                wait.as_millis()
+            );
+            self.defer(path, is_file, wait);
+            return Ok(());
+        }
+        if let Some(best) = fitting_buckets.first()
+            && best.burst_threshold > 1
+        {
+            let window = Duration::from_secs(best.burst_window_secs);
+            return match burst::add(
+                &best.name,
+                best.burst_threshold,
+                window,
+                self,
+                path,
+                is_file,
+            ) {
+                Some(burst) => apply_batch(best, burst, config),
+                None => Ok(()),
+            };
+        }
+        if let Some(best) = fitting_buckets.first()
+            && best.min_batch > 1
+        {
+            return match batch::add(&best.name, best.min_batch, self, path, is_file) {
+                Some(batch) => apply_batch(best, batch, config),
+                None => Ok(()),
+            };
+        }
+
+        let mut buckets: Vec<Cow<Bucket>> = fitting_buckets
+            .into_iter()
+            .map(|bucket| self.override_action(bucket, &path))
+            .collect();
+        // Copies have to be made before the path is moved away.
+        buckets.sort_by_key(|bucket| bucket.consumes());
+        let consuming: Vec<String> = buckets
+            .iter()
+            .filter(|bucket| bucket.consumes())
+            .map(|bucket| bucket.name.clone())
+            .collect();
+        if consuming.len() > 1 {
+            bail!(JError::ConflictingBuckets(path, consuming));
+        }
+        if buckets.is_empty()
+            && let Some(bucket) = self._default_bucket.0.as_ref()
+        {
+            buckets.push(Cow::Borrowed(bucket));
+        }
+
+        self.apply_buckets(&path, is_file, &buckets, fingerprint, config)
+    }
 
-            if let Some(bucket) = fitting_buckets.first() {
-                log::trace!("picked bucket {} for file {}", bucket.name, &path.display());
-                bucket.apply_action(&path, is_file)?;
+    /// Apply the actions of `buckets` to `path`, one after another, and record the `fingerprint`
+    /// of `path` in the manifest once all of them succeeded.
+    fn apply_buckets(
+        &self,
+        path: &Path,
+        is_file: bool,
+        buckets: &[Cow<Bucket>],
+        fingerprint: Option<Fingerprint>,
+        config: &Config,
+    ) -> JResult {
+        for bucket in buckets.iter() {
+            log::trace!("picked bucket {} for file {}", bucket.name, &path.display());
+            if matches!(bucket.action, Action::Delete) {
+                config.ensure_deletable(path)?;
             }
+            config
+                .with_retries(
+                    &format!("putting '{}' into bin '{}'", path.display(), bucket.name),
+                    || bucket.apply_action(&path, is_file),
+                )
+                .map_err(|e| {
+                    feed::publish(
+                        &bucket.name,
+                        &bucket.action,
+                        path.to_owned(),
+                        PathBuf::new(),
+                        Outcome::Failed,
+                        Some(format!("{e:#}")),
+                    );
+                    JError::ActionFailed(bucket.name.clone(), path.into(), format!("{e:#}"))
+                })?;
+        }
+        if let (Some(manifest), Some(fingerprint)) = (&config.manifest, fingerprint)
+            && !buckets.is_empty()
+            // The path is still there and handled for real once the dry run is over.
+            && !buckets.iter().any(|bucket| bucket.dry_run)
+            && let Err(e) = manifest::record(manifest, fingerprint)
+        {
+            log::warn!("writing manifest '{}': {e}", manifest.display());
         }
         Ok(())
     }
 }
+
+/// Put all paths of a batch into `bucket`, each with the action override of the watch path it
+/// was found in.
+pub(crate) fn apply_batch(bucket: &Bucket, batch: Vec<batch::Entry>, config: &Config) -> JResult {
+    log::info!("putting {} paths into bin '{}'", batch.len(), bucket.name);
+    JError::combine(batch.into_iter().map(|(watch_path, path, is_file)| {
+        // Paths may have been removed while they were waiting for the others.
+        if symlink_metadata(&path).is_err() {
+            return (path, Ok(()));
+        }
+        let _label = label::enter(watch_path.label());
+        let buckets = [watch_path.override_action(bucket, &path)];
+        let fingerprint = config.manifest_fingerprint(&path, is_file);
+        let res = watch_path.apply_buckets(&path, is_file, &buckets, fingerprint, config);
+        (path, res)
+    }))
+}
+
+/// A hash of the contents of a file.
+fn hash_contents(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..read]);
+    }
+}
+
+/// Compare the contents of two files of the same size byte by byte.
+fn same_contents(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (mut a, mut b) = (
+        BufReader::new(File::open(a)?),
+        BufReader::new(File::open(b)?),
+    );
+    loop {
+        let chunk = a.fill_buf()?;
+        let read = chunk.len();
+        if read == 0 {
+            return Ok(b.fill_buf()?.is_empty());
+        }
+        let mut other = vec![0; read];
+        if b.read_exact(&mut other).is_err() || chunk != other.as_slice() {
+            return Ok(false);
+        }
+        a.consume(read);
+    }
+}
+
+/// Apply the `empty_file_action` of a watch path to `path`, which is an empty file.
+fn handle_empty_file(path: &Path, action: EmptyFileAction) -> JResult {
+    if preview::is_active() && action != EmptyFileAction::Skip {
+        log::info!(
+            "would {} empty file '{}', which is not part of the plan",
+            format!("{action:?}").to_lowercase(),
+            path.display()
+        );
+        return Ok(());
+    }
+    match action {
+        EmptyFileAction::Skip => {
+            log::info!("skipping empty file '{}'", path.display());
+            stats::record_skip();
+        }
+        EmptyFileAction::Delete => {
+            remove_file(path)?;
+            log::info!("deleted empty file '{}'", path.display());
+        }
+        EmptyFileAction::Trash => {
+            let to = trash::trash(path, true)?;
+            log::info!(
+                "moved empty file '{}' to the trash at '{}'",
+                path.display(),
+                to.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// What `wait_until_stable` found out about a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stability {
+    /// The size and modification time didn't change for the whole window.
+    Stable,
+    /// The path disappeared while waiting, e.g. because it was renamed away.
+    Gone,
+    /// The path was still changing when the maximum wait passed.
+    Changing,
+}
+
+/// Block until the size and modification time of `path` have not changed for `window`, but no
+/// longer than `max_wait`, or `window` if that's longer.
+pub fn wait_until_stable(path: &Path, window: Duration, max_wait: Duration) -> JResult<Stability> {
+    let snapshot = || match metadata(path).and_then(|m| Ok((m.len(), m.modified()?))) {
+        Ok(snapshot) => Ok(Some(snapshot)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    };
+    let poll_interval = (window / 4).clamp(Duration::from_millis(10), Duration::from_millis(250));
+    let max_wait = max_wait.max(window);
+
+    let Some(mut last) = snapshot()? else {
+        return Ok(Stability::Gone);
+    };
+    let started = Instant::now();
+    let mut stable_since = started;
+    while stable_since.elapsed() < window {
+        if started.elapsed() >= max_wait {
+            return Ok(Stability::Changing);
+        }
+        sleep(poll_interval);
+        let Some(current) = snapshot()? else {
+            return Ok(Stability::Gone);
+        };
+        if current != last {
+            last = current;
+            stable_since = Instant::now();
+        }
+    }
+
+    Ok(Stability::Stable)
+}